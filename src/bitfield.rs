@@ -55,3 +55,4 @@ impl_bit!(u64, a, b);
 impl_bit!(u64, a, b, c);
 impl_bit!(u64, a, b, c, d);
 impl_bit!(u64, a, b, c, d, e);
+impl_bit!(u64, a, b, c, d, e, f);
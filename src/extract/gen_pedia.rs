@@ -6,7 +6,7 @@ use crate::gpu::*;
 use crate::gui::*;
 use crate::mesh::*;
 use crate::msg::*;
-use crate::pak::PakReader;
+use crate::pak::{PakFileIndex, PakReader};
 use crate::pfb::Pfb;
 use crate::rcol::Rcol;
 use crate::rsz::*;
@@ -260,7 +260,7 @@ pub fn gen_collider_mapping(rcol: Rcol) -> Result<ColliderMapping> {
 }
 
 pub fn pfb_user<T: FromRsz + 'static>(
-    pak: &mut PakReader<impl Read + Seek>,
+    pak: &PakReader<impl Read + Seek>,
     pfb: &Pfb,
     version_hint: Option<u32>,
 ) -> Result<T> {
@@ -279,7 +279,7 @@ pub fn pfb_user<T: FromRsz + 'static>(
 
 #[allow(clippy::too_many_arguments)]
 pub fn gen_monsters(
-    pak: &mut PakReader<impl Read + Seek>,
+    pak: &PakReader<impl Read + Seek + Send>,
     pfb_path_gen: fn(u32, u32) -> String,
     boss_init_path_gen: fn(u32, u32) -> Option<String>,
     collider_path_gen: fn(u32, u32) -> String,
@@ -292,76 +292,84 @@ pub fn gen_monsters(
 ) -> Result<Vec<Monster>> {
     lscope!(logger, "monsters(large:{is_large})");
 
-    let mut monsters = vec![];
+    let candidates: Vec<(u32, u32, PakFileIndex)> = (0..1000)
+        .flat_map(|id| (0..10).map(move |sub_id| (id, sub_id)))
+        .filter_map(|(id, sub_id)| {
+            let index = pak.find_file(&pfb_path_gen(id, sub_id)).ok()?;
+            Some((id, sub_id, index))
+        })
+        .collect();
 
-    for id in 0..1000 {
-        for sub_id in 0..10 {
-            let main_pfb_path = pfb_path_gen(id, sub_id);
-            let main_pfb_index = if let Ok(index) = pak.find_file(&main_pfb_path) {
-                index
-            } else {
-                continue;
-            };
-            let main_pfb = Pfb::new(Cursor::new(pak.read_file(main_pfb_index)?))?;
-
-            let data_base = pfb_user(pak, &main_pfb, version_hint).context("data_base")?;
-            let data_tune = {
-                // not using pfb_user here because some pfb also somehow reference the variantion file
-                let path = data_tune_path_gen(id, sub_id);
-                let index = pak.find_file(&path)?;
-                User::new(Cursor::new(pak.read_file(index)?))?
-                    .rsz
-                    .deserialize_single(version_hint)
-                    .context("data_tune")?
-            };
-            let meat_data = pfb_user(pak, &main_pfb, version_hint).context("meat_data")?;
-            let condition_damage_data =
-                pfb_user(pak, &main_pfb, version_hint).context("condition_damage_data")?;
-            let anger_data = pfb_user(pak, &main_pfb, version_hint).context("anger_data")?;
-            let stamina_data = pfb_user(pak, &main_pfb, version_hint).context("stamina_data")?;
-            let parts_break_data =
-                pfb_user(pak, &main_pfb, version_hint).context("parts_break_data")?;
-
-            let boss_init_set_data = if let Some(path) = boss_init_path_gen(id, sub_id) {
-                if let Ok(index) = pak.find_file(&path) {
-                    let data = User::new(Cursor::new(pak.read_file(index)?))?;
-                    Some(
-                        data.rsz
-                            .deserialize_single(version_hint)
-                            .context("boss_init_set_data")?,
-                    )
+    // Monsters are independent of each other, so they are loaded in parallel.
+    // Logs are buffered per monster and written in order afterwards
+    let monsters = candidates
+        .into_par_iter()
+        .map(
+            |(id, sub_id, main_pfb_index)| -> Result<(Monster, String)> {
+                let mut log = String::new();
+                let main_pfb_path = pfb_path_gen(id, sub_id);
+                let main_pfb = Pfb::new(Cursor::new(pak.read_file(main_pfb_index)?))?;
+
+                let data_base = pfb_user(pak, &main_pfb, version_hint).context("data_base")?;
+                let data_tune = {
+                    // not using pfb_user here because some pfb also somehow reference the variantion file
+                    let path = data_tune_path_gen(id, sub_id);
+                    let index = pak.find_file(&path)?;
+                    User::new(Cursor::new(pak.read_file(index)?))?
+                        .rsz
+                        .deserialize_single(version_hint)
+                        .context("data_tune")?
+                };
+                let meat_data = pfb_user(pak, &main_pfb, version_hint).context("meat_data")?;
+                let condition_damage_data =
+                    pfb_user(pak, &main_pfb, version_hint).context("condition_damage_data")?;
+                let anger_data = pfb_user(pak, &main_pfb, version_hint).context("anger_data")?;
+                let stamina_data =
+                    pfb_user(pak, &main_pfb, version_hint).context("stamina_data")?;
+                let parts_break_data =
+                    pfb_user(pak, &main_pfb, version_hint).context("parts_break_data")?;
+
+                let boss_init_set_data = if let Some(path) = boss_init_path_gen(id, sub_id) {
+                    if let Ok(index) = pak.find_file(&path) {
+                        let data = User::new(Cursor::new(pak.read_file(index)?))?;
+                        Some(
+                            data.rsz
+                                .deserialize_single(version_hint)
+                                .context("boss_init_set_data")?,
+                        )
+                    } else {
+                        None
+                    }
                 } else {
                     None
-                }
-            } else {
-                None
-            };
+                };
 
-            let enemy_type = boss_init_set_data
-                .as_ref()
-                .map(|b: &EnemyBossInitSetData| b.enemy_type)
-                .or_else(|| EMS_ID_MAP.get(&(id + (sub_id << 8) + 0x1000)).cloned());
+                let enemy_type = boss_init_set_data
+                    .as_ref()
+                    .map(|b: &EnemyBossInitSetData| b.enemy_type)
+                    .or_else(|| EMS_ID_MAP.get(&(id + (sub_id << 8) + 0x1000)).cloned());
 
-            let rcol_path = collider_path_gen(id, sub_id);
-            let rcol_index = pak.find_file(&rcol_path)?;
-            let rcol =
-                Rcol::new(Cursor::new(pak.read_file(rcol_index)?), true).context(rcol_path)?;
-            let collider_mapping = gen_collider_mapping(rcol)?;
+                let rcol_path = collider_path_gen(id, sub_id);
+                let rcol_index = pak.find_file(&rcol_path)?;
+                let rcol =
+                    Rcol::new(Cursor::new(pak.read_file(rcol_index)?), true).context(rcol_path)?;
+                let collider_mapping = gen_collider_mapping(rcol)?;
 
-            let drop_item = pfb_user(pak, &main_pfb, version_hint).context("drop_item")?;
-            let parts_break_reward = is_large
-                .then(|| pfb_user(pak, &main_pfb, version_hint).context("parts_break_reward"))
-                .transpose()?;
+                let drop_item = pfb_user(pak, &main_pfb, version_hint).context("drop_item")?;
+                let parts_break_reward = is_large
+                    .then(|| pfb_user(pak, &main_pfb, version_hint).context("parts_break_reward"))
+                    .transpose()?;
 
-            let em_type = if is_large { EmTypes::Em } else { EmTypes::Ems }(id | (sub_id << 8));
+                let em_type = if is_large { EmTypes::Em } else { EmTypes::Ems }(id | (sub_id << 8));
 
-            let mut atk_colliders = vec![];
+                let mut atk_colliders = vec![];
 
-            let mut add_atk_colliders = |rcol: Rcol| {
-                let mut dedup = HashSet::new();
-                for group in &rcol.group_attachments {
-                    let (data, is_shell) =
-                        if let Some(data) = group.user_data.downcast_ref::<EmHitAttackRsData>() {
+                let mut add_atk_colliders = |rcol: Rcol| {
+                    let mut dedup = HashSet::new();
+                    for group in &rcol.group_attachments {
+                        let (data, is_shell) = if let Some(data) =
+                            group.user_data.downcast_ref::<EmHitAttackRsData>()
+                        {
                             (&data.base.0, false)
                         } else if let Some(data) =
                             group.user_data.downcast_ref::<EmShellHitAttackRsData>()
@@ -370,113 +378,119 @@ pub fn gen_monsters(
                         } else {
                             continue;
                         };
-                    if !dedup.insert(data) {
-                        continue;
+                        if !dedup.insert(data) {
+                            continue;
+                        }
+                        atk_colliders.push(AttackCollider {
+                            is_shell,
+                            data: data.clone(),
+                        })
                     }
-                    atk_colliders.push(AttackCollider {
-                        is_shell,
-                        data: data.clone(),
-                    })
-                }
-            };
-
-            let atk_collider_path = atk_collider_path_gen(id, sub_id);
-            if let Ok(index) = pak.find_file(&atk_collider_path) {
-                let rcol = Rcol::new(Cursor::new(pak.read_file(index)?), true)
-                    .context(atk_collider_path)?;
-                add_atk_colliders(rcol);
-            } else {
-                writeln!(logger, "Attack collider file not found {atk_collider_path}")?;
-            }
+                };
 
-            for shell_collider_path in shell_collider_path_gen(id, sub_id) {
-                if let Ok(index) = pak.find_file(&shell_collider_path) {
+                let atk_collider_path = atk_collider_path_gen(id, sub_id);
+                if let Ok(index) = pak.find_file(&atk_collider_path) {
                     let rcol = Rcol::new(Cursor::new(pak.read_file(index)?), true)
-                        .context(shell_collider_path)?;
+                        .context(atk_collider_path)?;
                     add_atk_colliders(rcol);
                 } else {
-                    writeln!(
-                        logger,
-                        "Shell collider file not found {shell_collider_path}"
-                    )?;
+                    writeln!(log, "Attack collider file not found {atk_collider_path}")?;
                 }
-            }
 
-            let pop_parameter = pfb_user(pak, &main_pfb, version_hint).context("pop_parameter")?;
-
-            let unique_mystery = if let Some((loader, path)) =
-                atmost_one(main_pfb.children.iter().filter_map(|child| {
-                    UNIQUE_MYSTERY_TYPE_MAP
-                        .get(&child.hash)
-                        .map(|loader| (loader, &child.name))
-                }))? {
-                let index = pak.find_file(path)?;
-                let data = User::new(Cursor::new(pak.read_file(index)?))?;
-                let data = loader(
-                    data.rsz
-                        .deserialize_single_any(version_hint)
-                        .context(path.clone())?,
-                )?;
-                if data.base.condition_damage_data.len() > 1 {
-                    bail!("Multiple condition damage data for mystery {main_pfb_path}")
+                for shell_collider_path in shell_collider_path_gen(id, sub_id) {
+                    if let Ok(index) = pak.find_file(&shell_collider_path) {
+                        let rcol = Rcol::new(Cursor::new(pak.read_file(index)?), true)
+                            .context(shell_collider_path)?;
+                        add_atk_colliders(rcol);
+                    } else {
+                        writeln!(log, "Shell collider file not found {shell_collider_path}")?;
+                    }
                 }
-                Some(data)
-            } else {
-                writeln!(logger, "Unique mystery file not found for {main_pfb_path}")?;
-                None
-            };
 
-            let unique_over_mystery = if let Some(child) = atmost_one(
-                main_pfb
-                    .children
-                    .iter()
-                    .filter(|child| child.hash == EnemyUniqueOverMysteryData::type_hash()),
-            )? {
-                let path = &child.name;
-                let index = pak.find_file(path)?;
-                let data = User::new(Cursor::new(pak.read_file(index)?))?;
-                Some(
-                    data.rsz
-                        .deserialize_single(version_hint)
-                        .context(path.clone())?,
-                )
-            } else {
-                None
-            };
+                let pop_parameter =
+                    pfb_user(pak, &main_pfb, version_hint).context("pop_parameter")?;
 
-            let block_move = is_large
-                .then(|| pfb_user(pak, &main_pfb, version_hint).context("block_move"))
-                .transpose()?;
+                let unique_mystery = if let Some((loader, path)) =
+                    atmost_one(main_pfb.children.iter().filter_map(|child| {
+                        UNIQUE_MYSTERY_TYPE_MAP
+                            .get(&child.hash)
+                            .map(|loader| (loader, &child.name))
+                    }))? {
+                    let index = pak.find_file(path)?;
+                    let data = User::new(Cursor::new(pak.read_file(index)?))?;
+                    let data = loader(
+                        data.rsz
+                            .deserialize_single_any(version_hint)
+                            .context(path.clone())?,
+                    )?;
+                    if data.base.condition_damage_data.len() > 1 {
+                        bail!("Multiple condition damage data for mystery {main_pfb_path}")
+                    }
+                    Some(data)
+                } else {
+                    writeln!(log, "Unique mystery file not found for {main_pfb_path}")?;
+                    None
+                };
+
+                let unique_over_mystery = if let Some(child) = atmost_one(
+                    main_pfb
+                        .children
+                        .iter()
+                        .filter(|child| child.hash == EnemyUniqueOverMysteryData::type_hash()),
+                )? {
+                    let path = &child.name;
+                    let index = pak.find_file(path)?;
+                    let data = User::new(Cursor::new(pak.read_file(index)?))?;
+                    Some(
+                        data.rsz
+                            .deserialize_single(version_hint)
+                            .context(path.clone())?,
+                    )
+                } else {
+                    None
+                };
 
-            let ecological = pfb_user(pak, &main_pfb, version_hint).context("ecological")?;
+                let block_move = is_large
+                    .then(|| pfb_user(pak, &main_pfb, version_hint).context("block_move"))
+                    .transpose()?;
+
+                let ecological = pfb_user(pak, &main_pfb, version_hint).context("ecological")?;
+
+                let monster = Monster {
+                    id,
+                    sub_id,
+                    enemy_type,
+                    em_type,
+                    data_base,
+                    data_tune,
+                    meat_data,
+                    condition_damage_data,
+                    anger_data,
+                    stamina_data,
+                    parts_break_data,
+                    boss_init_set_data,
+                    collider_mapping,
+                    drop_item,
+                    parts_break_reward,
+                    atk_colliders,
+                    pop_parameter,
+                    unique_mystery,
+                    unique_over_mystery,
+                    block_move,
+                    ecological,
+                };
+                Ok((monster, log))
+            },
+        )
+        .collect::<Result<Vec<_>>>()?;
 
-            monsters.push(Monster {
-                id,
-                sub_id,
-                enemy_type,
-                em_type,
-                data_base,
-                data_tune,
-                meat_data,
-                condition_damage_data,
-                anger_data,
-                stamina_data,
-                parts_break_data,
-                boss_init_set_data,
-                collider_mapping,
-                drop_item,
-                parts_break_reward,
-                atk_colliders,
-                pop_parameter,
-                unique_mystery,
-                unique_over_mystery,
-                block_move,
-                ecological,
-            })
-        }
+    let mut result = vec![];
+    for (monster, log) in monsters {
+        write!(logger, "{log}")?;
+        result.push(monster);
     }
 
-    Ok(monsters)
+    Ok(result)
 }
 
 fn get_msg(pak: &mut PakReader<impl Read + Seek>, path: &str) -> Result<Msg> {
@@ -496,10 +510,12 @@ fn get_user<T: FromUser>(
         .with_context(|| path.to_string())
 }
 
+// Optional files are decoded leniently. A file that fails to decode is logged and skipped
 fn get_user_opt<T: FromUser>(
     pak: &mut PakReader<impl Read + Seek>,
     path: &str,
     version_hint: Option<u32>,
+    logger: &mut Logger,
 ) -> Result<Option<T>> {
     let index = if let Ok(index) = pak.find_file(path) {
         index
@@ -507,10 +523,21 @@ fn get_user_opt<T: FromUser>(
         return Ok(None);
     };
 
-    let user = User::new(Cursor::new(pak.read_file(index)?))?
+    let (mut roots, diagnostics) = User::new(Cursor::new(pak.read_file(index)?))?
         .rsz
-        .deserialize_single(version_hint)
+        .deserialize_lenient(version_hint)
         .with_context(|| path.to_string())?;
+    if !diagnostics.is_empty() {
+        lscope!(logger, "{}", path);
+        for diagnostic in diagnostics {
+            writeln!(logger, "{diagnostic}")?;
+        }
+        return Ok(None);
+    }
+    if roots.len() != 1 {
+        bail!("Not a single-valued RSZ: {path}");
+    }
+    let user = FromUser::from_any(roots.pop().unwrap()).with_context(|| path.to_string())?;
     Ok(Some(user))
 }
 
@@ -524,8 +551,9 @@ fn get_singleton<T: 'static + SingletonUser>(
 fn get_singleton_opt<T: 'static + SingletonUser>(
     pak: &mut PakReader<impl Read + Seek>,
     version_hint: Option<u32>,
+    logger: &mut Logger,
 ) -> Result<Option<T>> {
-    if let Some(user) = get_user_opt(pak, T::PATH, version_hint)? {
+    if let Some(user) = get_user_opt(pak, T::PATH, version_hint, logger)? {
         Ok(Some(T::from_rsz(user)))
     } else {
         Ok(None)
@@ -536,6 +564,7 @@ fn get_weapon_list<BaseData: FromUser>(
     pak: &mut PakReader<impl Read + Seek>,
     weapon_class: &str,
     version_hint: Option<u32>,
+    logger: &mut Logger,
 ) -> Result<WeaponList<BaseData>> {
     Ok(WeaponList {
         base_data: get_user(
@@ -569,6 +598,7 @@ fn get_weapon_list<BaseData: FromUser>(
                 "data/Define/Player/Weapon/{weapon_class}/{weapon_class}OverwearBaseData.user"
             ),
             version_hint,
+            logger,
         )?,
         overwear_product: get_user_opt(
             pak,
@@ -576,6 +606,7 @@ fn get_weapon_list<BaseData: FromUser>(
                 "data/Define/Player/Weapon/{weapon_class}/{weapon_class}OverwearProductData.user"
             ),
             version_hint,
+            logger,
         )?,
         name: get_msg(
             pak,
@@ -614,7 +645,7 @@ fn get_version_hint<T: 'static + SingletonUser, U: FromRsz>(
 }
 
 pub fn gen_pedia(
-    pak: &mut PakReader<impl Read + Seek>,
+    pak: &mut PakReader<impl Read + Seek + Send>,
     sha: bool,
     logger: &mut Logger,
 ) -> Result<Pedia> {
@@ -809,20 +840,20 @@ pub fn gen_pedia(
         "data/System/ContentsIdSystem/Common/ItemCategoryType_Name_MR.msg",
     )?;
 
-    let great_sword = get_weapon_list(pak, "GreatSword", version_hint)?;
-    let short_sword = get_weapon_list(pak, "ShortSword", version_hint)?;
-    let hammer = get_weapon_list(pak, "Hammer", version_hint)?;
-    let lance = get_weapon_list(pak, "Lance", version_hint)?;
-    let long_sword = get_weapon_list(pak, "LongSword", version_hint)?;
-    let slash_axe = get_weapon_list(pak, "SlashAxe", version_hint)?;
-    let gun_lance = get_weapon_list(pak, "GunLance", version_hint)?;
-    let dual_blades = get_weapon_list(pak, "DualBlades", version_hint)?;
-    let horn = get_weapon_list(pak, "Horn", version_hint)?;
-    let insect_glaive = get_weapon_list(pak, "InsectGlaive", version_hint)?;
-    let charge_axe = get_weapon_list(pak, "ChargeAxe", version_hint)?;
-    let light_bowgun = get_weapon_list(pak, "LightBowgun", version_hint)?;
-    let heavy_bowgun = get_weapon_list(pak, "HeavyBowgun", version_hint)?;
-    let bow = get_weapon_list(pak, "Bow", version_hint)?;
+    let great_sword = get_weapon_list(pak, "GreatSword", version_hint, logger)?;
+    let short_sword = get_weapon_list(pak, "ShortSword", version_hint, logger)?;
+    let hammer = get_weapon_list(pak, "Hammer", version_hint, logger)?;
+    let lance = get_weapon_list(pak, "Lance", version_hint, logger)?;
+    let long_sword = get_weapon_list(pak, "LongSword", version_hint, logger)?;
+    let slash_axe = get_weapon_list(pak, "SlashAxe", version_hint, logger)?;
+    let gun_lance = get_weapon_list(pak, "GunLance", version_hint, logger)?;
+    let dual_blades = get_weapon_list(pak, "DualBlades", version_hint, logger)?;
+    let horn = get_weapon_list(pak, "Horn", version_hint, logger)?;
+    let insect_glaive = get_weapon_list(pak, "InsectGlaive", version_hint, logger)?;
+    let charge_axe = get_weapon_list(pak, "ChargeAxe", version_hint, logger)?;
+    let light_bowgun = get_weapon_list(pak, "LightBowgun", version_hint, logger)?;
+    let heavy_bowgun = get_weapon_list(pak, "HeavyBowgun", version_hint, logger)?;
+    let bow = get_weapon_list(pak, "Bow", version_hint, logger)?;
 
     let horn_melody = get_msg(pak, "data/Define/Player/Weapon/Horn/Horn_UniqueParam.msg")?;
     let horn_melody_mr = get_msg(
@@ -953,7 +984,7 @@ pub fn gen_pedia(
     let servant_profile = get_msg(pak, "Message/Servant/ServantProfile_MR.msg")?;
 
     let mut random_mystery_difficulty: Option<RandomMysteryDifficultyRateListData> =
-        get_singleton_opt(pak, version_hint)?;
+        get_singleton_opt(pak, version_hint, logger)?;
 
     if let Some(rmd) = &mut random_mystery_difficulty {
         for nand_data in &mut rmd.nand_data {
@@ -1044,10 +1075,10 @@ pub fn gen_pedia(
         normal_quest_data_for_enemy_mr: get_singleton(pak, version_hint)?,
         dl_quest_data: get_singleton(pak, version_hint)?,
         dl_quest_data_for_enemy: get_singleton(pak, version_hint)?,
-        dl_quest_data_mr: get_singleton_opt(pak, version_hint)?,
-        dl_quest_data_for_enemy_mr: get_singleton_opt(pak, version_hint)?,
+        dl_quest_data_mr: get_singleton_opt(pak, version_hint, logger)?,
+        dl_quest_data_for_enemy_mr: get_singleton_opt(pak, version_hint, logger)?,
         difficulty_rate: get_singleton(pak, version_hint)?,
-        difficulty_rate_anomaly: get_singleton_opt(pak, version_hint)?,
+        difficulty_rate_anomaly: get_singleton_opt(pak, version_hint, logger)?,
         random_scale: get_singleton(pak, version_hint)?,
         size_list: get_singleton(pak, version_hint)?,
         discover_em_set_data: get_singleton(pak, version_hint)?,
@@ -1157,7 +1188,7 @@ pub fn gen_pedia(
         horn_melody,
         horn_melody_mr,
         hyakuryu_weapon_buildup: get_singleton(pak, version_hint)?,
-        weapon_chaos_critical: get_singleton_opt(pak, version_hint)?,
+        weapon_chaos_critical: get_singleton_opt(pak, version_hint, logger)?,
         weapon_series,
         weapon_series_mr,
         maps,
@@ -1205,22 +1236,22 @@ pub fn gen_pedia(
         airou_series_name_mr,
         dog_series_name_mr,
         servant_profile,
-        custom_buildup_base: get_singleton_opt(pak, version_hint)?,
-        custom_buildup_armor_open: get_singleton_opt(pak, version_hint)?,
-        custom_buildup_weapon_open: get_singleton_opt(pak, version_hint)?,
-        custom_buildup_armor_material: get_singleton_opt(pak, version_hint)?,
-        custom_buildup_weapon_material: get_singleton_opt(pak, version_hint)?,
-        custom_buildup_armor_lot: get_singleton_opt(pak, version_hint)?,
-        custom_buildup_armor_category_lot: get_singleton_opt(pak, version_hint)?,
-        custom_buildup_equip_skill_detail: get_singleton_opt(pak, version_hint)?,
-        custom_buildup_wep_table: get_singleton_opt(pak, version_hint)?,
-        custom_buildup_slot_bonus: get_singleton_opt(pak, version_hint)?,
+        custom_buildup_base: get_singleton_opt(pak, version_hint, logger)?,
+        custom_buildup_armor_open: get_singleton_opt(pak, version_hint, logger)?,
+        custom_buildup_weapon_open: get_singleton_opt(pak, version_hint, logger)?,
+        custom_buildup_armor_material: get_singleton_opt(pak, version_hint, logger)?,
+        custom_buildup_weapon_material: get_singleton_opt(pak, version_hint, logger)?,
+        custom_buildup_armor_lot: get_singleton_opt(pak, version_hint, logger)?,
+        custom_buildup_armor_category_lot: get_singleton_opt(pak, version_hint, logger)?,
+        custom_buildup_equip_skill_detail: get_singleton_opt(pak, version_hint, logger)?,
+        custom_buildup_wep_table: get_singleton_opt(pak, version_hint, logger)?,
+        custom_buildup_slot_bonus: get_singleton_opt(pak, version_hint, logger)?,
         random_mystery_difficulty,
-        random_mystery_enemy: get_singleton_opt(pak, version_hint)?,
-        random_mystery_rank_release: get_singleton_opt(pak, version_hint)?,
-        random_mystery_reward_base: get_singleton_opt(pak, version_hint)?,
-        random_mystery_reward_subtarget: get_singleton_opt(pak, version_hint)?,
-        random_mystery_research_point: get_singleton_opt(pak, version_hint)?,
+        random_mystery_enemy: get_singleton_opt(pak, version_hint, logger)?,
+        random_mystery_rank_release: get_singleton_opt(pak, version_hint, logger)?,
+        random_mystery_reward_base: get_singleton_opt(pak, version_hint, logger)?,
+        random_mystery_reward_subtarget: get_singleton_opt(pak, version_hint, logger)?,
+        random_mystery_research_point: get_singleton_opt(pak, version_hint, logger)?,
         progress: get_singleton(pak, version_hint)?,
         enemy_rank: get_singleton(pak, version_hint)?,
         species: get_singleton(pak, version_hint)?,
@@ -1234,7 +1265,7 @@ pub fn gen_pedia(
         item_shop: get_singleton(pak, version_hint)?,
         item_shop_lot: get_singleton(pak, version_hint)?,
         fukudama: get_singleton(pak, version_hint)?,
-        mystery_labo_trade_item: get_singleton_opt(pak, version_hint)?,
+        mystery_labo_trade_item: get_singleton_opt(pak, version_hint, logger)?,
         item_mix: get_singleton(pak, version_hint)?,
         bbq: get_singleton(pak, version_hint)?,
         exchange_item: get_singleton(pak, version_hint)?,
@@ -2935,6 +2966,7 @@ fn prepare_items<'a>(pedia: &'a Pedia, logger: &mut Logger) -> Result<BTreeMap<I
 static AMMOR_SPHERE_CATEGORY_MSG: Lazy<MsgEntry> = Lazy::new(|| MsgEntry {
     name: "".to_string(),
     guid: Guid { bytes: [0; 16] },
+    unknown: 0,
     hash: 0,
     attributes: vec![],
     content: vec!["Armor sphere".to_string(); 32],
@@ -13,7 +13,7 @@ use nalgebra_glm::*;
 use serde::*;
 use std::collections::BTreeMap;
 use std::io::{Cursor, Read, Seek};
-use std::rc::*;
+use std::sync::Arc;
 
 struct MapFiles {
     tex_files: &'static [&'static str],
@@ -217,7 +217,7 @@ pub struct GameMap {
     pub ec_data: Option<rsz::EnvironmentCreatureData>,
 }
 
-fn get_map<F: Read + Seek>(
+fn get_map<F: Read + Seek + Send>(
     pak: &mut PakReader<F>,
     files: &MapFiles,
     map_no: i32,
@@ -233,10 +233,10 @@ fn get_map<F: Read + Seek>(
 
     let scale: rsz::GuiMapScaleDefineData = if scale.symbol() == rsz::GuiMapScaleDefineData::SYMBOL
     {
-        Rc::try_unwrap(scale.downcast().unwrap()).map_err(|_| anyhow!("Shared node"))?
+        Arc::try_unwrap(scale.downcast().unwrap()).map_err(|_| anyhow!("Shared node"))?
     } else if scale.symbol() == rsz::GuiMap07DefineData::SYMBOL {
         let scale: rsz::GuiMap07DefineData =
-            Rc::try_unwrap(scale.downcast().unwrap()).map_err(|_| anyhow!("Shared node"))?;
+            Arc::try_unwrap(scale.downcast().unwrap()).map_err(|_| anyhow!("Shared node"))?;
         scale.base.0
     } else {
         bail!("Unknown map scale type {}", scale.symbol())
@@ -418,7 +418,9 @@ fn get_map<F: Read + Seek>(
     }))
 }
 
-pub fn prepare_maps(pak: &mut PakReader<impl Read + Seek>) -> Result<BTreeMap<i32, GameMap>> {
+pub fn prepare_maps(
+    pak: &mut PakReader<impl Read + Seek + Send>,
+) -> Result<BTreeMap<i32, GameMap>> {
     MAP_FILES
         .iter()
         .enumerate()
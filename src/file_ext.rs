@@ -2,7 +2,7 @@ use crate::align::*;
 use anyhow::{bail, Result};
 use nalgebra_glm::*;
 use std::convert::TryInto;
-use std::io::{Read, Seek};
+use std::io::{Read, Seek, Write};
 pub trait ReadExt {
     fn read_bool(&mut self) -> Result<bool>;
     fn read_u8(&mut self) -> Result<u8>;
@@ -31,6 +31,30 @@ pub trait SeekExt {
     fn tell(&mut self) -> Result<u64>;
 }
 
+pub trait WriteExt {
+    fn write_bool(&mut self, v: bool) -> Result<()>;
+    fn write_u8(&mut self, v: u8) -> Result<()>;
+    fn write_u16(&mut self, v: u16) -> Result<()>;
+    fn write_u32(&mut self, v: u32) -> Result<()>;
+    fn write_u64(&mut self, v: u64) -> Result<()>;
+    fn write_i8(&mut self, v: i8) -> Result<()>;
+    fn write_i16(&mut self, v: i16) -> Result<()>;
+    fn write_i32(&mut self, v: i32) -> Result<()>;
+    fn write_i64(&mut self, v: i64) -> Result<()>;
+    fn write_magic(&mut self, v: &[u8; 4]) -> Result<()>;
+    fn write_u16str(&mut self, v: &str) -> Result<()>;
+    fn write_f32(&mut self, v: f32) -> Result<()>;
+    fn write_f64(&mut self, v: f64) -> Result<()>;
+    fn write_f32vec2(&mut self, v: &Vec2) -> Result<()>;
+    fn write_f32vec3(&mut self, v: &Vec3) -> Result<()>;
+    fn write_f32vec4(&mut self, v: &Vec4) -> Result<()>;
+    fn write_f32m4x4(&mut self, v: &Mat4x4) -> Result<()>;
+}
+
+pub trait SeekWriteExt {
+    fn write_align_up(&mut self, align: u64) -> Result<u64>;
+}
+
 impl<T: Read + ?Sized> ReadExt for T {
     fn read_bool(&mut self) -> Result<bool> {
         let v = self.read_u8()?;
@@ -193,3 +217,91 @@ impl<T: Seek + Read + ?Sized> SeekExt for T {
         Ok(self.stream_position()?)
     }
 }
+
+impl<T: Write + ?Sized> WriteExt for T {
+    fn write_bool(&mut self, v: bool) -> Result<()> {
+        self.write_u8(u8::from(v))
+    }
+    fn write_u8(&mut self, v: u8) -> Result<()> {
+        self.write_all(&[v])?;
+        Ok(())
+    }
+    fn write_u16(&mut self, v: u16) -> Result<()> {
+        self.write_all(&v.to_le_bytes())?;
+        Ok(())
+    }
+    fn write_u32(&mut self, v: u32) -> Result<()> {
+        self.write_all(&v.to_le_bytes())?;
+        Ok(())
+    }
+    fn write_u64(&mut self, v: u64) -> Result<()> {
+        self.write_all(&v.to_le_bytes())?;
+        Ok(())
+    }
+    fn write_i8(&mut self, v: i8) -> Result<()> {
+        self.write_all(&v.to_le_bytes())?;
+        Ok(())
+    }
+    fn write_i16(&mut self, v: i16) -> Result<()> {
+        self.write_all(&v.to_le_bytes())?;
+        Ok(())
+    }
+    fn write_i32(&mut self, v: i32) -> Result<()> {
+        self.write_all(&v.to_le_bytes())?;
+        Ok(())
+    }
+    fn write_i64(&mut self, v: i64) -> Result<()> {
+        self.write_all(&v.to_le_bytes())?;
+        Ok(())
+    }
+    fn write_magic(&mut self, v: &[u8; 4]) -> Result<()> {
+        self.write_all(v)?;
+        Ok(())
+    }
+    fn write_u16str(&mut self, v: &str) -> Result<()> {
+        for c in v.encode_utf16() {
+            self.write_u16(c)?;
+        }
+        self.write_u16(0)
+    }
+    fn write_f32(&mut self, v: f32) -> Result<()> {
+        self.write_all(&v.to_le_bytes())?;
+        Ok(())
+    }
+    fn write_f64(&mut self, v: f64) -> Result<()> {
+        self.write_all(&v.to_le_bytes())?;
+        Ok(())
+    }
+    fn write_f32vec2(&mut self, v: &Vec2) -> Result<()> {
+        self.write_f32(v.x)?;
+        self.write_f32(v.y)
+    }
+    fn write_f32vec3(&mut self, v: &Vec3) -> Result<()> {
+        self.write_f32(v.x)?;
+        self.write_f32(v.y)?;
+        self.write_f32(v.z)
+    }
+    fn write_f32vec4(&mut self, v: &Vec4) -> Result<()> {
+        self.write_f32(v.x)?;
+        self.write_f32(v.y)?;
+        self.write_f32(v.z)?;
+        self.write_f32(v.w)
+    }
+    fn write_f32m4x4(&mut self, v: &Mat4x4) -> Result<()> {
+        for &f in v.as_slice() {
+            self.write_f32(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Seek + Write + ?Sized> SeekWriteExt for T {
+    fn write_align_up(&mut self, align: u64) -> Result<u64> {
+        let pos = self.stream_position()?;
+        let aligned = align_up(pos, align);
+        if aligned != pos {
+            self.write_all(&vec![0; (aligned - pos).try_into()?])?;
+        }
+        Ok(aligned)
+    }
+}
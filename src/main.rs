@@ -2,14 +2,14 @@
 
 use anyhow::{anyhow, bail, Context, Result};
 use clap::*;
+use memmap2::Mmap;
 use minidump::*;
 use rayon::prelude::*;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write};
 use std::path::*;
-use std::sync::Mutex;
 
 mod align;
 mod bitfield;
@@ -53,8 +53,6 @@ pub mod built_info {
     include!(concat!(env!("OUT_DIR"), "/built.rs"));
 }
 
-const TDB_ANCHOR: &[u8] = b"TDB\0\x47\0\0\0";
-
 #[derive(clap::Parser)]
 pub struct TdbOptions {
     /// Optional output to a file in JSON
@@ -72,30 +70,48 @@ pub struct TdbOptions {
     #[clap(short, long)]
     pub cs: Option<String>,
 
-    /// C#: Remove runtime addresses.
+    /// Optional output to a C header file, with native field layouts and method prototypes.
+    /// Value types embedded in the listed types are always included.
+    #[clap(long)]
+    pub header: Option<String>,
+
+    /// C# and header: Remove runtime addresses.
     #[clap(long)]
     pub no_runtime: bool,
 
-    /// C#: Remove classes in System namespace.
+    /// C# and header: Remove classes in System namespace.
     #[clap(long)]
     pub no_system: bool,
 
-    /// C#: Remove template instantiation and array.
+    /// C# and header: Remove template instantiation and array.
     #[clap(long)]
     pub no_compound: bool,
 
     /// C#: Remove type flags.
     #[clap(long)]
     pub no_type_flag: bool,
+
+    /// Optional output of RSZ field layouts to a file in JSON.
+    /// This can be passed to --schema of read-user, dump-scn and dump-pfb
+    /// to decode types not supported by mhrice.
+    #[clap(long)]
+    pub rsz_schema: Option<String>,
+}
+
+#[derive(clap::Args)]
+struct PakArgs {
+    /// Paths to the PAK files, folder containing PAK files, or a .txt file listing all PAK files.
+    /// Loose files in the natives folder under given folders override PAK files
+    #[clap(short, long)]
+    pak: Vec<String>,
 }
 
 #[derive(clap::Parser)]
 enum Mhrice {
     /// Dump a sub-file with specific name from the PAK file
     Dump {
-        /// Paths to the PAK files, folder containing PAK files, or a .txt file listing all PAK files
-        #[clap(short, long)]
-        pak: Vec<String>,
+        #[clap(flatten)]
+        pak: PakArgs,
         /// Name of the sub-file to dump
         #[clap(short, long)]
         name: String,
@@ -106,9 +122,8 @@ enum Mhrice {
 
     /// Dump a sub-file with specific index from the PAK file
     DumpIndex {
-        /// Paths to the PAK files, folder containing PAK files, or a .txt file listing all PAK files
-        #[clap(short, long)]
-        pak: Vec<String>,
+        #[clap(flatten)]
+        pak: PakArgs,
         #[clap(short, long, default_value_t = 0)]
         version: usize,
         /// Index of the sub-file to dump
@@ -119,24 +134,93 @@ enum Mhrice {
         output: String,
     },
 
+    /// List all sub-files in the PAK file
+    List {
+        #[clap(flatten)]
+        pak: PakArgs,
+        /// Optional file name list, can be the output from search-path command
+        #[clap(short, long)]
+        list: Option<String>,
+    },
+
+    /// Compare two sets of PAK files and list added, removed and changed sub-files
+    DiffPak {
+        /// Old PAK files, in the same format as --pak in other commands
+        #[clap(long)]
+        old: Vec<String>,
+        /// New PAK files, in the same format as --pak in other commands
+        #[clap(long)]
+        new: Vec<String>,
+        /// Optional file name list to name the sub-files and group them by extension,
+        /// can be the output from search-path command
+        #[clap(short, long)]
+        list: Option<String>,
+    },
+
+    /// Extract all sub-files from the PAK file to a folder
+    ///
+    /// Sub-files without known names are extracted to unknown/<hash>.<ext>,
+    /// where the extension is guessed from the content.
+    /// Existing files of the expected size are skipped, so an interrupted extraction can be resumed.
+    Extract {
+        #[clap(flatten)]
+        pak: PakArgs,
+        /// Optional file name list, can be the output from search-path command
+        #[clap(short, long)]
+        list: Option<String>,
+        /// Output directory
+        #[clap(short, long)]
+        output: String,
+        /// Only extract sub-files whose output path matches the glob pattern, e.g. "gui/**/*.tex"
+        #[clap(short, long)]
+        filter: Option<String>,
+        /// Only extract sub-files with these extensions, e.g. "user"
+        #[clap(short, long)]
+        ext: Vec<String>,
+    },
+
+    /// Decompress all sub-files in the PAK file to verify their integrity
+    Verify {
+        #[clap(flatten)]
+        pak: PakArgs,
+        /// Optional file name list, can be the output from search-path command
+        #[clap(short, long)]
+        list: Option<String>,
+        /// Optional output of a JSON manifest with SHA-256 of all sub-files
+        #[clap(short, long)]
+        manifest: Option<String>,
+    },
+
     /// Scan the PAK file to verify known file formats that contains RSZ
     ///
     /// This will verify the files conform the format,
     /// and list the CRC mismatch among RSZ types found in them.
     ScanRsz {
-        /// Paths to the PAK files, folder containing PAK files, or a .txt file listing all PAK files
-        #[clap(short, long)]
-        pak: Vec<String>,
+        #[clap(flatten)]
+        pak: PakArgs,
         /// Print all gathered CRC instead of mismatched ones
         #[clap(short, long)]
         crc: bool,
+        /// Optional file name list to name the sub-files in the report, can be the output from search-path command
+        #[clap(short, long)]
+        list: Option<String>,
+        /// Also write USER, PFB and SCN files and supported RSZ data back,
+        /// and report files that don't round trip
+        #[clap(short, long)]
+        round_trip: bool,
+        /// Write all known CRCs, plus the mismatched ones, to a versions file.
+        /// Set MHRICE_RSZ_VERSIONS to the file to load it in later runs
+        #[clap(long, requires = "version")]
+        versions: Option<String>,
+        /// Game version to assign to the mismatched CRCs in the versions file, e.g. 150000
+        #[clap(long)]
+        version: Option<u32>,
     },
 
     /// Generate JSON file of game information from the PAK file
     GenJson {
-        /// Paths to the PAK files, folder containing PAK files, or a .txt file listing all PAK files
-        #[clap(short, long)]
-        pak: Vec<String>,
+        #[clap(flatten)]
+        pak: PakArgs,
         /// Record SHA-256 of the PAK file
         #[clap(short, long)]
         sha: bool,
@@ -144,9 +228,8 @@ enum Mhrice {
 
     /// Generate the mhrice website the PAK file
     GenWebsite {
-        /// Paths to the PAK files, folder containing PAK files, or a .txt file listing all PAK files
-        #[clap(short, long)]
-        pak: Vec<String>,
+        #[clap(flatten)]
+        pak: PakArgs,
         /// Output directory
         #[clap(short, long)]
         output: String,
@@ -168,6 +251,51 @@ enum Mhrice {
         options: TdbOptions,
     },
 
+    /// Generate RSZ type definitions from TDB
+    GenRsz {
+        /// Path to a TDB file, or a binary that contains one
+        #[clap(short, long)]
+        tdb: String,
+
+        /// Full name of the type to generate. Can be specified multiple times
+        #[clap(short, long)]
+        symbol: Vec<String>,
+
+        /// Game version for the current CRC, e.g. 150000
+        #[clap(short, long, default_value_t = 0)]
+        version: u32,
+    },
+
+    /// Compare TDB of two game builds, and list RSZ types that need to be updated
+    DiffTdb {
+        /// The old TDB file, a binary that contains one, or a full minidump (DMP file)
+        #[clap(long)]
+        old: String,
+        /// The new TDB file, a binary that contains one, or a full minidump (DMP file)
+        #[clap(long)]
+        new: String,
+    },
+
+    /// Search types in TDB, or print details of a type
+    TdbQuery {
+        /// Path to a TDB file, a binary that contains one, a full minidump (DMP file),
+        /// or the JSON output of read-tdb --json, which is faster to load repeatedly
+        #[clap(short, long)]
+        tdb: String,
+        /// Search types whose full name contains the text, case-insensitively
+        #[clap(short, long)]
+        name: Option<String>,
+        /// Search types whose full name matches the regex
+        #[clap(short, long)]
+        regex: Option<String>,
+        /// Print the inheritance chain, fields, methods and enum constants of a type
+        #[clap(long = "type")]
+        type_name: Option<String>,
+        /// Same as --type, but find the type by the hash used in RSZ type descriptors, in hex
+        #[clap(long)]
+        hash: Option<String>,
+    },
+
     /// Print messages from a MSG file
     ReadMsg {
         /// Path to the MSG file
@@ -175,11 +303,23 @@ enum Mhrice {
         msg: String,
     },
 
+    /// Rebuild a MSG file with messages edited in the JSON format of read-msg
+    WriteMsg {
+        /// Path to the original MSG file
+        #[clap(short, long)]
+        msg: String,
+        /// Path to the edited JSON file
+        #[clap(short, long)]
+        json: String,
+        /// Path to the output MSG file
+        #[clap(short, long)]
+        output: String,
+    },
+
     /// Scan the PAK file and output messages from all MSG files
     ScanMsg {
-        /// Paths to the PAK files, folder containing PAK files, or a .txt file listing all PAK files
-        #[clap(short, long)]
-        pak: Vec<String>,
+        #[clap(flatten)]
+        pak: PakArgs,
         /// Output directory
         #[clap(short, long)]
         output: String,
@@ -187,21 +327,25 @@ enum Mhrice {
 
     /// Scan the PAK file and find a regex pattern in MSG files
     GrepMsg {
-        /// Paths to the PAK files, folder containing PAK files, or a .txt file listing all PAK files
+        #[clap(flatten)]
+        pak: PakArgs,
+        /// Optional file name list to name the sub-files in the report, can be the output from search-path command
         #[clap(short, long)]
-        pak: Vec<String>,
+        list: Option<String>,
         /// The regex pattern
         pattern: String,
     },
 
     /// Scan the PAK file and find a regex pattern in all files
     Grep {
-        /// Paths to the PAK files, folder containing PAK files, or a .txt file listing all PAK files
-        #[clap(short, long)]
-        pak: Vec<String>,
+        #[clap(flatten)]
+        pak: PakArgs,
         /// Search for UTF-16 string
         #[clap(short, long)]
         utf16: bool,
+        /// Optional file name list to name the sub-files in the report, can be the output from search-path command
+        #[clap(short, long)]
+        list: Option<String>,
         /// The regex pattern
         pattern: String,
     },
@@ -209,9 +353,8 @@ enum Mhrice {
     /// Scan the PAK file as well as optionally full minidump samples
     /// and print all potential sub-file names
     SearchPath {
-        /// Paths to the PAK files, folder containing PAK files, or a .txt file listing all PAK files
-        #[clap(short, long)]
-        pak: Vec<String>,
+        #[clap(flatten)]
+        pak: PakArgs,
 
         /// Path to the full minidump files
         #[clap(short, long)]
@@ -220,9 +363,8 @@ enum Mhrice {
 
     /// Dump all sub-files from the PAK file
     DumpTree {
-        /// Paths to the PAK files, folder containing PAK files, or a .txt file listing all PAK files
-        #[clap(short, long)]
-        pak: Vec<String>,
+        #[clap(flatten)]
+        pak: PakArgs,
         /// File name list, can be the output from search-path command
         #[clap(short, long)]
         list: String,
@@ -231,32 +373,53 @@ enum Mhrice {
         output: String,
     },
 
+    /// Pack all files in a folder into a PAK file
+    Pack {
+        /// Input directory, which contains the "natives" folder
+        #[clap(short, long)]
+        input: String,
+        /// Output PAK file
+        #[clap(short, long)]
+        output: String,
+        /// Compression for sub-files: "none", "deflate" or "zstd"
+        #[clap(short, long, default_value = "zstd")]
+        compression: String,
+    },
+
     /// Scan the PAK file and verify the format of all MESH files
     ScanMesh {
-        /// Paths to the PAK files, folder containing PAK files, or a .txt file listing all PAK files
+        #[clap(flatten)]
+        pak: PakArgs,
+        /// Optional file name list to name the sub-files in the report, can be the output from search-path command
         #[clap(short, long)]
-        pak: Vec<String>,
+        list: Option<String>,
     },
 
     /// Scan the PAK file and verify the format of all TEX files
     ScanTex {
-        /// Paths to the PAK files, folder containing PAK files, or a .txt file listing all PAK files
+        #[clap(flatten)]
+        pak: PakArgs,
+        /// Optional file name list to name the sub-files in the report, can be the output from search-path command
         #[clap(short, long)]
-        pak: Vec<String>,
+        list: Option<String>,
     },
 
     /// Scan the PAK file and verify the format of all GUI files
     ScanGui {
-        /// Paths to the PAK files, folder containing PAK files, or a .txt file listing all PAK files
+        #[clap(flatten)]
+        pak: PakArgs,
+        /// Optional file name list to name the sub-files in the report, can be the output from search-path command
         #[clap(short, long)]
-        pak: Vec<String>,
+        list: Option<String>,
     },
 
     /// Scan the PAK file and verify the format of all UVS files
     ScanUvs {
-        /// Paths to the PAK files, folder containing PAK files, or a .txt file listing all PAK files
+        #[clap(flatten)]
+        pak: PakArgs,
+        /// Optional file name list to name the sub-files in the report, can be the output from search-path command
         #[clap(short, long)]
-        pak: Vec<String>,
+        list: Option<String>,
     },
 
     /// Convert a MESH file to a OBJ model file
@@ -317,9 +480,8 @@ enum Mhrice {
 
     /// Generate meat diagram PNG file for a monster
     GenMeat {
-        /// Paths to the PAK files, folder containing PAK files, or a .txt file listing all PAK files
-        #[clap(short, long)]
-        pak: Vec<String>,
+        #[clap(flatten)]
+        pak: PakArgs,
         /// Monster EmTypes ID
         #[clap(short, long)]
         index: u32,
@@ -330,9 +492,8 @@ enum Mhrice {
 
     /// Generate resource files (images etc.) for the website
     GenResources {
-        /// Paths to the PAK files, folder containing PAK files, or a .txt file listing all PAK files
-        #[clap(short, long)]
-        pak: Vec<String>,
+        #[clap(flatten)]
+        pak: PakArgs,
         /// Output directory
         #[clap(short, long)]
         output: String,
@@ -355,6 +516,42 @@ enum Mhrice {
         /// Version of the game, optional
         #[clap(short, long)]
         version: Option<u32>,
+        /// RSZ schema file generated by read-tdb, optional
+        #[clap(long)]
+        schema: Option<String>,
+        /// Print the type symbol of each root, in the format accepted by write-user
+        #[clap(long)]
+        typed: bool,
+    },
+
+    /// Build a USER file from JSON printed by read-user --typed
+    WriteUser {
+        /// Path to the JSON file
+        #[clap(short, long)]
+        json: String,
+        /// Output path of the USER file
+        #[clap(short, long)]
+        output: String,
+        /// The original USER file to take resource names, type CRCs and extern types from.
+        /// Without it, extern user files can't be written
+        #[clap(short, long)]
+        base: Option<String>,
+        /// Version of the game, optional
+        #[clap(short, long)]
+        version: Option<u32>,
+    },
+
+    /// Compare two versions of the same USER, PFB or SCN file field by field
+    DiffUser {
+        /// Path to the old file
+        #[clap(long)]
+        old: String,
+        /// Path to the new file
+        #[clap(long)]
+        new: String,
+        /// Version of the game, optional
+        #[clap(short, long)]
+        version: Option<u32>,
     },
 
     /// Find TDB in the a full minidump (DMP file) and print the converted TDB
@@ -377,6 +574,9 @@ enum Mhrice {
         /// Path to the SCN file
         #[clap(short, long)]
         scn: String,
+        /// RSZ schema file generated by read-tdb, optional
+        #[clap(long)]
+        schema: Option<String>,
     },
 
     /// Print information of a PFB file
@@ -384,13 +584,15 @@ enum Mhrice {
         /// Path to the SCN file
         #[clap(short, long)]
         pfb: String,
+        /// RSZ schema file generated by read-tdb, optional
+        #[clap(long)]
+        schema: Option<String>,
     },
 
     /// Print information of a SCN tree
     Scene {
-        /// Paths to the PAK files, folder containing PAK files, or a .txt file listing all PAK files
-        #[clap(short, long)]
-        pak: Vec<String>,
+        #[clap(flatten)]
+        pak: PakArgs,
         /// The name of the root SCN file
         #[clap(short, long)]
         name: String,
@@ -409,9 +611,25 @@ enum Mhrice {
         crc: String,
     },
 
-    Map {
+    /// Decode a managed object in a minidump to JSON
+    InspectObject {
+        /// Path to the full minidump (DMP file)
+        #[clap(short, long)]
+        dmp: String,
+        /// Address of the object in hex
         #[clap(short, long)]
-        pak: Vec<String>,
+        address: String,
+        /// Full name of the object type. Detected from the object header if not specified
+        #[clap(short, long = "type")]
+        type_name: Option<String>,
+        /// Levels of references to follow
+        #[clap(long, default_value_t = 2)]
+        depth: usize,
+    },
+
+    Map {
+        #[clap(flatten)]
+        pak: PakArgs,
 
         #[clap(short, long)]
         name: String,
@@ -427,7 +645,8 @@ enum Mhrice {
     },
 }
 
-fn open_pak_files(mut pak: Vec<String>) -> Result<Vec<File>> {
+fn open_pak(mut pak: Vec<String>) -> Result<PakReader<File>> {
+    let mut loose_roots = vec![];
     if pak.len() == 1 && Path::new(&pak[0]).is_dir() {
         eprintln!("Listing all PAK files in the folder...");
         let dir = pak.pop().unwrap();
@@ -448,6 +667,9 @@ fn open_pak_files(mut pak: Vec<String>) -> Result<Vec<File>> {
         for path in &pak {
             eprintln!("Found PAK file: {path}");
         }
+        if dir.join("natives").is_dir() {
+            loose_roots.push(dir.to_owned());
+        }
     } else if pak.len() == 1 && pak[0].to_lowercase().ends_with(".txt") {
         eprintln!("Listing all PAK files from the txt file...");
         let mut txt = BufReader::new(File::open(&pak[0])?);
@@ -463,22 +685,351 @@ fn open_pak_files(mut pak: Vec<String>) -> Result<Vec<File>> {
         }
     }
 
-    pak.into_iter().map(|path| Ok(File::open(path)?)).collect()
+    pak.retain(|path| {
+        let path = Path::new(path);
+        if path.is_dir() {
+            loose_roots.push(path.to_owned());
+            false
+        } else {
+            true
+        }
+    });
+
+    let files = pak
+        .into_iter()
+        .map(|path| {
+            let file = File::open(&path)?;
+            // Safety: PAK files are not expected to be modified while we are reading them
+            Ok(match unsafe { Mmap::map(&file) } {
+                Ok(mmap) => PakSource::Mapped(mmap),
+                Err(e) => {
+                    eprintln!("Cannot map {path} ({e}), reading it as a stream");
+                    PakSource::Stream(file)
+                }
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut pak = PakReader::new(files)?;
+    for root in loose_roots {
+        eprintln!("Using loose files in {}", root.display());
+        pak.add_loose_root(&root)?;
+    }
+    Ok(pak)
+}
+
+fn open_pak_with_list(pak: Vec<String>, list: Option<String>) -> Result<PakReader<File>> {
+    let mut pak = open_pak(pak)?;
+    if let Some(list) = list {
+        let matched = pak.load_path_list(BufReader::new(File::open(list)?))?;
+        eprintln!("Matched {matched} paths from the list");
+    }
+    Ok(pak)
 }
 
 fn dump(pak: Vec<String>, name: String, output: String) -> Result<()> {
-    let mut pak = PakReader::new(open_pak_files(pak)?)?;
+    let pak = open_pak(pak)?;
     let index = pak.find_file(&name).context("Cannot find subfile")?;
     println!("Index {index:?}");
-    let content = pak.read_file(index)?;
-    std::fs::write(output, content)?;
+    std::io::copy(&mut pak.open_file(index)?, &mut File::create(output)?)?;
     Ok(())
 }
 
 fn dump_index(pak: Vec<String>, version: usize, index: usize, output: String) -> Result<()> {
-    let mut pak = PakReader::new(open_pak_files(pak)?)?;
-    let content = pak.read_file_at(version, index)?;
-    std::fs::write(output, content)?;
+    let pak = open_pak(pak)?;
+    let index = pak.file_index_at(version, index)?;
+    std::io::copy(&mut pak.open_file(index)?, &mut File::create(output)?)?;
+    Ok(())
+}
+
+fn list_files(pak: Vec<String>, list: Option<String>) -> Result<()> {
+    let pak = open_pak_with_list(pak, list)?;
+    let mut unresolved = 0;
+    for index in pak.all_file_indexs() {
+        let info = pak.entry_info(index)?;
+        let format = match info.format {
+            0 => "stored".to_owned(),
+            1 | 0x11 => "deflate".to_owned(),
+            2 => "zstd".to_owned(),
+            format => format!("{format:#x}"),
+        };
+        let encryption = if info.encryption != 0 { "E" } else { "-" };
+        let name = if let Some(name) = pak.file_name(index) {
+            name
+        } else {
+            unresolved += 1;
+            "?"
+        };
+        println!(
+            "{} {:016X} {:>10} {:>10} {:<7} {} {}",
+            index.short_string(),
+            info.hash,
+            info.len,
+            info.len_compressed,
+            format,
+            encryption,
+            name
+        );
+    }
+    eprintln!("{unresolved} unresolved entries");
+    Ok(())
+}
+
+fn diff_pak(old: Vec<String>, new: Vec<String>, list: Option<String>) -> Result<()> {
+    let group_by_extension = list.is_some();
+    let old = open_pak_with_list(old, list.clone())?;
+    let new = open_pak_with_list(new, list)?;
+
+    fn hash_indexs(pak: &PakReader<impl Read + Seek>) -> Result<BTreeMap<u64, PakFileIndex>> {
+        pak.all_file_indexs()
+            .into_iter()
+            .map(|index| Ok((pak.entry_info(index)?.hash, index)))
+            .collect()
+    }
+
+    let old_indexs = hash_indexs(&old)?;
+    let new_indexs = hash_indexs(&new)?;
+
+    let name = |pak: &PakReader<_>, hash: u64, index| {
+        pak.file_name(index)
+            .map(|name| name.to_owned())
+            .unwrap_or_else(|| format!("{hash:016X}"))
+    };
+
+    let mut diffs: Vec<(String, char, String)> = vec![];
+    for (&hash, &index) in &old_indexs {
+        if !new_indexs.contains_key(&hash) {
+            diffs.push((name(&old, hash, index), '-', String::new()));
+        }
+    }
+    for (&hash, &index) in &new_indexs {
+        if !old_indexs.contains_key(&hash) {
+            diffs.push((name(&new, hash, index), '+', String::new()));
+        }
+    }
+
+    eprintln!("Comparing common files...");
+    let common: Vec<_> = old_indexs
+        .iter()
+        .filter_map(|(hash, &old_index)| Some((*hash, old_index, *new_indexs.get(hash)?)))
+        .collect();
+    diffs.extend(
+        common
+            .into_par_iter()
+            .map(|(hash, old_index, new_index)| {
+                let old_len = old.entry_info(old_index)?.len;
+                let new_len = new.entry_info(new_index)?.len;
+                let changed = old_len != new_len
+                    || old.entry_sha256(old_index)? != new.entry_sha256(new_index)?;
+                Ok(changed.then(|| {
+                    let name = new
+                        .file_name(new_index)
+                        .or_else(|| old.file_name(old_index))
+                        .map(|name| name.to_owned())
+                        .unwrap_or_else(|| format!("{hash:016X}"));
+                    (name, 'M', format!(" ({old_len} -> {new_len} bytes)"))
+                }))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten(),
+    );
+
+    let mut groups: BTreeMap<String, Vec<(String, char, String)>> = BTreeMap::new();
+    for diff in diffs {
+        let extension = if group_by_extension {
+            file_extension(&diff.0).unwrap_or("?").to_owned()
+        } else {
+            String::new()
+        };
+        groups.entry(extension).or_default().push(diff);
+    }
+
+    let (mut added, mut removed, mut changed) = (0, 0, 0);
+    for (extension, mut diffs) in groups {
+        if group_by_extension {
+            println!("[{extension}]");
+        }
+        diffs.sort();
+        for (name, kind, detail) in diffs {
+            match kind {
+                '+' => added += 1,
+                '-' => removed += 1,
+                _ => changed += 1,
+            }
+            println!("{kind} {name}{detail}");
+        }
+    }
+    eprintln!("{added} added, {removed} removed, {changed} changed");
+
+    Ok(())
+}
+
+/// Extension of a sub-file name, excluding the version and language suffixes
+fn file_extension(name: &str) -> Option<&str> {
+    name.rsplit('/').next().unwrap().split('.').nth(1)
+}
+
+fn guess_extension(data: &[u8]) -> Option<&'static str> {
+    let magic = data.get(0..4)?;
+    let magic_at_4 = data.get(4..8);
+    Some(if &magic[0..3] == b"USR" {
+        "user"
+    } else if &magic[0..3] == b"PFB" {
+        "pfb"
+    } else if &magic[0..3] == b"SCN" {
+        "scn"
+    } else if magic == b"RCOL" {
+        "rcol"
+    } else if magic == b"MESH" {
+        "mesh"
+    } else if magic == b"TEX\0" {
+        "tex"
+    } else if magic == b".SVU" {
+        "uvs"
+    } else if magic_at_4 == Some(b"GMSG") {
+        "msg"
+    } else if magic_at_4 == Some(b"GUIR") {
+        "gui"
+    } else {
+        return None;
+    })
+}
+
+fn glob_to_regex(glob: &str) -> Result<regex::Regex> {
+    let mut pattern = "^".to_owned();
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                pattern += ".*";
+            }
+            '*' => pattern += "[^/]*",
+            '?' => pattern += "[^/]",
+            c => pattern += &regex::escape(&c.to_string()),
+        }
+    }
+    pattern += "$";
+    Ok(regex::RegexBuilder::new(&pattern)
+        .case_insensitive(true)
+        .build()?)
+}
+
+fn extract(
+    pak: Vec<String>,
+    list: Option<String>,
+    output: String,
+    filter: Option<String>,
+    ext: Vec<String>,
+) -> Result<()> {
+    let pak = open_pak_with_list(pak, list)?;
+    let filter = filter.map(|filter| glob_to_regex(&filter)).transpose()?;
+    let counter = std::sync::atomic::AtomicU32::new(0);
+
+    pak.all_file_indexs()
+        .into_par_iter()
+        .try_for_each(|index| -> Result<()> {
+            let info = pak.entry_info(index)?;
+            let name = if let Some(name) = pak.file_name(index) {
+                name.to_owned()
+            } else {
+                let mut magic = vec![];
+                pak.open_file(index)?.take(8).read_to_end(&mut magic)?;
+                let extension = guess_extension(&magic).unwrap_or("bin");
+                format!("unknown/{:016X}.{extension}", info.hash)
+            };
+
+            if !ext.is_empty() && !file_extension(&name).is_some_and(|e| ext.iter().any(|x| x == e))
+            {
+                return Ok(());
+            }
+            if let Some(filter) = &filter {
+                if !filter.is_match(&name) {
+                    return Ok(());
+                }
+            }
+
+            let mut path = PathBuf::from(&output);
+            for component in name.split('/') {
+                path.push(component);
+            }
+            if std::fs::metadata(&path).is_ok_and(|m| m.len() == info.len) {
+                return Ok(());
+            }
+
+            std::fs::create_dir_all(path.parent().context("no parent")?)?;
+            let mut part_path = path.clone().into_os_string();
+            part_path.push(".part");
+            std::io::copy(&mut pak.open_file(index)?, &mut File::create(&part_path)?)
+                .context(format!("Failed to extract {name}"))?;
+            std::fs::rename(&part_path, &path)?;
+
+            let counter_prev = counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            if counter_prev % 1000 == 0 {
+                eprintln!("Extracted {counter_prev} files so far")
+            }
+            Ok(())
+        })?;
+
+    eprintln!(
+        "Extracted {} files",
+        counter.load(std::sync::atomic::Ordering::SeqCst)
+    );
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+struct ManifestEntry {
+    hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    len: u64,
+    sha256: String,
+}
+
+fn verify(pak: Vec<String>, list: Option<String>, manifest: Option<String>) -> Result<()> {
+    let pak = open_pak_with_list(pak, list)?;
+
+    let results: Vec<_> = pak
+        .all_file_indexs()
+        .into_par_iter()
+        .map(|index| -> Result<ManifestEntry> {
+            let info = pak.entry_info(index)?;
+            Ok(ManifestEntry {
+                hash: format!("{:016X}", info.hash),
+                name: pak.file_name(index).map(|name| name.to_owned()),
+                len: info.len,
+                sha256: pak.entry_sha256(index).context(format!(
+                    "Failed at {} (format {:#x}, encryption {})",
+                    pak.display_name(index),
+                    info.format,
+                    info.encryption
+                ))?,
+            })
+        })
+        .collect();
+
+    let mut entries = vec![];
+    let mut error_count = 0;
+    for result in results {
+        match result {
+            Ok(entry) => entries.push(entry),
+            Err(e) => {
+                error_count += 1;
+                println!("{e:#}");
+            }
+        }
+    }
+
+    if let Some(manifest) = manifest {
+        entries.sort_by(|a, b| a.hash.cmp(&b.hash));
+        std::fs::write(manifest, serde_json::to_string_pretty(&entries)?)?;
+    }
+
+    eprintln!("Verified {} files", entries.len());
+    if error_count != 0 {
+        bail!("{error_count} files failed to verify");
+    }
     Ok(())
 }
 
@@ -509,51 +1060,120 @@ fn visit_tree(nodes: &mut [TreeNode], current: usize, depth: i32) {
     nodes[current].visited = true;
 }*/
 
-fn scan_rsz(pak: Vec<String>, print_all: bool) -> Result<()> {
-    let mut pak = PakReader::new(open_pak_files(pak)?)?;
+fn scan_rsz(
+    pak: Vec<String>,
+    print_all: bool,
+    list: Option<String>,
+    round_trip: bool,
+    versions: Option<String>,
+    version: Option<u32>,
+) -> Result<()> {
+    let pak = open_pak_with_list(pak, list)?;
+
+    let (crc_mismatches, round_trip_errors) = pak
+        .all_file_indexs()
+        .into_par_iter()
+        .map(|index| -> Result<_> {
+            let mut crc_mismatches = BTreeMap::new();
+            let mut round_trip_errors = BTreeMap::new();
+            let content = pak.read_file(index).context(format!(
+                "Failed to open file at {}",
+                pak.display_name(index)
+            ))?;
+            if content.len() < 4 {
+                return Ok((crc_mismatches, round_trip_errors));
+            }
 
-    let mut crc_mismatches = BTreeMap::new();
+            // The container is written back with the original RSZ block to check the headers
+            let mut rewritten = Cursor::new(vec![]);
+            let rsz = if &content[0..3] == b"USR" {
+                let user = User::new(Cursor::new(&content)).context(format!(
+                    "Failed to open USER at {}",
+                    pak.display_name(index)
+                ))?;
+                if round_trip {
+                    user.write(&mut rewritten)?;
+                }
+                user.rsz
+            } else if &content[0..3] == b"PFB" {
+                let pfb = Pfb::new(Cursor::new(&content))
+                    .context(format!("Failed to open PFB at {}", pak.display_name(index)))?;
+                if round_trip {
+                    pfb.write(&mut rewritten)?;
+                }
+                pfb.rsz
+            } else if &content[0..3] == b"SCN" {
+                let scn = Scn::new(Cursor::new(&content))
+                    .context(format!("Failed to open SCN at {}", pak.display_name(index)))?;
+                if round_trip {
+                    scn.write(&mut rewritten)?;
+                }
+                scn.rsz
+            } else if &content[0..4] == b"RCOL" {
+                Rcol::new(Cursor::new(&content), false)
+                    .context(format!(
+                        "Failed to open RCOL at {}",
+                        pak.display_name(index)
+                    ))?
+                    .rsz
+            } else {
+                return Ok((crc_mismatches, round_trip_errors));
+            };
+
+            let rewritten = rewritten.into_inner();
+            if !rewritten.is_empty() && rewritten != content {
+                let pos = rewritten
+                    .iter()
+                    .zip(&content)
+                    .position(|(a, b)| a != b)
+                    .unwrap_or(rewritten.len().min(content.len()));
+                round_trip_errors.insert(
+                    pak.display_name(index),
+                    format!("Container mismatch at {pos:08X}"),
+                );
+            }
 
-    for index in pak.all_file_indexs() {
-        let content = pak
-            .read_file(index)
-            .context(format!("Failed to open file at {index:?}"))?;
-        if content.len() < 4 {
-            continue;
-        }
+            rsz.verify_crc(&mut crc_mismatches, print_all);
 
-        if &content[0..3] == b"USR" {
-            User::new(Cursor::new(&content))
-                .context(format!("Failed to open USER at {index:?}"))?
-                .rsz
-                .verify_crc(&mut crc_mismatches, print_all);
-        } else if &content[0..3] == b"PFB" {
-            Pfb::new(Cursor::new(&content))
-                .context(format!("Failed to open PFB at {index:?}"))?
-                .rsz
-                .verify_crc(&mut crc_mismatches, print_all);
-        } else if &content[0..3] == b"SCN" {
-            Scn::new(Cursor::new(&content))
-                .context(format!("Failed to open SCN at {index:?}"))?
-                .rsz
-                .verify_crc(&mut crc_mismatches, print_all);
-        } else if &content[0..4] == b"RCOL" {
-            Rcol::new(Cursor::new(&content), false)
-                .context(format!("Failed to open RCOL at {index:?}"))?
-                .rsz
-                .verify_crc(&mut crc_mismatches, print_all);
-        }
-    }
+            if round_trip {
+                // Only data made entirely of supported types can be checked
+                if let Ok(roots) = rsz.deserialize(None) {
+                    if let Err(e) = rsz.verify_round_trip(&roots, None) {
+                        round_trip_errors
+                            .entry(pak.display_name(index))
+                            .or_insert(format!("{e:#}"));
+                    }
+                }
+            }
+
+            Ok((crc_mismatches, round_trip_errors))
+        })
+        .try_reduce(
+            || (BTreeMap::new(), BTreeMap::new()),
+            |mut a, b| {
+                a.0.extend(b.0);
+                a.1.extend(b.1);
+                Ok(a)
+            },
+        )?;
 
-    for (symbol, crc) in crc_mismatches {
+    for (symbol, crc) in &crc_mismatches {
         println!("Mismatch CRC {crc:08X} for {symbol}")
     }
 
+    if let (Some(versions), Some(version)) = (versions, version) {
+        rsz::save_versions(&versions, &crc_mismatches, version)?;
+    }
+
+    for (name, error) in round_trip_errors {
+        println!("Round trip failed for {name}: {error}")
+    }
+
     Ok(())
 }
 
 fn gen_json(pak: Vec<String>, sha: bool) -> Result<()> {
-    let mut pak = PakReader::new(open_pak_files(pak)?)?;
+    let mut pak = open_pak(pak)?;
     let mut logger_root = LoggerRoot::new();
     let logger = &mut logger_root.logger();
     let pedia = extract::gen_pedia(&mut pak, sha, logger)?;
@@ -568,7 +1188,7 @@ fn gen_website_to_sink(
     config: extract::WebsiteConfig,
     sha: bool,
 ) -> Result<()> {
-    let mut pak = PakReader::new(open_pak_files(pak)?)?;
+    let mut pak = open_pak(pak)?;
     let mut logger_root = LoggerRoot::new();
     {
         let logger = &mut logger_root.logger();
@@ -640,22 +1260,43 @@ impl<F: Seek> Seek for OffsetFile<F> {
     }
 }
 
-fn read_tdb(tdb: String, options: TdbOptions) -> Result<()> {
+// The anchor can also match unrelated data, so the search continues until a supported version.
+// Otherwise the first match is used, so that its version is reported as unsupported.
+fn find_tdb(tdb: String) -> Result<OffsetFile<BufReader<File>>> {
     let mut file = BufReader::new(File::open(tdb)?);
+    let mut unsupported = None;
     let offset = loop {
-        let mut magic = vec![0; TDB_ANCHOR.len()];
-        file.read_exact(&mut magic)?;
-        if magic == TDB_ANCHOR {
-            break file.seek(SeekFrom::Current(-(TDB_ANCHOR.len() as i64)))?;
-        } else {
-            file.seek(SeekFrom::Current(-(TDB_ANCHOR.len() as i64) + 1))?;
+        let mut anchor = [0; tdb::ANCHOR_LEN];
+        match file.read_exact(&mut anchor) {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break unsupported.context("TDB not found")?;
+            }
+            Err(e) => return Err(e.into()),
         }
+        match tdb::anchor_version(&anchor) {
+            Some(version) if tdb::is_supported_version(version) => {
+                break file.seek(SeekFrom::Current(-(tdb::ANCHOR_LEN as i64)))?;
+            }
+            Some(_) if unsupported.is_none() => {
+                unsupported = Some(file.stream_position()? - tdb::ANCHOR_LEN as u64);
+            }
+            _ => (),
+        }
+        file.seek(SeekFrom::Current(-(tdb::ANCHOR_LEN as i64) + 1))?;
     };
+    OffsetFile::new(file, offset)
+}
 
-    tdb::print(OffsetFile::new(file, offset)?, 0, options)?;
+fn read_tdb(tdb: String, options: TdbOptions) -> Result<()> {
+    tdb::print(find_tdb(tdb)?, 0, options)?;
     Ok(())
 }
 
+fn gen_rsz(tdb: String, symbol: Vec<String>, version: u32) -> Result<()> {
+    tdb::gen_rsz(find_tdb(tdb)?, 0, &symbol, version)
+}
+
 struct MinidumpReader<'a> {
     memory_list: &'a MinidumpMemory64List<'a>,
     pos: u64,
@@ -725,22 +1366,97 @@ fn read_dmp_tdb(dmp: String, address: Option<String>, options: TdbOptions) -> Re
         return Ok(());
     }
 
+    if let Some(base) = find_dmp_tdb(&memory)? {
+        eprintln!("Found at address 0x{base:016X}");
+        let file = MinidumpReader::new(&memory);
+
+        tdb::print(file, base, options)?;
+    }
+
+    Ok(())
+}
+
+// Same as find_tdb, a supported version is preferred over the first match
+fn find_dmp_tdb(memory: &MinidumpMemory64List) -> Result<Option<u64>> {
+    let mut unsupported = None;
     for block in memory.iter() {
-        if let Some(pos) = block
-            .bytes
-            .windows(TDB_ANCHOR.len())
-            .position(|w| w == TDB_ANCHOR)
-        {
-            let base = block.base_address + u64::try_from(pos)?;
-            eprintln!("Found at address 0x{base:016X}");
-            let file = MinidumpReader::new(&memory);
-
-            tdb::print(file, base, options)?;
-
-            break;
+        for (pos, window) in block.bytes.windows(tdb::ANCHOR_LEN).enumerate() {
+            let Some(version) = tdb::anchor_version(window) else {
+                continue;
+            };
+            let address = block.base_address + u64::try_from(pos)?;
+            if tdb::is_supported_version(version) {
+                return Ok(Some(address));
+            }
+            unsupported.get_or_insert(address);
+        }
+    }
+    Ok(unsupported)
+}
+
+fn load_tdb(path: &str) -> Result<tdb::Tdb> {
+    let mut magic = [0; 4];
+    File::open(path)?.read_exact(&mut magic)?;
+    if magic[0] == b'{' {
+        tdb::Tdb::load_json(path)
+    } else if &magic == b"MDMP" {
+        let dmp = Minidump::read_path(path).map_err(|e| anyhow!(e))?;
+        let memory = dmp
+            .get_stream::<MinidumpMemory64List>()
+            .map_err(|e| anyhow!(e))
+            .context("No full dump memory found")?;
+        let base = find_dmp_tdb(&memory)?.context("TDB not found in the minidump")?;
+        tdb::Tdb::new(MinidumpReader::new(&memory), base)
+    } else {
+        tdb::Tdb::new(find_tdb(path.to_owned())?, 0)
+    }
+}
+
+fn tdb_query(
+    tdb: String,
+    name: Option<String>,
+    regex: Option<String>,
+    type_name: Option<String>,
+    hash: Option<String>,
+) -> Result<()> {
+    let tdb = load_tdb(&tdb)?;
+
+    let pattern = match (name, regex) {
+        (Some(name), _) => Some(regex::Regex::new(&format!("(?i){}", regex::escape(&name)))?),
+        (None, Some(regex)) => Some(regex::Regex::new(&regex)?),
+        (None, None) => None,
+    };
+    if let Some(pattern) = pattern {
+        for ti in tdb.search(&pattern) {
+            println!("{}", tdb.type_summary(ti));
         }
     }
 
+    let ti = if let Some(type_name) = type_name {
+        Some(
+            tdb.find_type(&type_name)
+                .with_context(|| format!("Type {type_name} not found"))?,
+        )
+    } else if let Some(hash) = hash {
+        let hash = u32::from_str_radix(hash.trim_start_matches("0x"), 16)?;
+        Some(
+            tdb.find_hash(hash)
+                .with_context(|| format!("Type with hash {hash:08X} not found"))?,
+        )
+    } else {
+        None
+    };
+    if let Some(ti) = ti {
+        print!("{}", tdb.describe_type(ti)?);
+    }
+
+    Ok(())
+}
+
+fn diff_tdb(old: String, new: String) -> Result<()> {
+    let old = load_tdb(&old).context("Failed to load the old TDB")?;
+    let new = load_tdb(&new).context("Failed to load the new TDB")?;
+    print!("{}", old.diff(&new)?);
     Ok(())
 }
 
@@ -799,14 +1515,49 @@ fn type_info(dmp: String, hash: String, crc: String) -> Result<()> {
     Ok(())
 }
 
+fn inspect_object(
+    dmp: String,
+    address: String,
+    type_name: Option<String>,
+    depth: usize,
+) -> Result<()> {
+    let address = u64::from_str_radix(address.trim_start_matches("0x"), 16)?;
+    let dmp = Minidump::read_path(dmp).map_err(|e| anyhow!(e))?;
+    let memory = dmp
+        .get_stream::<MinidumpMemory64List>()
+        .map_err(|e| anyhow!(e))
+        .context("No full dump memory found")?;
+    let base = find_dmp_tdb(&memory)?.context("TDB not found in the minidump")?;
+    let tdb = tdb::Tdb::new(MinidumpReader::new(&memory), base)?;
+
+    let ti = type_name
+        .map(|type_name| {
+            tdb.find_type(&type_name)
+                .with_context(|| format!("Type {type_name} not found"))
+        })
+        .transpose()?;
+    let object = tdb.inspect_object(MinidumpReader::new(&memory), address, ti, depth)?;
+    println!("{}", serde_json::to_string_pretty(&object)?);
+    Ok(())
+}
+
 fn read_msg(msg: String) -> Result<()> {
     let msg = Msg::new(File::open(msg)?)?;
     println!("{}", serde_json::to_string_pretty(&msg)?);
     Ok(())
 }
 
+fn write_msg(msg: String, json: String, output: String) -> Result<()> {
+    let mut msg = Msg::new(File::open(msg)?)?;
+    let edited: Msg = serde_json::from_reader(BufReader::new(File::open(json)?))
+        .context("Failed to parse JSON")?;
+    msg.attribute_headers = edited.attribute_headers;
+    msg.entries = edited.entries;
+    msg.write(std::io::BufWriter::new(File::create(output)?))
+}
+
 fn scan_msg(pak: Vec<String>, output: String) -> Result<()> {
-    let mut pak = PakReader::new(open_pak_files(pak)?)?;
+    let pak = open_pak(pak)?;
     std::fs::create_dir_all(&output)?;
     for i in pak.all_file_indexs() {
         let file = pak.read_file(i)?;
@@ -825,81 +1576,88 @@ fn scan_msg(pak: Vec<String>, output: String) -> Result<()> {
     Ok(())
 }
 
-fn grep_msg(pak: Vec<String>, pattern: String) -> Result<()> {
-    let mut pak = PakReader::new(open_pak_files(pak)?)?;
+fn grep_msg(pak: Vec<String>, pattern: String, list: Option<String>) -> Result<()> {
+    let pak = open_pak_with_list(pak, list)?;
     use regex::*;
     let regex = RegexBuilder::new(&pattern).build()?;
-    for i in pak.all_file_indexs() {
-        let file = pak.read_file(i)?;
-        if file.len() < 8 || file[4..8] != b"GMSG"[..] {
-            continue;
-        }
-        let msg = Msg::new(Cursor::new(&file)).context(format!("at {i:?}"))?;
-        for entry in &msg.entries {
-            for text in &entry.content {
-                if regex.is_match(text) {
-                    println!("Found @ {i:?}");
-                }
+    let found = pak
+        .all_file_indexs()
+        .into_par_iter()
+        .map(|i| {
+            let file = pak.read_file(i)?;
+            if file.len() < 8 || file[4..8] != b"GMSG"[..] {
+                return Ok((i, 0));
             }
+            let msg =
+                Msg::new(Cursor::new(&file)).context(format!("at {}", pak.display_name(i)))?;
+            let count = msg
+                .entries
+                .iter()
+                .flat_map(|entry| &entry.content)
+                .filter(|text| regex.is_match(text))
+                .count();
+            Ok((i, count))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for (i, count) in found {
+        for _ in 0..count {
+            println!("Found @ {}", pak.display_name(i));
         }
     }
     Ok(())
 }
 
-fn scan_mesh(pak: Vec<String>) -> Result<()> {
-    let mut pak = PakReader::new(open_pak_files(pak)?)?;
-    for i in pak.all_file_indexs() {
+fn scan_mesh(pak: Vec<String>, list: Option<String>) -> Result<()> {
+    let pak = open_pak_with_list(pak, list)?;
+    pak.all_file_indexs().into_par_iter().try_for_each(|i| {
         let file = pak.read_file(i)?;
         if file.len() < 4 || file[0..4] != b"MESH"[..] {
-            continue;
+            return Ok(());
         }
-        let _ = Mesh::new(Cursor::new(&file)).context(format!("at {i:?}"))?;
-    }
-    Ok(())
+        let _ = Mesh::new(Cursor::new(&file)).context(format!("at {}", pak.display_name(i)))?;
+        Ok(())
+    })
 }
 
-fn scan_tex(pak: Vec<String>) -> Result<()> {
-    let mut pak = PakReader::new(open_pak_files(pak)?)?;
-    for i in pak.all_file_indexs() {
+fn scan_tex(pak: Vec<String>, list: Option<String>) -> Result<()> {
+    let pak = open_pak_with_list(pak, list)?;
+    pak.all_file_indexs().into_par_iter().try_for_each(|i| {
         let file = pak.read_file(i)?;
         if file.len() < 4 || file[0..4] != b"TEX\0"[..] {
-            continue;
+            return Ok(());
         }
-        let _ = Tex::new(Cursor::new(&file)).context(format!("at {i:?}"))?;
-    }
-
-    Ok(())
+        let _ = Tex::new(Cursor::new(&file)).context(format!("at {}", pak.display_name(i)))?;
+        Ok(())
+    })
 }
 
-fn scan_gui(pak: Vec<String>) -> Result<()> {
-    let mut pak = PakReader::new(open_pak_files(pak)?)?;
-    for i in pak.all_file_indexs() {
+fn scan_gui(pak: Vec<String>, list: Option<String>) -> Result<()> {
+    let pak = open_pak_with_list(pak, list)?;
+    pak.all_file_indexs().into_par_iter().try_for_each(|i| {
         let file = pak.read_file(i)?;
         if file.len() < 8 || file[4..8] != b"GUIR"[..] {
-            continue;
+            return Ok(());
         }
-        let _ = Gui::new(Cursor::new(&file)).context(format!("at {i:?}"))?;
-    }
-
-    Ok(())
+        let _ = Gui::new(Cursor::new(&file)).context(format!("at {}", pak.display_name(i)))?;
+        Ok(())
+    })
 }
 
-fn scan_uvs(pak: Vec<String>) -> Result<()> {
-    let mut pak = PakReader::new(open_pak_files(pak)?)?;
-    for i in pak.all_file_indexs() {
+fn scan_uvs(pak: Vec<String>, list: Option<String>) -> Result<()> {
+    let pak = open_pak_with_list(pak, list)?;
+    pak.all_file_indexs().into_par_iter().try_for_each(|i| {
         let file = pak.read_file(i)?;
         if file.len() < 4 || file[0..4] != b".SVU"[..] {
-            continue;
+            return Ok(());
         }
-        let _ = Uvs::new(Cursor::new(&file)).context(format!("at {i:?}"))?;
-    }
-
-    Ok(())
+        let _ = Uvs::new(Cursor::new(&file)).context(format!("at {}", pak.display_name(i)))?;
+        Ok(())
+    })
 }
 
-fn grep(pak: Vec<String>, utf16: bool, mut pattern: String) -> Result<()> {
+fn grep(pak: Vec<String>, utf16: bool, mut pattern: String, list: Option<String>) -> Result<()> {
     use regex::bytes::*;
-    let mut pak = PakReader::new(open_pak_files(pak)?)?;
+    let pak = open_pak_with_list(pak, list)?;
     if utf16 {
         pattern = pattern
             .encode_utf16()
@@ -911,18 +1669,20 @@ fn grep(pak: Vec<String>, utf16: bool, mut pattern: String) -> Result<()> {
     }
     println!("Searching for patterns \"{}\"", &pattern);
     let re = RegexBuilder::new(&pattern).unicode(false).build()?;
-    for i in pak.all_file_indexs() {
-        let file = pak.read_file(i)?;
-        if re.is_match(&file) {
-            println!("Matched @ {i:?}");
-        }
+    let matched = pak
+        .all_file_indexs()
+        .into_par_iter()
+        .map(|i| Ok(re.is_match(&pak.read_file(i)?).then_some(i)))
+        .collect::<Result<Vec<_>>>()?;
+    for i in matched.into_iter().flatten() {
+        println!("Matched @ {}", pak.display_name(i));
     }
     Ok(())
 }
 
 fn search_path(pak: Vec<String>, dmp: Vec<String>) -> Result<()> {
-    let pak = Mutex::new(PakReader::new(open_pak_files(pak)?)?);
-    let indexs = pak.lock().unwrap().all_file_indexs();
+    let pak = open_pak(pak)?;
+    let indexs = pak.all_file_indexs();
     let counter = std::sync::atomic::AtomicU32::new(0);
 
     let mut paths: Vec<(String, Vec<I18nPakFileIndex>)> = vec![];
@@ -973,7 +1733,7 @@ fn search_path(pak: Vec<String>, dmp: Vec<String>) -> Result<()> {
                 for pos in (begin..end).step_by(2) {
                     path.push(char::from(memory[pos]));
                 }
-                let index = pak.lock().unwrap().find_file_i18n(&path)?;
+                let index = pak.find_file_i18n(&path)?;
                 paths.push((path, index));
             }
         }
@@ -1034,7 +1794,7 @@ fn search_path(pak: Vec<String>, dmp: Vec<String>) -> Result<()> {
         indexs
             .into_par_iter()
             .map(|index| {
-                let file = pak.lock().unwrap().read_file(index)?;
+                let file = pak.read_file(index)?;
                 search_memory(&file)
             })
             .flat_map_iter(|paths: Result<_>| paths.unwrap()),
@@ -1051,7 +1811,7 @@ fn search_path(pak: Vec<String>, dmp: Vec<String>) -> Result<()> {
 }
 
 fn dump_tree(pak: Vec<String>, list: String, output: String) -> Result<()> {
-    let mut pak = PakReader::new(open_pak_files(pak)?)?;
+    let pak = open_pak(pak)?;
     let list = File::open(list)?;
     let mut unvisited: std::collections::HashSet<_> = pak.all_file_indexs().into_iter().collect();
     for line in BufReader::new(list).lines() {
@@ -1079,7 +1839,7 @@ fn dump_tree(pak: Vec<String>, list: String, output: String) -> Result<()> {
                 }
 
                 std::fs::create_dir_all(path.parent().context("no parent")?)?;
-                std::fs::write(path, pak.read_file(index)?)?;
+                std::io::copy(&mut pak.open_file(index)?, &mut File::create(path)?)?;
                 unvisited.remove(&index);
             }
         }
@@ -1113,6 +1873,27 @@ fn dump_tree(pak: Vec<String>, list: String, output: String) -> Result<()> {
     Ok(())
 }
 
+fn pack(input: String, output: String, compression: String) -> Result<()> {
+    let compression = match compression.as_str() {
+        "none" => PakCompression::Stored,
+        "deflate" => PakCompression::Deflate,
+        "zstd" => PakCompression::Zstd,
+        _ => bail!("Unknown compression {compression}"),
+    };
+
+    let mut writer = PakWriter::new();
+    for (full_path, path) in walk_dir(Path::new(&input), "")? {
+        eprintln!("Adding {full_path}");
+        let data = std::fs::read(path)?;
+        writer.add_file(&full_path, &data, compression)?;
+    }
+
+    let mut output = std::io::BufWriter::new(File::create(output)?);
+    writer.write(&mut output)?;
+    output.flush()?;
+    Ok(())
+}
+
 fn dump_mesh(mesh: String, output: String) -> Result<()> {
     let mesh = Mesh::new(File::open(mesh)?)?;
     mesh.dump(output)?;
@@ -1210,7 +1991,7 @@ fn dump_meat(_mesh: String, _rcol: String, _output: String) -> Result<()> {
 }
 
 fn gen_meat(pak: Vec<String>, index: u32, output: impl Write) -> Result<()> {
-    let mut pak = PakReader::new(open_pak_files(pak)?)?;
+    let pak = open_pak(pak)?;
 
     let mesh_path = format!("enemy/em{index:03}/00/mod/em{index:03}_00.mesh");
     let rcol_path = format!("enemy/em{index:03}/00/collision/em{index:03}_00_colliders.rcol");
@@ -1231,7 +2012,7 @@ fn gen_meat(pak: Vec<String>, index: u32, output: impl Write) -> Result<()> {
 fn gen_resources(pak: Vec<String>, output: String) -> Result<()> {
     let mut logger_root = LoggerRoot::new();
     let logger = &mut logger_root.logger();
-    let mut pak = PakReader::new(open_pak_files(pak)?)?;
+    let mut pak = open_pak(pak)?;
 
     let sink = DiskSink::init(Path::new(&output))?;
     extract::gen_resources(&mut pak, &sink, logger)?;
@@ -1247,26 +2028,115 @@ fn hash(input: String, utf16: bool) {
     }
 }
 
-fn read_user(user: String, version_hint: Option<u32>) -> Result<()> {
-    let nodes = User::new(File::open(user)?)?
-        .rsz
-        .deserialize(version_hint)?;
-    for node in nodes {
-        println!("{}", node.to_json()?);
+fn read_user(
+    user: String,
+    version_hint: Option<u32>,
+    schema: Option<String>,
+    typed: bool,
+) -> Result<()> {
+    let schema = schema
+        .map(|schema| rsz::RszSchema::load(&schema))
+        .transpose()?;
+    let rsz = User::new(File::open(user)?)?.rsz;
+    if typed {
+        for node in rsz.deserialize_with_schema(version_hint, schema.as_ref())? {
+            println!("{}", node.to_json_typed()?);
+        }
+    } else {
+        for json in rsz.to_json_dag(version_hint, schema.as_ref())? {
+            println!("{json}");
+        }
     }
     Ok(())
 }
 
-fn dump_scn(scn: String) -> Result<()> {
+fn write_user(
+    json: String,
+    output: String,
+    base: Option<String>,
+    version_hint: Option<u32>,
+) -> Result<()> {
+    let base = base
+        .map(|base| User::new(File::open(base)?))
+        .transpose()
+        .context("Failed to open the base USER file")?;
+
+    let mut serializer = rsz::RszSerializer::new(version_hint);
+    if let Some(base) = &base {
+        serializer.add_hints(&base.rsz);
+    }
+    let json = BufReader::new(File::open(json)?);
+    let mut shared = HashMap::new();
+    for value in serde_json::Deserializer::from_reader(json).into_iter() {
+        let root = rsz::AnyRsz::from_json(value.context("Failed to parse JSON")?, &mut shared)?;
+        serializer.add_root_any(&root)?;
+    }
+    let rsz = serializer.finish();
+
+    let mut extern_slots: Vec<_> = rsz.extern_slots.iter().collect();
+    extern_slots.sort_by_key(|(&slot, _)| slot);
+    let children = extern_slots
+        .into_iter()
+        .map(|(_, e)| UserChild {
+            hash: e.hash,
+            name: e.path.clone(),
+        })
+        .collect();
+
+    let user = User {
+        resource_names: base.map_or_else(Vec::new, |base| base.resource_names),
+        children,
+        rsz,
+    };
+    user.write(std::io::BufWriter::new(File::create(output)?))
+}
+
+fn read_rsz_file(path: &str) -> Result<rsz::Rsz> {
+    let mut file = File::open(path).context(format!("Failed to open {path}"))?;
+    let mut magic = [0; 4];
+    file.read_exact(&mut magic)?;
+    file.rewind()?;
+    let rsz = if &magic[0..3] == b"USR" {
+        User::new(file)?.rsz
+    } else if &magic[0..3] == b"PFB" {
+        Pfb::new(file)?.rsz
+    } else if &magic[0..3] == b"SCN" {
+        Scn::new(file)?.rsz
+    } else {
+        bail!("{path} is not a USER, PFB or SCN file")
+    };
+    Ok(rsz)
+}
+
+fn diff_user(old: String, new: String, version_hint: Option<u32>) -> Result<()> {
+    let old = read_rsz_file(&old)?;
+    let new = read_rsz_file(&new)?;
+    let (diffs, diagnostics) = rsz::diff_rsz(&old, &new, version_hint)?;
+    for diagnostic in diagnostics {
+        println!("Decoding error, {diagnostic}");
+    }
+    for diff in diffs {
+        println!("{diff}");
+    }
+    Ok(())
+}
+
+fn dump_scn(scn: String, schema: Option<String>) -> Result<()> {
+    let schema = schema
+        .map(|schema| rsz::RszSchema::load(&schema))
+        .transpose()?;
     let scn = Scn::new(File::open(scn)?)?;
-    scn.dump();
+    scn.dump(schema.as_ref());
 
     Ok(())
 }
 
-fn dump_pfb(pfb: String) -> Result<()> {
+fn dump_pfb(pfb: String, schema: Option<String>) -> Result<()> {
+    let schema = schema
+        .map(|schema| rsz::RszSchema::load(&schema))
+        .transpose()?;
     let pfb = Pfb::new(File::open(pfb)?)?;
-    pfb.dump();
+    pfb.dump(schema.as_ref());
 
     Ok(())
 }
@@ -1352,15 +2222,15 @@ fn scene_print_scene(scene: &Scene, level: usize) {
 }
 
 fn scene(pak: Vec<String>, name: String) -> Result<()> {
-    let mut pak = PakReader::new(open_pak_files(pak)?)?;
-    let scene = Scene::new(&mut pak, &name)?;
+    let pak = open_pak(pak)?;
+    let scene = Scene::new(&pak, &name)?;
     scene_print_scene(&scene, 0);
     Ok(())
 }
 
 fn map(pak: Vec<String>, name: String, scale: String, tex: String, output: String) -> Result<()> {
-    let mut pak = PakReader::new(open_pak_files(pak)?)?;
-    let scene = Scene::new(&mut pak, &name)?;
+    let pak = open_pak(pak)?;
+    let scene = Scene::new(&pak, &name)?;
     let scale: rsz::GuiMapScaleDefineData = User::new(File::open(scale)?)?
         .rsz
         .deserialize_single(None)?;
@@ -1391,37 +2261,81 @@ fn map(pak: Vec<String>, name: String, scale: String, tex: String, output: Strin
 
 fn main() -> Result<()> {
     gpu::gpu_init();
+    if let Ok(versions) = std::env::var("MHRICE_RSZ_VERSIONS") {
+        rsz::load_versions(&versions)?;
+    }
     match Mhrice::parse() {
-        Mhrice::Dump { pak, name, output } => dump(pak, name, output),
+        Mhrice::Dump { pak, name, output } => dump(pak.pak, name, output),
         Mhrice::DumpIndex {
             pak,
             version,
             index,
             output,
-        } => dump_index(pak, version, index, output),
-        Mhrice::ScanRsz { pak, crc } => scan_rsz(pak, crc),
-        Mhrice::GenJson { pak, sha } => gen_json(pak, sha),
+        } => dump_index(pak.pak, version, index, output),
+        Mhrice::List { pak, list } => list_files(pak.pak, list),
+        Mhrice::DiffPak { old, new, list } => diff_pak(old, new, list),
+        Mhrice::Verify {
+            pak,
+            list,
+            manifest,
+        } => verify(pak.pak, list, manifest),
+        Mhrice::Extract {
+            pak,
+            list,
+            output,
+            filter,
+            ext,
+        } => extract(pak.pak, list, output, filter, ext),
+        Mhrice::ScanRsz {
+            pak,
+            crc,
+            list,
+            round_trip,
+            versions,
+            version,
+        } => scan_rsz(pak.pak, crc, list, round_trip, versions, version),
+        Mhrice::GenJson { pak, sha } => gen_json(pak.pak, sha),
         Mhrice::GenWebsite {
             pak,
             output,
             origin,
             sha,
-        } => gen_website(pak, output, origin, sha),
+        } => gen_website(pak.pak, output, origin, sha),
         Mhrice::ReadTdb { tdb, options } => read_tdb(tdb, options),
+        Mhrice::GenRsz {
+            tdb,
+            symbol,
+            version,
+        } => gen_rsz(tdb, symbol, version),
+        Mhrice::DiffTdb { old, new } => diff_tdb(old, new),
+        Mhrice::TdbQuery {
+            tdb,
+            name,
+            regex,
+            type_name,
+            hash,
+        } => tdb_query(tdb, name, regex, type_name, hash),
         Mhrice::ReadMsg { msg } => read_msg(msg),
-        Mhrice::ScanMsg { pak, output } => scan_msg(pak, output),
-        Mhrice::GrepMsg { pak, pattern } => grep_msg(pak, pattern),
+        Mhrice::WriteMsg { msg, json, output } => write_msg(msg, json, output),
+        Mhrice::ScanMsg { pak, output } => scan_msg(pak.pak, output),
+        Mhrice::GrepMsg { pak, pattern, list } => grep_msg(pak.pak, pattern, list),
         Mhrice::Grep {
             pak,
             utf16,
             pattern,
-        } => grep(pak, utf16, pattern),
-        Mhrice::SearchPath { pak, dmp } => search_path(pak, dmp),
-        Mhrice::DumpTree { pak, list, output } => dump_tree(pak, list, output),
-        Mhrice::ScanMesh { pak } => scan_mesh(pak),
-        Mhrice::ScanTex { pak } => scan_tex(pak),
-        Mhrice::ScanGui { pak } => scan_gui(pak),
-        Mhrice::ScanUvs { pak } => scan_uvs(pak),
+            list,
+        } => grep(pak.pak, utf16, pattern, list),
+        Mhrice::SearchPath { pak, dmp } => search_path(pak.pak, dmp),
+        Mhrice::DumpTree { pak, list, output } => dump_tree(pak.pak, list, output),
+        Mhrice::Pack {
+            input,
+            output,
+            compression,
+        } => pack(input, output, compression),
+        Mhrice::ScanMesh { pak, list } => scan_mesh(pak.pak, list),
+        Mhrice::ScanTex { pak, list } => scan_tex(pak.pak, list),
+        Mhrice::ScanGui { pak, list } => scan_gui(pak.pak, list),
+        Mhrice::ScanUvs { pak, list } => scan_uvs(pak.pak, list),
         Mhrice::DumpMesh { mesh, output } => dump_mesh(mesh, output),
         Mhrice::DumpMeshDae { mesh, output } => dump_mesh_dae(mesh, output),
         Mhrice::DumpRcol { rcol } => dump_rcol(rcol),
@@ -1433,29 +2347,47 @@ fn main() -> Result<()> {
         } => dump_tex(tex, output, swizzle),
         Mhrice::DumpGui { gui } => dump_gui(gui),
         Mhrice::GenMeat { pak, index, output } => {
-            gen_meat(pak, index, std::fs::File::create(output)?)
+            gen_meat(pak.pak, index, std::fs::File::create(output)?)
         }
-        Mhrice::GenResources { pak, output } => gen_resources(pak, output),
+        Mhrice::GenResources { pak, output } => gen_resources(pak.pak, output),
         Mhrice::Hash { input, utf16 } => {
             hash(input, utf16);
             Ok(())
         }
-        Mhrice::ReadUser { user, version } => read_user(user, version),
+        Mhrice::ReadUser {
+            user,
+            version,
+            schema,
+            typed,
+        } => read_user(user, version, schema, typed),
+        Mhrice::WriteUser {
+            json,
+            output,
+            base,
+            version,
+        } => write_user(json, output, base, version),
+        Mhrice::DiffUser { old, new, version } => diff_user(old, new, version),
         Mhrice::ReadDmpTdb {
             dmp,
             address,
             options,
         } => read_dmp_tdb(dmp, address, options),
-        Mhrice::DumpScn { scn } => dump_scn(scn),
-        Mhrice::DumpPfb { pfb } => dump_pfb(pfb),
-        Mhrice::Scene { pak, name } => scene(pak, name),
+        Mhrice::DumpScn { scn, schema } => dump_scn(scn, schema),
+        Mhrice::DumpPfb { pfb, schema } => dump_pfb(pfb, schema),
+        Mhrice::Scene { pak, name } => scene(pak.pak, name),
         Mhrice::TypeInfo { dmp, hash, crc } => type_info(dmp, hash, crc),
+        Mhrice::InspectObject {
+            dmp,
+            address,
+            type_name,
+            depth,
+        } => inspect_object(dmp, address, type_name, depth),
         Mhrice::Map {
             pak,
             name,
             scale,
             tex,
             output,
-        } => map(pak, name, scale, tex, output),
+        } => map(pak.pak, name, scale, tex, output),
     }
 }
@@ -1,19 +1,24 @@
+use crate::align::*;
 use crate::file_ext::*;
 use crate::hash::hash_as_utf16;
 use crate::rsz::Guid;
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use serde::*;
 use std::collections::HashMap;
 use std::convert::TryFrom;
-use std::io::{Read, Seek};
+use std::io::{Read, Seek, Write};
 
-#[derive(Debug, Serialize)]
+const KEY: [u8; 16] = [
+    0xCF, 0xCE, 0xFB, 0xF8, 0xEC, 0x0A, 0x33, 0x66, 0x93, 0xA9, 0x1D, 0x93, 0x50, 0x39, 0x5F, 0x09,
+];
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct MsgAttributeHeader {
     pub ty: i32,
     pub name: String,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum MsgAttribute {
     Int(i64),
     Float(f64),
@@ -21,19 +26,44 @@ pub enum MsgAttribute {
     Unknown(u64),
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct MsgEntry {
     pub name: String,
     pub guid: Guid,
+    pub unknown: u32,
     pub hash: u32,
     pub attributes: Vec<MsgAttribute>,
     pub content: Vec<String>,
 }
 
-#[derive(Debug, Serialize)]
+// A string pointer in the file, by attribute header or entry index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum StringSlot {
+    AttributeName(usize),
+    Name(usize),
+    Attribute(usize, usize),
+    Content(usize, usize),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Msg {
     pub attribute_headers: Vec<MsgAttributeHeader>,
     pub entries: Vec<MsgEntry>,
+
+    #[serde(skip)]
+    pub version: u32,
+    #[serde(skip)]
+    pub language_count: usize,
+
+    // The decrypted string pool of the source file, where each string is first found in it,
+    // and where each pointer pointed to. The same string can be found at several places,
+    // so unmodified strings are written back at the place of their own pointer.
+    #[serde(skip)]
+    string_pool: Vec<u8>,
+    #[serde(skip)]
+    string_offsets: HashMap<String, u64>,
+    #[serde(skip)]
+    string_pointers: HashMap<StringSlot, u64>,
 }
 
 impl Msg {
@@ -96,7 +126,7 @@ impl Msg {
                 file.seek_noop(entry)?;
                 let mut guid = [0; 16];
                 file.read_exact(&mut guid)?;
-                let _ = file.read_u32()?; //???
+                let unknown = file.read_u32()?;
                 let hash = file.read_u32()?;
 
                 let name = file.read_u64()?;
@@ -105,16 +135,23 @@ impl Msg {
                     .map(|_| file.read_u64())
                     .collect::<Result<Vec<_>>>()?;
 
-                Ok((name, Guid { bytes: guid }, hash, attributes, content))
+                Ok((
+                    name,
+                    Guid { bytes: guid },
+                    unknown,
+                    hash,
+                    attributes,
+                    content,
+                ))
             })
             .collect::<Result<Vec<_>>>()?
             .into_iter()
-            .map(|(name, guid, hash, attributes, content)| {
+            .map(|(name, guid, unknown, hash, attributes, content)| {
                 file.seek_noop(attributes)?;
                 let attributes = (0..attribute_count)
                     .map(|_| file.read_u64())
                     .collect::<Result<Vec<_>>>()?;
-                Ok((name, guid, hash, attributes, content))
+                Ok((name, guid, unknown, hash, attributes, content))
             })
             .collect::<Result<Vec<_>>>()?;
 
@@ -122,35 +159,45 @@ impl Msg {
         let mut data = vec![];
         file.read_to_end(&mut data)?;
 
-        let key = [
-            0xCF, 0xCE, 0xFB, 0xF8, 0xEC, 0x0A, 0x33, 0x66, 0x93, 0xA9, 0x1D, 0x93, 0x50, 0x39,
-            0x5F, 0x09,
-        ];
-
         let mut prev = 0;
         for (i, byte) in data.iter_mut().enumerate() {
             let cur = *byte;
-            *byte ^= prev ^ key[i & 0xF];
+            *byte ^= prev ^ KEY[i & 0xF];
             prev = cur;
         }
 
+        let mut string_offsets = HashMap::new();
+        let mut string_pointers = HashMap::new();
+        let mut read_string = |slot: StringSlot, offset: u64| -> Result<String> {
+            let offset = offset
+                .checked_sub(data_offset)
+                .context("String outside of the string pool")?;
+            let string = (&data[usize::try_from(offset)?..]).read_u16str()?;
+            string_offsets.entry(string.clone()).or_insert(offset);
+            string_pointers.insert(slot, offset);
+            Ok(string)
+        };
+
         let entries = entries
             .into_iter()
-            .map(|(name, guid, hash, attributes, content)| {
-                let name = (&data[usize::try_from(name - data_offset)?..]).read_u16str()?;
+            .enumerate()
+            .map(|(i, (name, guid, unknown, hash, attributes, content))| {
+                let name = read_string(StringSlot::Name(i), name)?;
                 if hash_as_utf16(&name) != hash {
                     bail!("Wrong hash")
                 }
                 let attributes = attributes
                     .into_iter()
                     .zip(&attribute_types)
-                    .map(|(attr, &ty)| {
+                    .enumerate()
+                    .map(|(j, (attr, &ty))| {
                         Ok(match ty {
                             0 => MsgAttribute::Int(attr as i64),
                             1 => MsgAttribute::Float(f64::from_bits(attr)),
-                            2 => MsgAttribute::String(
-                                (&data[usize::try_from(attr - data_offset)?..]).read_u16str()?,
-                            ),
+                            2 => MsgAttribute::String(read_string(
+                                StringSlot::Attribute(i, j),
+                                attr,
+                            )?),
                             -1 => MsgAttribute::Unknown(attr),
                             _ => bail!("Unknown attribute {ty}"),
                         })
@@ -158,11 +205,13 @@ impl Msg {
                     .collect::<Result<Vec<_>>>()?;
                 let content = content
                     .into_iter()
-                    .map(|o| (&data[usize::try_from(o - data_offset)?..]).read_u16str())
+                    .enumerate()
+                    .map(|(j, content)| read_string(StringSlot::Content(i, j), content))
                     .collect::<Result<Vec<_>>>()?;
                 Ok(MsgEntry {
                     name,
                     guid,
+                    unknown,
                     hash,
                     attributes,
                     content,
@@ -173,8 +222,9 @@ impl Msg {
         let attribute_headers = attribute_types
             .into_iter()
             .zip(attribute_names)
-            .map(|(ty, name)| {
-                let name = (&data[usize::try_from(name - data_offset)?..]).read_u16str()?;
+            .enumerate()
+            .map(|(i, (ty, name))| {
+                let name = read_string(StringSlot::AttributeName(i), name)?;
                 Ok(MsgAttributeHeader { ty, name })
             })
             .collect::<Result<Vec<_>>>()?;
@@ -182,9 +232,165 @@ impl Msg {
         Ok(Msg {
             attribute_headers,
             entries,
+            version,
+            language_count: usize::try_from(language_count)?,
+            string_pool: data,
+            string_offsets,
+            string_pointers,
         })
     }
 
+    pub fn write<F: Write + Seek>(&self, mut file: F) -> Result<()> {
+        let attribute_count = self.attribute_headers.len();
+        for entry in &self.entries {
+            if entry.content.len() != self.language_count {
+                bail!(
+                    "Entry {} has {} languages, expected {}",
+                    entry.name,
+                    entry.content.len(),
+                    self.language_count
+                )
+            }
+            if entry.attributes.len() != attribute_count {
+                bail!(
+                    "Entry {} has {} attributes, expected {attribute_count}",
+                    entry.name,
+                    entry.attributes.len()
+                )
+            }
+        }
+
+        let entry_count = u64::try_from(self.entries.len())?;
+        let language_count = u64::try_from(self.language_count)?;
+        let entry_list_offset = 0x48;
+        let p_offset = entry_list_offset + entry_count * 8;
+        let languages_offset = p_offset + 8;
+        let attribute_types_offset = align_up(languages_offset + language_count * 4, 8);
+        let attribute_names_offset = align_up(
+            attribute_types_offset + u64::try_from(attribute_count)? * 4,
+            8,
+        );
+        let entry_offset = attribute_names_offset + u64::try_from(attribute_count)? * 8;
+        let entry_len = 0x28 + language_count * 8;
+        let attributes_offset = entry_offset + entry_count * entry_len;
+        let attributes_len = u64::try_from(attribute_count)? * 8;
+        let data_offset = attributes_offset + entry_count * attributes_len;
+
+        // New strings are appended after the original ones
+        let mut data = self.string_pool.clone();
+        let mut string_offsets = self.string_offsets.clone();
+        let string_pool = &self.string_pool;
+        let string_pointers = &self.string_pointers;
+        let mut add_string = |slot: StringSlot, string: &str| -> Result<u64> {
+            if let Some(&offset) = string_pointers.get(&slot) {
+                if (&string_pool[usize::try_from(offset)?..]).read_u16str()? == string {
+                    return Ok(data_offset + offset);
+                }
+            }
+            if let Some(&offset) = string_offsets.get(string) {
+                return Ok(data_offset + offset);
+            }
+            let offset = u64::try_from(data.len())?;
+            data.write_u16str(string)?;
+            string_offsets.insert(string.to_owned(), offset);
+            Ok(data_offset + offset)
+        };
+
+        let attribute_names = self
+            .attribute_headers
+            .iter()
+            .enumerate()
+            .map(|(i, header)| add_string(StringSlot::AttributeName(i), &header.name))
+            .collect::<Result<Vec<_>>>()?;
+        let entries = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let name = add_string(StringSlot::Name(i), &entry.name)?;
+                let attributes = entry
+                    .attributes
+                    .iter()
+                    .enumerate()
+                    .map(|(j, attr)| {
+                        Ok(match attr {
+                            MsgAttribute::Int(v) => *v as u64,
+                            MsgAttribute::Float(v) => v.to_bits(),
+                            MsgAttribute::String(v) => add_string(StringSlot::Attribute(i, j), v)?,
+                            MsgAttribute::Unknown(v) => *v,
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let content = entry
+                    .content
+                    .iter()
+                    .enumerate()
+                    .map(|(j, content)| add_string(StringSlot::Content(i, j), content))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok((name, attributes, content))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut prev = 0;
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte ^= prev ^ KEY[i & 0xF];
+            prev = *byte;
+        }
+
+        file.write_u32(self.version)?;
+        file.write_magic(b"GMSG")?;
+        file.write_u64(0x10)?;
+        file.write_u32(u32::try_from(entry_count)?)?;
+        file.write_u32(u32::try_from(attribute_count)?)?;
+        file.write_u32(u32::try_from(language_count)?)?;
+        file.write_align_up(8)?;
+
+        file.write_u64(data_offset)?;
+        file.write_u64(p_offset)?;
+        file.write_u64(languages_offset)?;
+        file.write_u64(attribute_types_offset)?;
+        file.write_u64(attribute_names_offset)?;
+
+        for i in 0..entry_count {
+            file.write_u64(entry_offset + i * entry_len)?;
+        }
+
+        file.write_u64(0)?;
+        for i in 0..u32::try_from(language_count)? {
+            file.write_u32(i)?;
+        }
+
+        file.write_align_up(8)?;
+        for header in &self.attribute_headers {
+            file.write_i32(header.ty)?;
+        }
+
+        file.write_align_up(8)?;
+        for name in attribute_names {
+            file.write_u64(name)?;
+        }
+
+        for (i, (entry, (name, _, content))) in self.entries.iter().zip(&entries).enumerate() {
+            file.write_all(&entry.guid.bytes)?;
+            file.write_u32(entry.unknown)?;
+            file.write_u32(hash_as_utf16(&entry.name))?;
+            file.write_u64(*name)?;
+            file.write_u64(attributes_offset + u64::try_from(i)? * attributes_len)?;
+            for &content in content {
+                file.write_u64(content)?;
+            }
+        }
+
+        for (_, attributes, _) in &entries {
+            for &attr in attributes {
+                file.write_u64(attr)?;
+            }
+        }
+
+        file.write_all(&data)?;
+        Ok(())
+    }
+
     pub fn get_entry(&self, name: &str) -> Option<&MsgEntry> {
         self.entries.iter().find(|entry| entry.name == name)
     }
@@ -203,3 +409,122 @@ impl Msg {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn entry(name: &str, label: &str, content: &[&str]) -> MsgEntry {
+        MsgEntry {
+            name: name.to_owned(),
+            guid: Guid {
+                bytes: [name.len() as u8; 16],
+            },
+            unknown: 3,
+            hash: hash_as_utf16(name),
+            attributes: vec![
+                MsgAttribute::Int(-1),
+                MsgAttribute::String(label.to_owned()),
+            ],
+            content: content.iter().map(|&s| s.to_owned()).collect(),
+        }
+    }
+
+    // A source file repeating the same strings at different places in the string pool
+    fn source_file() -> Vec<u8> {
+        let strings = [
+            "Kind", "Label", "Name0", "", "", "Hello", "", "", "Name1", "", "Hi", "", "",
+        ];
+        let mut string_pool = vec![];
+        let mut offsets = vec![];
+        for string in strings {
+            offsets.push(u64::try_from(string_pool.len()).unwrap());
+            string_pool.write_u16str(string).unwrap();
+        }
+        let slots = [
+            StringSlot::AttributeName(0),
+            StringSlot::AttributeName(1),
+            StringSlot::Name(0),
+            StringSlot::Attribute(0, 1),
+            StringSlot::Content(0, 0),
+            StringSlot::Content(0, 1),
+            StringSlot::Content(0, 2),
+            StringSlot::Attribute(1, 1),
+            StringSlot::Name(1),
+            StringSlot::Content(1, 0),
+            StringSlot::Content(1, 1),
+            StringSlot::Content(1, 2),
+        ];
+        let string_pointers = slots.into_iter().zip(offsets).collect();
+
+        let msg = Msg {
+            attribute_headers: vec![
+                MsgAttributeHeader {
+                    ty: 0,
+                    name: "Kind".to_owned(),
+                },
+                MsgAttributeHeader {
+                    ty: 2,
+                    name: "Label".to_owned(),
+                },
+            ],
+            entries: vec![
+                entry("Name0", "", &["", "Hello", ""]),
+                entry("Name1", "", &["", "Hi", ""]),
+            ],
+            version: 539100710,
+            language_count: 3,
+            string_pool,
+            string_offsets: HashMap::new(),
+            string_pointers,
+        };
+        let mut file = Cursor::new(vec![]);
+        msg.write(&mut file).unwrap();
+        file.into_inner()
+    }
+
+    #[test]
+    fn round_trip() {
+        let source = source_file();
+        let msg = Msg::new(Cursor::new(&source)).unwrap();
+        assert_eq!(msg.entries[1].content, ["", "Hi", ""]);
+        assert_ne!(
+            msg.string_pointers[&StringSlot::Content(1, 0)],
+            msg.string_pointers[&StringSlot::Content(1, 2)]
+        );
+        assert_eq!(msg.entries[1].unknown, 3);
+
+        let mut file = Cursor::new(vec![]);
+        msg.write(&mut file).unwrap();
+        assert_eq!(file.into_inner(), source);
+    }
+
+    #[test]
+    fn write_edited() {
+        let mut msg = Msg::new(Cursor::new(source_file())).unwrap();
+        msg.entries[0].content[1] = "Edited".to_owned();
+        msg.entries.swap(0, 1);
+        let mut file = Cursor::new(vec![]);
+        msg.write(&mut file).unwrap();
+
+        let edited = Msg::new(Cursor::new(file.into_inner())).unwrap();
+        assert_eq!(edited.entries[0].name, "Name1");
+        assert_eq!(edited.entries[0].content, ["", "Hi", ""]);
+        assert_eq!(edited.entries[1].content, ["", "Edited", ""]);
+        // Only the edited string is appended
+        let len = |s: &str| (s.encode_utf16().count() + 1) * 2;
+        assert_eq!(
+            edited.string_pool.len(),
+            msg.string_pool.len() + len("Edited")
+        );
+    }
+
+    #[test]
+    fn unknown_is_required() {
+        let mut value = serde_json::to_value(entry("Name0", "", &[""])).unwrap();
+        assert!(serde_json::from_value::<MsgEntry>(value.clone()).is_ok());
+        value.as_object_mut().unwrap().remove("unknown");
+        assert!(serde_json::from_value::<MsgEntry>(value).is_err());
+    }
+}
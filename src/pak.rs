@@ -5,12 +5,15 @@ use crate::suffix::SUFFIX_MAP;
 use anyhow::{bail, Context, Result};
 use base64::prelude::*;
 use compress::flate;
+use memmap2::Mmap;
 use num_bigint::BigUint;
 use once_cell::sync::Lazy;
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::{TryFrom, TryInto};
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
 static PAK_MAIN_KEY_MOD: Lazy<Option<Vec<u8>>> = Lazy::new(|| None);
 
@@ -50,140 +53,300 @@ impl PakFileIndex {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct PakEntryInfo {
+    pub hash: u64,
+    pub len_compressed: u64,
+    pub len: u64,
+    pub format: u8,
+    pub encryption: u8,
+}
+
 #[derive(Debug)]
 struct PakEntry {
+    hash: u64,
     offset: u64,
     len_compressed: u64,
     len: u64,
     format: u8,
-    #[allow(dead_code)]
     flag: u8,
     encryption: u8,
 }
 
+impl PakEntry {
+    // Set on entries stored in chunks of the chunk table
+    const FLAG_CHUNKED: u8 = 1;
+}
+
+/// Chunk table of version 4.2. A chunked entry is split into chunks of `chunk_len` bytes,
+/// which are compressed separately and located by their offsets in the table.
+#[derive(Debug)]
+struct PakChunkTable {
+    chunk_len: u64,
+    offsets: Vec<u64>,
+}
+
+/// Backing storage of a PAK file
+pub enum PakSource<F> {
+    Mapped(Mmap),
+    Stream(F),
+}
+
+#[derive(Debug)]
+enum PakData<F> {
+    // Read in place without locking
+    Mapped(Mmap),
+    // Only locked while reading the raw data, so that entries can be decompressed in parallel
+    Stream(Mutex<F>),
+}
+
+impl<F: Read + Seek> PakData<F> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> std::io::Result<()> {
+        match self {
+            PakData::Mapped(map) => {
+                let data = usize::try_from(offset)
+                    .ok()
+                    .and_then(|start| map.get(start..start.checked_add(buf.len())?))
+                    .ok_or_else(|| {
+                        std::io::Error::new(
+                            std::io::ErrorKind::UnexpectedEof,
+                            "Read beyond the end of PAK file",
+                        )
+                    })?;
+                buf.copy_from_slice(data);
+            }
+            PakData::Stream(file) => {
+                let mut file = file.lock().unwrap();
+                file.seek(SeekFrom::Start(offset))?;
+                file.read_exact(buf)?;
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 struct PakFile<F> {
-    file: F,
+    data: PakData<F>,
     entries: Vec<PakEntry>,
+    chunk_table: Option<PakChunkTable>,
+}
+
+impl<F> PakFile<F> {
+    // The chunk table if the entry is stored in chunks
+    fn chunks_of(&self, entry: &PakEntry) -> Option<&PakChunkTable> {
+        self.chunk_table
+            .as_ref()
+            .filter(|_| entry.flag & PakEntry::FLAG_CHUNKED != 0)
+    }
 }
 
 #[derive(Debug)]
 pub struct PakReader<F> {
     files: Vec<PakFile<F>>,
     hash_map: HashMap<u64, PakFileIndex>,
+    // Loose files use the version number right after all PAK files
+    loose_files: Vec<(u64, PathBuf)>,
+    loose_hash_map: HashMap<u64, PakFileIndex>,
+    names: HashMap<PakFileIndex, String>,
 }
 
 impl<F: Read + Seek> PakReader<F> {
-    pub fn new(raw_files: Vec<F>) -> Result<PakReader<F>> {
+    pub fn new(sources: Vec<PakSource<F>>) -> Result<PakReader<F>> {
         let mut hash_map = HashMap::new();
-        let files = raw_files
+        let files = sources
             .into_iter()
             .enumerate()
-            .map(|(version, mut file)| {
-                let magic = file.read_magic()?;
-                if &magic != b"KPKA" {
-                    bail!("Wrong magic for PAK file");
-                }
-                let pak_version = file.read_u16()?;
-                if pak_version != 4 {
-                    bail!("Wrong version for PAK file");
-                }
-                let flag = file.read_u16()?;
-                if flag & 1 != 0 {
-                    bail!("Unimplemented flag 1")
-                }
-                let count = file.read_u32()?;
-                file.seek(SeekFrom::Current(4))?;
-
-                let mut entries_buffer = vec![0; count as usize * 0x30];
-                file.read_exact(&mut entries_buffer)?;
-
-                if flag & 8 != 0 {
-                    let key = if let Some(m) = &*PAK_MAIN_KEY_MOD {
-                        let mut encrypted_key = [0; 128];
-                        file.read_exact(&mut encrypted_key)?;
-
-                        let base = BigUint::from_bytes_le(&encrypted_key);
-                        let modulus = BigUint::from_bytes_le(m);
-                        let exponent = BigUint::from(0x10001u32);
-                        let power = base.modpow(&exponent, &modulus);
-                        let key_vec = power.to_bytes_le();
-                        if key_vec.len() > 32 {
-                            bail!("Key too long")
-                        }
-                        let mut key = [0; 32];
-                        key[0..key_vec.len()].copy_from_slice(&key_vec);
-                        key
-                    } else {
-                        eprintln!("PAK_MAIN_KEY_MOD not provided. Going to guess the key...");
-                        guess_key(&entries_buffer)?
-                    };
-                    decrypt_pak_entry_table(&mut entries_buffer, &key);
+            .map(|(version, source)| {
+                let (data, (entries, chunk_table)) = match source {
+                    PakSource::Mapped(map) => {
+                        let entries = Self::read_entries(
+                            std::io::Cursor::new(&map[..]),
+                            version,
+                            &mut hash_map,
+                        )?;
+                        (PakData::Mapped(map), entries)
+                    }
+                    PakSource::Stream(mut file) => {
+                        let entries = Self::read_entries(&mut file, version, &mut hash_map)?;
+                        (PakData::Stream(Mutex::new(file)), entries)
+                    }
+                };
+                Ok(PakFile {
+                    data,
+                    entries,
+                    chunk_table,
+                })
+            })
+            .collect::<Result<Vec<PakFile<F>>>>()?;
+
+        Ok(PakReader {
+            files,
+            hash_map,
+            loose_files: vec![],
+            loose_hash_map: HashMap::new(),
+            names: HashMap::new(),
+        })
+    }
+
+    fn read_entries(
+        mut file: impl Read + Seek,
+        version: usize,
+        hash_map: &mut HashMap<u64, PakFileIndex>,
+    ) -> Result<(Vec<PakEntry>, Option<PakChunkTable>)> {
+        let magic = file.read_magic()?;
+        if &magic != b"KPKA" {
+            bail!("Wrong magic for PAK file");
+        }
+        let major_version = file.read_u8()?;
+        let minor_version = file.read_u8()?;
+        let entry_len = match (major_version, minor_version) {
+            (2, 0) => 0x18,
+            (4, 0..=2) => 0x30,
+            _ => bail!("Unsupported version {major_version}.{minor_version} for PAK file"),
+        };
+        // Flag 1 doesn't change the layout of the header or the tables
+        let flag = file.read_u16()?;
+        let count = file.read_u32()?;
+        file.seek(SeekFrom::Current(4))?;
+        if flag & 0x10 != 0 {
+            // Extra header field in 4.1 and later
+            file.read_u32()?;
+        }
+
+        let mut entries_buffer = vec![0; count as usize * entry_len];
+        file.read_exact(&mut entries_buffer)?;
+
+        if flag & 8 != 0 {
+            let mut encrypted_key = [0; 128];
+            file.read_exact(&mut encrypted_key)?;
+            let key = if let Some(m) = &*PAK_MAIN_KEY_MOD {
+                let base = BigUint::from_bytes_le(&encrypted_key);
+                let modulus = BigUint::from_bytes_le(m);
+                let exponent = BigUint::from(0x10001u32);
+                let power = base.modpow(&exponent, &modulus);
+                let key_vec = power.to_bytes_le();
+                if key_vec.len() > 32 {
+                    bail!("Key too long")
                 }
+                let mut key = [0; 32];
+                key[0..key_vec.len()].copy_from_slice(&key_vec);
+                key
+            } else {
+                eprintln!("PAK_MAIN_KEY_MOD not provided. Going to guess the key...");
+                guess_key(&entries_buffer)?
+            };
+            decrypt_pak_entry_table(&mut entries_buffer, &key);
+        }
 
-                let entries: Vec<PakEntry> = entries_buffer
-                    .chunks(0x30)
-                    .enumerate()
-                    .map(|(index, mut entry)| {
-                        let hash = entry.read_u64()?;
-                        hash_map.insert(hash, PakFileIndex { version, index });
-                        let offset = entry.read_u64()?;
-                        let len_compressed = entry.read_u64()?;
-                        let len = entry.read_u64()?;
-                        let format = entry.read_u8()?;
-                        let flag = entry.read_u8()?;
-                        let encryption = entry.read_u8()?;
-                        Ok(PakEntry {
-                            offset,
-                            len_compressed,
-                            len,
-                            format,
-                            flag,
-                            encryption,
-                        })
-                    })
-                    .collect::<Result<Vec<PakEntry>>>()?;
-
-                Ok(PakFile { file, entries })
+        let chunk_table = if flag & 0x20 != 0 {
+            let chunk_len = u64::from(file.read_u32()?);
+            if chunk_len == 0 {
+                bail!("Zero chunk length")
+            }
+            let chunk_count = file.read_u32()?;
+            let offsets = (0..chunk_count)
+                .map(|_| file.read_u64())
+                .collect::<Result<_>>()?;
+            Some(PakChunkTable { chunk_len, offsets })
+        } else {
+            None
+        };
+
+        let entries: Vec<PakEntry> = entries_buffer
+            .chunks(entry_len)
+            .enumerate()
+            .map(|(index, mut entry)| {
+                let entry = if major_version == 2 {
+                    // Version 2 only has uncompressed entries
+                    let offset = entry.read_u64()?;
+                    let len = entry.read_u64()?;
+                    let hash = entry.read_u64()?;
+                    PakEntry {
+                        hash,
+                        offset,
+                        len_compressed: len,
+                        len,
+                        format: 0,
+                        flag: 0,
+                        encryption: 0,
+                    }
+                } else {
+                    let hash = entry.read_u64()?;
+                    let offset = entry.read_u64()?;
+                    let len_compressed = entry.read_u64()?;
+                    let len = entry.read_u64()?;
+                    let format = entry.read_u8()?;
+                    let flag = entry.read_u8()?;
+                    let encryption = entry.read_u8()?;
+                    PakEntry {
+                        hash,
+                        offset,
+                        len_compressed,
+                        len,
+                        format,
+                        flag,
+                        encryption,
+                    }
+                };
+                hash_map.insert(entry.hash, PakFileIndex { version, index });
+                Ok(entry)
             })
-            .collect::<Result<Vec<PakFile<F>>>>()?;
+            .collect::<Result<Vec<PakEntry>>>()?;
+
+        Ok((entries, chunk_table))
+    }
 
-        Ok(PakReader { files, hash_map })
+    /// Adds loose files under `root`/natives, which take priority over PAK files.
+    /// Roots added later take priority over earlier ones.
+    pub fn add_loose_root(&mut self, root: &Path) -> Result<()> {
+        let files = walk_dir(&root.join("natives"), "natives")?;
+
+        let version = self.files.len();
+        for (full_path, path) in files {
+            let hash = hash_full_path(&full_path);
+            let index = self.loose_files.len();
+            self.loose_files.push((hash, path));
+            self.loose_hash_map
+                .insert(hash, PakFileIndex { version, index });
+            self.names
+                .insert(PakFileIndex { version, index }, full_path);
+        }
+        Ok(())
     }
 
-    fn find_file_internal(&mut self, full_path: String) -> Option<PakFileIndex> {
-        let hash: u64 = u64::from(hash_as_utf16(&full_path.to_lowercase()))
-            | (u64::from(hash_as_utf16(&full_path.to_uppercase())) << 32);
-        self.hash_map.get(&hash).cloned()
+    fn find_file_internal(
+        hash_map: &HashMap<u64, PakFileIndex>,
+        full_path: String,
+    ) -> Option<PakFileIndex> {
+        hash_map.get(&hash_full_path(&full_path)).cloned()
     }
 
-    pub fn find_file_i18n(&mut self, mut path: &str) -> Result<Vec<I18nPakFileIndex>> {
-        if path.starts_with('@') {
-            path = &path[1..];
+    pub fn find_file_i18n(&self, path: &str) -> Result<Vec<I18nPakFileIndex>> {
+        // A loose file only overrides the PAK entry of its own language
+        let mut result = Self::find_file_i18n_internal(&self.hash_map, path)?;
+        for loose in Self::find_file_i18n_internal(&self.loose_hash_map, path)? {
+            if let Some(existing) = result.iter_mut().find(|i| i.language == loose.language) {
+                *existing = loose;
+            } else {
+                result.push(loose);
+            }
         }
-        let dot = path.rfind('.').context("Path missing extension")?;
-        let suffix = SUFFIX_MAP
-            .get(&path[dot + 1..])
-            .context("Unknown extension")?;
-        for suffix in suffix.iter().rev() {
-            let full_paths = [
-                format!("natives/NSW/{path}.{suffix}"),
-                format!("natives/NSW/{path}.{suffix}.NSW"),
-                format!("natives/STM/{path}.{suffix}"),
-                format!("natives/STM/{path}.{suffix}.x64"),
-                format!("natives/STM/{path}.{suffix}.STM"),
-                format!("natives/MSG/{path}.{suffix}"),
-                format!("natives/MSG/{path}.{suffix}.x64"),
-                format!("natives/MSG/{path}.{suffix}.MSG"),
-            ];
+        result.sort_by_key(|i| LANGUAGE_LIST.iter().position(|&l| l == i.language));
+        Ok(result)
+    }
 
+    fn find_file_i18n_internal(
+        hash_map: &HashMap<u64, PakFileIndex>,
+        path: &str,
+    ) -> Result<Vec<I18nPakFileIndex>> {
+        for full_paths in natives_paths(path)? {
             let mut result = vec![];
 
             for &language in LANGUAGE_LIST {
                 for full_path in &full_paths {
-                    let dot = if language.is_empty() { "" } else { "." };
-                    let with_language = format!("{full_path}{dot}{language}");
-                    if let Some(index) = self.find_file_internal(with_language) {
+                    let with_language = with_language(full_path, language);
+                    if let Some(index) = Self::find_file_internal(hash_map, with_language) {
                         result.push(I18nPakFileIndex { language, index });
                         break;
                     }
@@ -196,7 +359,7 @@ impl<F: Read + Seek> PakReader<F> {
         Ok(vec![])
     }
 
-    pub fn find_file(&mut self, path: &str) -> Result<PakFileIndex> {
+    pub fn find_file(&self, path: &str) -> Result<PakFileIndex> {
         Ok(self
             .find_file_i18n(path)?
             .first()
@@ -204,8 +367,16 @@ impl<F: Read + Seek> PakReader<F> {
             .index)
     }
 
-    pub fn read_file(&mut self, file_index: PakFileIndex) -> Result<Vec<u8>> {
-        let PakFile { file, entries } = &mut self.files[file_index.version];
+    pub fn read_file(&self, file_index: PakFileIndex) -> Result<Vec<u8>> {
+        if file_index.version == self.files.len() {
+            return Ok(std::fs::read(&self.loose_files[file_index.index].1)?);
+        }
+        let pak_file = &self.files[file_index.version];
+        let file = &pak_file.data;
+        let entry = &pak_file.entries[file_index.index];
+        if let Some(chunk_table) = pak_file.chunks_of(entry) {
+            return read_chunked(file, chunk_table, entry);
+        }
         let PakEntry {
             offset,
             len_compressed,
@@ -213,11 +384,10 @@ impl<F: Read + Seek> PakReader<F> {
             format,
             encryption,
             ..
-        } = entries[file_index.index];
+        } = *entry;
 
-        file.seek(SeekFrom::Start(offset))?;
         let mut data = vec![0; len_compressed.try_into()?];
-        file.read_exact(&mut data)?;
+        file.read_at(offset, &mut data)?;
 
         match encryption {
             0 => {}
@@ -229,72 +399,245 @@ impl<F: Read + Seek> PakReader<F> {
                     bail!("Unexpected size for decryption")
                 }
                 data = vec![0; plain_len];
-                let e = &*PAK_SUB_KEY_EXP;
-                let m = &*PAK_SUB_KEY_MOD;
-                let e = BigUint::from_bytes_le(e);
-                let m = BigUint::from_bytes_le(m);
+                let e = BigUint::from_bytes_le(&PAK_SUB_KEY_EXP);
+                let m = BigUint::from_bytes_le(&PAK_SUB_KEY_MOD);
                 for (plain, enc) in data.chunks_mut(8).zip(encrypted.chunks(0x80)) {
-                    let p = BigUint::from_bytes_le(&enc[0..0x40]);
-                    let q = BigUint::from_bytes_le(&enc[0x40..0x80]);
-                    let r = (q / (p.modpow(&e, &m))).to_bytes_le();
-                    if r.len() > plain.len() {
-                        bail!("Unexpected plain text")
-                    }
-                    plain[0..r.len()].copy_from_slice(&r);
+                    decrypt_block(enc, plain, &e, &m)?;
                 }
             }
             _ => bail!("Unsupported encryption {}", encryption),
         }
 
-        match format {
+        decompress(format, data, len)
+    }
+
+    /// Opens an entry for streaming read, so that large files don't need to be fully
+    /// loaded in memory. The reader is seekable only if the entry is not compressed
+    /// or encrypted.
+    pub fn open_file(&self, file_index: PakFileIndex) -> Result<PakEntryReader<'_, F>> {
+        if file_index.version == self.files.len() {
+            let path = &self.loose_files[file_index.index].1;
+            return Ok(PakEntryReader::Loose(std::fs::File::open(path)?));
+        }
+        let pak_file = &self.files[file_index.version];
+        let data = &pak_file.data;
+        let entry = &pak_file.entries[file_index.index];
+        if let Some(chunk_table) = pak_file.chunks_of(entry) {
+            // Chunked entries are decoded up front
+            let decoded = read_chunked(data, chunk_table, entry)?;
+            return Ok(PakEntryReader::Stream(Box::new(std::io::Cursor::new(
+                decoded,
+            ))));
+        }
+        let PakEntry {
+            offset,
+            len_compressed,
+            len,
+            format,
+            encryption,
+            ..
+        } = *entry;
+
+        let mut raw = PakRangeReader {
+            data,
+            start: offset,
+            len: len_compressed,
+            pos: 0,
+        };
+
+        let data: Box<dyn Read + '_> = match encryption {
             0 => {
-                if len != u64::try_from(data.len())? {
-                    bail!("Uncompressed file should have len == len_compressed")
+                if format == 0 {
+                    if len != len_compressed {
+                        bail!("Uncompressed file should have len == len_compressed")
+                    }
+                    return Ok(PakEntryReader::Stored(raw));
                 }
-                Ok(data)
+                Box::new(BufReader::new(raw))
             }
-            1 | 0x11 => {
-                let mut decompressed = Vec::new();
-                flate::Decoder::new(&data[..]).read_to_end(&mut decompressed)?;
-                if u64::try_from(decompressed.len()).unwrap() != len {
-                    bail!("Expected size {}, actual size {}", len, decompressed.len());
+            1 => {
+                let plain_len = raw.read_u64()?;
+                let encrypted_len = usize::try_from(len_compressed)?
+                    .checked_sub(8)
+                    .context("Encrypted entry too short")?;
+                if align_up(usize::try_from(plain_len)?, 8).checked_mul(0x10) != Some(encrypted_len)
+                {
+                    bail!("Unexpected size for decryption")
                 }
-                Ok(decompressed)
+                Box::new(PakDecryptReader {
+                    inner: BufReader::new(raw),
+                    remaining: plain_len,
+                    block: [0; 8],
+                    block_pos: 0,
+                    block_len: 0,
+                    e: BigUint::from_bytes_le(&PAK_SUB_KEY_EXP),
+                    m: BigUint::from_bytes_le(&PAK_SUB_KEY_MOD),
+                })
+            }
+            _ => bail!("Unsupported encryption {}", encryption),
+        };
+
+        let decoded: Box<dyn Read + '_> = match format {
+            0 => data,
+            1 | 0x11 => Box::new(flate::Decoder::new(data)),
+            2 => Box::new(zstd::Decoder::new(data)?),
+            _ => bail!("Unsupported format: {}", format),
+        };
+
+        Ok(PakEntryReader::Stream(Box::new(PakLenCheckReader {
+            inner: decoded,
+            len,
+            pos: 0,
+        })))
+    }
+
+    /// SHA-256 of the decompressed content of an entry
+    pub fn entry_sha256(&self, file_index: PakFileIndex) -> Result<String> {
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut self.open_file(file_index)?, &mut hasher)?;
+        let hash = hasher.finalize();
+        Ok(format!("{hash:x}"))
+    }
+
+    pub fn entry_info(&self, file_index: PakFileIndex) -> Result<PakEntryInfo> {
+        if file_index.version == self.files.len() {
+            let (hash, path) = &self.loose_files[file_index.index];
+            let len = std::fs::metadata(path)?.len();
+            return Ok(PakEntryInfo {
+                hash: *hash,
+                len_compressed: len,
+                len,
+                format: 0,
+                encryption: 0,
+            });
+        }
+        let entry = &self.files[file_index.version].entries[file_index.index];
+        Ok(PakEntryInfo {
+            hash: entry.hash,
+            len_compressed: entry.len_compressed,
+            len: entry.len,
+            format: entry.format,
+            encryption: entry.encryption,
+        })
+    }
+
+    /// Loads a list of known file paths to name the entries.
+    ///
+    /// Each line is either a full path (e.g. "natives/STM/...") or a path accepted by
+    /// `find_file_i18n`, optionally followed by " $ ...", such as the output of search-path.
+    /// Entries are named with their full paths, including the suffix version, the platform and
+    /// the language.
+    /// Returns the number of lines that matched any entry.
+    pub fn load_path_list(&mut self, list: impl BufRead) -> Result<usize> {
+        let mut matched = 0;
+        for line in list.lines() {
+            let line = line?;
+            let mut origin_path = line.split(" $ ").next().context("Empty line")?.trim();
+            if let Some(new_path) = origin_path.strip_prefix('@') {
+                origin_path = new_path;
+            }
+            if origin_path.is_empty() {
+                continue;
             }
-            2 => {
-                let decoded = zstd::decode_all(&data[..])?;
-                if u64::try_from(decoded.len()).unwrap() != len {
-                    bail!("Expected size {}, actual size {}", len, decoded.len());
+
+            if origin_path.starts_with("natives/") {
+                if self.name_full_path(origin_path) {
+                    matched += 1;
                 }
-                Ok(decoded)
+                continue;
+            }
+
+            let known_suffix = origin_path
+                .rfind('.')
+                .is_some_and(|dot| SUFFIX_MAP.contains_key(&origin_path[dot + 1..]));
+            if !known_suffix {
+                continue;
+            }
+
+            // Every variant is named with its full path, so that extracted files follow the
+            // layout of the natives folder
+            let streaming_path = "streaming/".to_owned() + origin_path;
+            let mut found = false;
+            for path in [origin_path, &streaming_path] {
+                for full_paths in natives_paths(path)? {
+                    for full_path in &full_paths {
+                        for &language in LANGUAGE_LIST {
+                            found |= self.name_full_path(&with_language(full_path, language));
+                        }
+                    }
+                }
+            }
+            if found {
+                matched += 1;
             }
-            _ => bail!("Unsupported format: {}", format),
         }
+        Ok(matched)
+    }
+
+    // Names the PAK entry and the loose file with this full path, if any
+    fn name_full_path(&mut self, full_path: &str) -> bool {
+        let hash = hash_full_path(full_path);
+        let mut found = false;
+        for hash_map in [&self.hash_map, &self.loose_hash_map] {
+            if let Some(&index) = hash_map.get(&hash) {
+                self.names.insert(index, full_path.to_owned());
+                found = true;
+            }
+        }
+        found
+    }
+
+    pub fn file_name(&self, file_index: PakFileIndex) -> Option<&str> {
+        self.names.get(&file_index).map(|s| s.as_str())
     }
 
-    pub fn read_file_at(&mut self, version: usize, index: usize) -> Result<Vec<u8>> {
+    /// The file name if known, or the index otherwise
+    pub fn display_name(&self, file_index: PakFileIndex) -> String {
+        self.file_name(file_index)
+            .map(|name| name.to_owned())
+            .unwrap_or_else(|| format!("{file_index:?}"))
+    }
+
+    pub fn file_index_at(&self, version: usize, index: usize) -> Result<PakFileIndex> {
         if version > self.files.len() {
             bail!("Version out of bound")
         }
-        if index >= self.files[version].entries.len() {
+        let len = if version == self.files.len() {
+            self.loose_files.len()
+        } else {
+            self.files[version].entries.len()
+        };
+        if index >= len {
             bail!("Index out of bound");
         }
-        self.read_file(PakFileIndex { version, index })
+        Ok(PakFileIndex { version, index })
     }
 
     pub fn all_file_indexs(&self) -> Vec<PakFileIndex> {
-        let mut v: Vec<_> = self.hash_map.values().cloned().collect();
+        let mut v: Vec<_> = self
+            .hash_map
+            .iter()
+            .filter(|(hash, _)| !self.loose_hash_map.contains_key(hash))
+            .chain(&self.loose_hash_map)
+            .map(|(_, index)| *index)
+            .collect();
         v.sort();
         v
     }
 
-    pub fn sha256(&mut self) -> Result<Vec<String>> {
+    pub fn sha256(&self) -> Result<Vec<String>> {
         self.files
-            .iter_mut()
+            .iter()
             .map(|file| {
                 let mut hasher = Sha256::new();
-                file.file.rewind()?;
-                std::io::copy(&mut file.file, &mut hasher)?;
+                match &file.data {
+                    PakData::Mapped(map) => hasher.update(&map[..]),
+                    PakData::Stream(file) => {
+                        let mut file = file.lock().unwrap();
+                        file.rewind()?;
+                        std::io::copy(&mut *file, &mut hasher)?;
+                    }
+                }
                 let hash = hasher.finalize();
                 Ok(format!("{hash:x}"))
             })
@@ -302,6 +645,371 @@ impl<F: Read + Seek> PakReader<F> {
     }
 }
 
+/// Streaming reader of a PAK entry, returned by `PakReader::open_file`
+fn decompress(format: u8, data: Vec<u8>, len: u64) -> Result<Vec<u8>> {
+    match format {
+        0 => {
+            if len != u64::try_from(data.len())? {
+                bail!("Uncompressed file should have len == len_compressed")
+            }
+            Ok(data)
+        }
+        1 | 0x11 => {
+            let mut decompressed = Vec::new();
+            flate::Decoder::new(&data[..]).read_to_end(&mut decompressed)?;
+            if u64::try_from(decompressed.len()).unwrap() != len {
+                bail!("Expected size {}, actual size {}", len, decompressed.len());
+            }
+            Ok(decompressed)
+        }
+        2 => {
+            let decoded = zstd::decode_all(&data[..])?;
+            if u64::try_from(decoded.len()).unwrap() != len {
+                bail!("Expected size {}, actual size {}", len, decoded.len());
+            }
+            Ok(decoded)
+        }
+        _ => bail!("Unsupported format: {}", format),
+    }
+}
+
+// The offset of a chunked entry is the index of its first chunk. Its chunks are consecutive,
+// and the last one ends at `len_compressed` bytes after the start of the first one.
+fn read_chunked<F: Read + Seek>(
+    file: &PakData<F>,
+    chunk_table: &PakChunkTable,
+    entry: &PakEntry,
+) -> Result<Vec<u8>> {
+    if entry.encryption != 0 {
+        bail!(
+            "Unsupported encryption {} for chunked entry",
+            entry.encryption
+        )
+    }
+    let first: usize = entry.offset.try_into()?;
+    let chunk_count: usize = entry.len.div_ceil(chunk_table.chunk_len).try_into()?;
+    let offsets = first
+        .checked_add(chunk_count)
+        .and_then(|end| chunk_table.offsets.get(first..end))
+        .context("Chunk out of bound")?;
+    let end = offsets
+        .first()
+        .and_then(|start| start.checked_add(entry.len_compressed));
+
+    let mut decoded = Vec::with_capacity(entry.len.try_into()?);
+    let mut remaining = entry.len;
+    for (i, &start) in offsets.iter().enumerate() {
+        let chunk_end = offsets.get(i + 1).copied().or(end).context("Bad chunk")?;
+        let chunk_len_compressed = chunk_end
+            .checked_sub(start)
+            .context("Chunk offsets out of order")?;
+        let mut data = vec![0; chunk_len_compressed.try_into()?];
+        file.read_at(start, &mut data)?;
+        let chunk_len = std::cmp::min(remaining, chunk_table.chunk_len);
+        decoded.extend(decompress(entry.format, data, chunk_len)?);
+        remaining -= chunk_len;
+    }
+    Ok(decoded)
+}
+
+pub enum PakEntryReader<'a, F> {
+    Stored(PakRangeReader<'a, F>),
+    Loose(std::fs::File),
+    Stream(Box<dyn Read + 'a>),
+}
+
+impl<'a, F: Read + Seek> Read for PakEntryReader<'a, F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            PakEntryReader::Stored(reader) => reader.read(buf),
+            PakEntryReader::Loose(reader) => reader.read(buf),
+            PakEntryReader::Stream(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl<'a, F: Read + Seek> Seek for PakEntryReader<'a, F> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            PakEntryReader::Stored(reader) => reader.seek(pos),
+            PakEntryReader::Loose(reader) => reader.seek(pos),
+            PakEntryReader::Stream(_) => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Cannot seek in compressed or encrypted PAK entry",
+            )),
+        }
+    }
+}
+
+/// Reads a range in a PAK file.
+/// The file is only locked during each read, so multiple readers can be used at the same time.
+pub struct PakRangeReader<'a, F> {
+    data: &'a PakData<F>,
+    start: u64,
+    len: u64,
+    pos: u64,
+}
+
+impl<'a, F: Read + Seek> Read for PakRangeReader<'a, F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.pos);
+        let len = std::cmp::min(u64::try_from(buf.len()).unwrap(), remaining) as usize;
+        if len == 0 {
+            return Ok(0);
+        }
+        self.data.read_at(self.start + self.pos, &mut buf[..len])?;
+        self.pos += u64::try_from(len).unwrap();
+        Ok(len)
+    }
+}
+
+impl<'a, F> Seek for PakRangeReader<'a, F> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        }
+        .ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "Seek before start")
+        })?;
+        self.pos = pos;
+        Ok(pos)
+    }
+}
+
+/// Fails the read if the decoded data doesn't match the expected length
+struct PakLenCheckReader<R> {
+    inner: R,
+    len: u64,
+    pos: u64,
+}
+
+impl<R: Read> Read for PakLenCheckReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.pos += u64::try_from(read).unwrap();
+        if self.pos > self.len || (read == 0 && !buf.is_empty() && self.pos != self.len) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Expected size {}, actual size {}", self.len, self.pos),
+            ));
+        }
+        Ok(read)
+    }
+}
+
+/// Decrypts type-1 encrypted entry block by block
+struct PakDecryptReader<R> {
+    inner: R,
+    remaining: u64,
+    block: [u8; 8],
+    block_pos: usize,
+    block_len: usize,
+    e: BigUint,
+    m: BigUint,
+}
+
+impl<R: Read> Read for PakDecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.block_pos == self.block_len {
+            if self.remaining == 0 {
+                return Ok(0);
+            }
+            let mut encrypted = [0; 0x80];
+            self.inner.read_exact(&mut encrypted)?;
+            self.block_len = std::cmp::min(self.remaining, 8) as usize;
+            self.block = [0; 8];
+            decrypt_block(
+                &encrypted,
+                &mut self.block[0..self.block_len],
+                &self.e,
+                &self.m,
+            )
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            self.remaining -= u64::try_from(self.block_len).unwrap();
+            self.block_pos = 0;
+        }
+        let len = std::cmp::min(buf.len(), self.block_len - self.block_pos);
+        buf[..len].copy_from_slice(&self.block[self.block_pos..][..len]);
+        self.block_pos += len;
+        Ok(len)
+    }
+}
+
+fn decrypt_block(encrypted: &[u8], plain: &mut [u8], e: &BigUint, m: &BigUint) -> Result<()> {
+    let p = BigUint::from_bytes_le(&encrypted[0..0x40]);
+    let q = BigUint::from_bytes_le(&encrypted[0x40..0x80]);
+    let r = (q / (p.modpow(e, m))).to_bytes_le();
+    if r.len() > plain.len() {
+        bail!("Unexpected plain text")
+    }
+    plain[0..r.len()].copy_from_slice(&r);
+    Ok(())
+}
+
+/// Full paths in the natives folder that a path may be stored as, per suffix version from the newest
+fn natives_paths(mut path: &str) -> Result<Vec<[String; 8]>> {
+    if path.starts_with('@') {
+        path = &path[1..];
+    }
+    let dot = path.rfind('.').context("Path missing extension")?;
+    let suffix = SUFFIX_MAP
+        .get(&path[dot + 1..])
+        .context("Unknown extension")?;
+    Ok(suffix
+        .iter()
+        .rev()
+        .map(|suffix| {
+            [
+                format!("natives/NSW/{path}.{suffix}"),
+                format!("natives/NSW/{path}.{suffix}.NSW"),
+                format!("natives/STM/{path}.{suffix}"),
+                format!("natives/STM/{path}.{suffix}.x64"),
+                format!("natives/STM/{path}.{suffix}.STM"),
+                format!("natives/MSG/{path}.{suffix}"),
+                format!("natives/MSG/{path}.{suffix}.x64"),
+                format!("natives/MSG/{path}.{suffix}.MSG"),
+            ]
+        })
+        .collect())
+}
+
+fn with_language(full_path: &str, language: &str) -> String {
+    if language.is_empty() {
+        full_path.to_owned()
+    } else {
+        format!("{full_path}.{language}")
+    }
+}
+
+/// Lists all files under `dir` recursively, sorted by their paths.
+/// The paths use `/` as the separator and start with `prefix`.
+pub fn walk_dir(dir: &Path, prefix: &str) -> Result<Vec<(String, PathBuf)>> {
+    fn visit(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) -> Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_str().context("Bad path")?;
+            let full_path = if prefix.is_empty() {
+                name.to_owned()
+            } else {
+                format!("{prefix}/{name}")
+            };
+            if entry.file_type()?.is_dir() {
+                visit(&entry.path(), &full_path, files)?;
+            } else {
+                files.push((full_path, entry.path()));
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = vec![];
+    visit(dir, prefix, &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+/// Hash of a full path (e.g. "natives/STM/...") as used for PAK entry lookup
+pub fn hash_full_path(full_path: &str) -> u64 {
+    u64::from(hash_as_utf16(&full_path.to_lowercase()))
+        | (u64::from(hash_as_utf16(&full_path.to_uppercase())) << 32)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PakCompression {
+    Stored,
+    Deflate,
+    Zstd,
+}
+
+impl PakCompression {
+    fn format(self) -> u8 {
+        match self {
+            PakCompression::Stored => 0,
+            PakCompression::Deflate => 1,
+            PakCompression::Zstd => 2,
+        }
+    }
+}
+
+struct PakWriterEntry {
+    len: u64,
+    format: u8,
+    data: Vec<u8>,
+}
+
+/// Builds a KPKA version 4 PAK file that can be read back by `PakReader`
+#[derive(Default)]
+pub struct PakWriter {
+    entries: BTreeMap<u64, PakWriterEntry>,
+}
+
+impl PakWriter {
+    pub fn new() -> PakWriter {
+        PakWriter::default()
+    }
+
+    pub fn add_file(
+        &mut self,
+        full_path: &str,
+        data: &[u8],
+        compression: PakCompression,
+    ) -> Result<()> {
+        let hash = hash_full_path(full_path);
+        if self.entries.contains_key(&hash) {
+            bail!("Duplicated file {full_path}")
+        }
+        let compressed = match compression {
+            PakCompression::Stored => data.to_vec(),
+            PakCompression::Deflate => {
+                let mut encoder =
+                    flate2::write::DeflateEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()?
+            }
+            PakCompression::Zstd => zstd::encode_all(data, 0)?,
+        };
+        self.entries.insert(
+            hash,
+            PakWriterEntry {
+                len: u64::try_from(data.len())?,
+                format: compression.format(),
+                data: compressed,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn write(&self, mut output: impl Write) -> Result<()> {
+        let count = u32::try_from(self.entries.len())?;
+        output.write_all(b"KPKA")?;
+        output.write_all(&4u16.to_le_bytes())?;
+        output.write_all(&0u16.to_le_bytes())?; // flag
+        output.write_all(&count.to_le_bytes())?;
+        output.write_all(&0u32.to_le_bytes())?;
+
+        let mut offset = 0x10 + u64::from(count) * 0x30;
+        for (hash, entry) in &self.entries {
+            let len_compressed = u64::try_from(entry.data.len())?;
+            output.write_all(&hash.to_le_bytes())?;
+            output.write_all(&offset.to_le_bytes())?;
+            output.write_all(&len_compressed.to_le_bytes())?;
+            output.write_all(&entry.len.to_le_bytes())?;
+            output.write_all(&[entry.format, 0, 0])?; // format, flag, encryption
+            output.write_all(&[0; 0xD])?;
+            offset += len_compressed;
+        }
+
+        for entry in self.entries.values() {
+            output.write_all(&entry.data)?;
+        }
+
+        Ok(())
+    }
+}
+
 fn guess_key(bytes: &[u8]) -> Result<[u8; 0x20]> {
     const P0: usize = 32;
     const P1: usize = 29;
@@ -411,3 +1119,297 @@ fn decrypt_pak_entry_table(bytes: &mut [u8], key: &[u8; 0x20]) {
         *b ^= (key[i % 32] as usize * key[i % 29] as usize + i) as u8
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const CONTENT: &[u8] = b"synthetic pak entry";
+    // Enough entries for guess_key to collect statistics on every key pair
+    const ENTRY_COUNT: u64 = 20000;
+
+    fn build_pak(major_version: u8, minor_version: u8, flag: u16) -> Vec<u8> {
+        let mut table = vec![];
+        let mut header_len = 16 + if flag & 0x10 != 0 { 4 } else { 0 };
+        let entry_len = if major_version == 2 { 0x18 } else { 0x30 };
+        header_len += ENTRY_COUNT * entry_len;
+        if flag & 8 != 0 {
+            header_len += 128;
+        }
+        // Chunk length and an empty chunk table
+        let chunk_table = [0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00];
+        if flag & 0x20 != 0 {
+            header_len += chunk_table.len() as u64;
+        }
+        let len = CONTENT.len() as u64;
+        for hash in 1..=ENTRY_COUNT {
+            let offset = header_len + (hash - 1) * len;
+            if major_version == 2 {
+                table.write_u64(offset).unwrap();
+                table.write_u64(len).unwrap();
+                table.write_u64(hash).unwrap();
+            } else {
+                table.write_u64(hash).unwrap();
+                table.write_u64(offset).unwrap();
+                table.write_u64(len).unwrap();
+                table.write_u64(len).unwrap();
+                table.resize(table.len() + 0x10, 0);
+            }
+        }
+        if flag & 8 != 0 {
+            let key = std::array::from_fn(|i| (i * 38 + 7) as u8);
+            decrypt_pak_entry_table(&mut table, &key);
+        }
+
+        let mut pak = vec![];
+        pak.write_magic(b"KPKA").unwrap();
+        pak.write_u8(major_version).unwrap();
+        pak.write_u8(minor_version).unwrap();
+        pak.write_u16(flag).unwrap();
+        pak.write_u32(ENTRY_COUNT as u32).unwrap();
+        pak.write_u32(0).unwrap();
+        if flag & 0x10 != 0 {
+            pak.write_u32(0xAAAAAAAA).unwrap();
+        }
+        pak.extend_from_slice(&table);
+        if flag & 8 != 0 {
+            pak.extend_from_slice(&[0xBB; 128]);
+        }
+        if flag & 0x20 != 0 {
+            pak.extend_from_slice(&chunk_table);
+        }
+        assert_eq!(pak.len() as u64, header_len);
+        for _ in 0..ENTRY_COUNT {
+            pak.extend_from_slice(CONTENT);
+        }
+        pak
+    }
+
+    fn check_pak(major_version: u8, minor_version: u8, flag: u16) {
+        let bytes = build_pak(major_version, minor_version, flag);
+        let mut map = memmap2::MmapMut::map_anon(bytes.len()).unwrap();
+        map.copy_from_slice(&bytes);
+        let pak = PakReader::new(vec![PakSource::Stream(Cursor::new(bytes))]).unwrap();
+        check_entries(&pak);
+        let pak = PakReader::<Cursor<Vec<u8>>>::new(vec![PakSource::Mapped(
+            map.make_read_only().unwrap(),
+        )])
+        .unwrap();
+        check_entries(&pak);
+    }
+
+    fn check_entries<F: Read + Seek>(pak: &PakReader<F>) {
+        assert_eq!(pak.all_file_indexs().len(), ENTRY_COUNT as usize);
+        for hash in [1, ENTRY_COUNT / 2, ENTRY_COUNT] {
+            let index = pak.hash_map[&hash];
+            assert_eq!(pak.read_file(index).unwrap(), CONTENT);
+            let mut streamed = vec![];
+            pak.open_file(index)
+                .unwrap()
+                .read_to_end(&mut streamed)
+                .unwrap();
+            assert_eq!(streamed, CONTENT);
+        }
+    }
+
+    #[test]
+    fn version_2_0() {
+        check_pak(2, 0, 0);
+        check_pak(2, 0, 8);
+    }
+
+    #[test]
+    fn version_4_0() {
+        check_pak(4, 0, 0);
+        check_pak(4, 0, 8);
+    }
+
+    #[test]
+    fn version_4_1() {
+        check_pak(4, 1, 0x10);
+        check_pak(4, 1, 0x10 | 8);
+    }
+
+    #[test]
+    fn version_4_2() {
+        check_pak(4, 2, 0x10 | 0x20);
+        check_pak(4, 2, 0x10 | 0x20 | 8);
+    }
+
+    #[test]
+    fn flag_1() {
+        check_pak(4, 0, 1);
+        check_pak(4, 2, 0x10 | 0x20 | 1);
+    }
+
+    #[test]
+    fn chunked_entries() {
+        const CHUNK_LEN: usize = 0x100;
+        let content: Vec<u8> = (0..CHUNK_LEN * 3 + 5).map(|i| (i / 7) as u8).collect();
+        let compressions = [
+            PakCompression::Stored,
+            PakCompression::Deflate,
+            PakCompression::Zstd,
+        ];
+
+        let mut chunks = vec![];
+        for compression in compressions {
+            let mut writer = PakWriter::new();
+            let compressed: Vec<Vec<u8>> = content
+                .chunks(CHUNK_LEN)
+                .map(|chunk| {
+                    writer.add_file("chunk", chunk, compression).unwrap();
+                    writer.entries.pop_first().unwrap().1.data
+                })
+                .collect();
+            chunks.push(compressed);
+        }
+
+        let chunk_count: usize = chunks.iter().map(|c| c.len()).sum();
+        let mut offset = (20 + compressions.len() * 0x30 + 8 + chunk_count * 8) as u64;
+        let mut pak = vec![];
+        pak.write_magic(b"KPKA").unwrap();
+        pak.write_u8(4).unwrap();
+        pak.write_u8(2).unwrap();
+        pak.write_u16(0x10 | 0x20).unwrap();
+        pak.write_u32(compressions.len() as u32).unwrap();
+        pak.write_u32(0).unwrap();
+        pak.write_u32(0).unwrap();
+        let mut first_chunk = 0;
+        for (hash, (compression, chunks)) in compressions.iter().zip(&chunks).enumerate() {
+            let len_compressed: usize = chunks.iter().map(|c| c.len()).sum();
+            pak.write_u64(hash as u64).unwrap();
+            pak.write_u64(first_chunk).unwrap();
+            pak.write_u64(len_compressed as u64).unwrap();
+            pak.write_u64(content.len() as u64).unwrap();
+            pak.write_u8(compression.format()).unwrap();
+            pak.write_u8(PakEntry::FLAG_CHUNKED).unwrap();
+            pak.extend_from_slice(&[0; 0xE]);
+            first_chunk += chunks.len() as u64;
+        }
+        pak.write_u32(CHUNK_LEN as u32).unwrap();
+        pak.write_u32(chunk_count as u32).unwrap();
+        for chunk in chunks.iter().flatten() {
+            pak.write_u64(offset).unwrap();
+            offset += chunk.len() as u64;
+        }
+        for chunk in chunks.iter().flatten() {
+            pak.extend_from_slice(chunk);
+        }
+
+        let pak = PakReader::new(vec![PakSource::Stream(Cursor::new(pak))]).unwrap();
+        for hash in 0..compressions.len() as u64 {
+            let index = pak.hash_map[&hash];
+            assert_eq!(pak.read_file(index).unwrap(), content);
+            let mut streamed = vec![];
+            pak.open_file(index)
+                .unwrap()
+                .read_to_end(&mut streamed)
+                .unwrap();
+            assert_eq!(streamed, content);
+        }
+    }
+
+    // 2.0: offset, length and hash
+    const FIXTURE_2_0: &[u8] = &[
+        0x4B, 0x50, 0x4B, 0x41, 0x02, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x28, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0xEF, 0xCD, 0xAB, 0x89, 0x67, 0x45, 0x23, 0x01, 0x66, 0x69, 0x78, 0x74, 0x75,
+        0x72, 0x65, 0x21,
+    ];
+
+    // 4.0 with flag 1
+    const FIXTURE_4_0: &[u8] = &[
+        0x4B, 0x50, 0x4B, 0x41, 0x04, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0xEF, 0xCD, 0xAB, 0x89, 0x67, 0x45, 0x23, 0x01, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x66, 0x69, 0x78, 0x74, 0x75, 0x72, 0x65, 0x21,
+    ];
+
+    // 4.1 with the extra header field
+    const FIXTURE_4_1: &[u8] = &[
+        0x4B, 0x50, 0x4B, 0x41, 0x04, 0x01, 0x10, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0xAA, 0xAA, 0xAA, 0xAA, 0xEF, 0xCD, 0xAB, 0x89, 0x67, 0x45, 0x23, 0x01, 0x44, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x66, 0x69, 0x78, 0x74, 0x75, 0x72, 0x65,
+        0x21,
+    ];
+
+    // 4.2 with an entry stored in two chunks of 4 bytes
+    const FIXTURE_4_2: &[u8] = &[
+        0x4B, 0x50, 0x4B, 0x41, 0x04, 0x02, 0x30, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0xAA, 0xAA, 0xAA, 0xAA, 0xEF, 0xCD, 0xAB, 0x89, 0x67, 0x45, 0x23, 0x01, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00,
+        0x00, 0x5C, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x60, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x66, 0x69, 0x78, 0x74, 0x75, 0x72, 0x65, 0x21,
+    ];
+
+    #[test]
+    fn fixtures() {
+        for fixture in [FIXTURE_2_0, FIXTURE_4_0, FIXTURE_4_1, FIXTURE_4_2] {
+            let pak = PakReader::new(vec![PakSource::Stream(Cursor::new(fixture))]).unwrap();
+            let index = pak.hash_map[&0x0123456789ABCDEF];
+            assert_eq!(pak.read_file(index).unwrap(), b"fixture!");
+            let mut streamed = vec![];
+            pak.open_file(index)
+                .unwrap()
+                .read_to_end(&mut streamed)
+                .unwrap();
+            assert_eq!(streamed, b"fixture!");
+        }
+    }
+
+    #[test]
+    fn writer_round_trip() {
+        let files = [
+            ("test/stored.user", PakCompression::Stored),
+            ("test/deflate.user", PakCompression::Deflate),
+            ("test/zstd.user", PakCompression::Zstd),
+        ];
+        let content = |path: &str| path.repeat(100).into_bytes();
+
+        let mut writer = PakWriter::new();
+        for (path, compression) in files {
+            let full_path = format!("natives/STM/{path}.2");
+            writer
+                .add_file(&full_path, &content(path), compression)
+                .unwrap();
+        }
+        assert!(writer
+            .add_file("natives/STM/test/zstd.user.2", b"", PakCompression::Stored)
+            .is_err());
+        let mut bytes = vec![];
+        writer.write(&mut bytes).unwrap();
+
+        let pak = PakReader::new(vec![PakSource::Stream(Cursor::new(bytes))]).unwrap();
+        assert_eq!(pak.all_file_indexs().len(), files.len());
+        for (path, compression) in files {
+            let index = pak.find_file(path).unwrap();
+            let info = pak.entry_info(index).unwrap();
+            assert_eq!(info.hash, hash_full_path(&format!("natives/STM/{path}.2")));
+            assert_eq!(info.format, compression.format());
+            assert_eq!(info.len, content(path).len() as u64);
+            if compression != PakCompression::Stored {
+                assert!(info.len_compressed < info.len);
+            }
+            assert_eq!(pak.read_file(index).unwrap(), content(path));
+            let mut streamed = vec![];
+            pak.open_file(index)
+                .unwrap()
+                .read_to_end(&mut streamed)
+                .unwrap();
+            assert_eq!(streamed, content(path));
+        }
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let pak = build_pak(3, 0, 0);
+        assert!(PakReader::new(vec![PakSource::Stream(Cursor::new(pak))]).is_err());
+    }
+}
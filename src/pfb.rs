@@ -1,10 +1,11 @@
+use crate::align::*;
 use crate::file_ext::*;
 use crate::rsz;
 use crate::rsz::Rsz;
 use crate::scn::scn_option;
 use crate::user::UserChild;
 use anyhow::{bail, Context, Result};
-use std::io::{Read, Seek};
+use std::io::{Read, Seek, Write};
 
 #[derive(Debug)]
 pub struct PfbGameObject {
@@ -162,7 +163,75 @@ impl Pfb {
         })
     }
 
-    pub fn dump(&self) {
+    pub fn write<F: Write + Seek>(&self, mut file: F) -> Result<()> {
+        let rathalos_offset = 0x38 + u64::try_from(self.game_objects.len())? * 0xC;
+        let resource_list_offset = align_up(
+            rathalos_offset + u64::try_from(self.ref_links.len())? * 0x10,
+            16,
+        );
+        let child_list_offset = align_up(
+            resource_list_offset + u64::try_from(self.resource_names.len())? * 8,
+            16,
+        );
+        let mut string_offset = child_list_offset + u64::try_from(self.children.len())? * 0x10;
+        let mut resource_name_offsets = vec![];
+        for name in &self.resource_names {
+            resource_name_offsets.push(string_offset);
+            string_offset += u64::try_from(name.encode_utf16().count() + 1)? * 2;
+        }
+        let mut child_name_offsets = vec![];
+        for child in &self.children {
+            child_name_offsets.push(string_offset);
+            string_offset += u64::try_from(child.name.encode_utf16().count() + 1)? * 2;
+        }
+        let rsz_offset = align_up(string_offset, 16);
+
+        file.write_magic(b"PFB\0")?;
+        file.write_u32(u32::try_from(self.game_objects.len())?)?;
+        file.write_u32(u32::try_from(self.resource_names.len())?)?;
+        file.write_u32(u32::try_from(self.ref_links.len())?)?;
+        file.write_u32(u32::try_from(self.children.len())?)?;
+        file.write_u32(0)?;
+        file.write_u64(rathalos_offset)?;
+        file.write_u64(resource_list_offset)?;
+        file.write_u64(child_list_offset)?;
+        file.write_u64(rsz_offset)?;
+
+        for game_object in &self.game_objects {
+            file.write_u32(game_object.object_index)?;
+            file.write_u32(game_object.parent_index.unwrap_or(0xFFFFFFFF))?;
+            file.write_u32(game_object.component_count)?;
+        }
+
+        for ref_link in &self.ref_links {
+            file.write_u32(ref_link.node_index)?;
+            file.write_u16(ref_link.member_index)?;
+            file.write_u16(ref_link.b)?;
+            file.write_u32(ref_link.array_index)?;
+            file.write_u32(ref_link.object_index)?;
+        }
+
+        file.write_align_up(16)?;
+        for offset in resource_name_offsets {
+            file.write_u64(offset)?;
+        }
+        file.write_align_up(16)?;
+        for (child, offset) in self.children.iter().zip(child_name_offsets) {
+            file.write_u32(child.hash)?;
+            file.write_u32(0)?;
+            file.write_u64(offset)?;
+        }
+        for name in &self.resource_names {
+            file.write_u16str(name)?;
+        }
+        for child in &self.children {
+            file.write_u16str(&child.name)?;
+        }
+        file.write_align_up(16)?;
+        self.rsz.write(file)
+    }
+
+    pub fn dump(&self, schema: Option<&rsz::RszSchema>) {
         println!("Game objects:");
         for game_object in &self.game_objects {
             println!(
@@ -202,11 +271,11 @@ impl Pfb {
             println!("{i:4} -> {root:4}")
         }
         println!();
-        match self.rsz.deserialize(None) {
-            Ok(objects) => {
-                for (i, o) in objects.into_iter().enumerate() {
+        match self.rsz.to_json_dag(None, schema) {
+            Ok(jsons) => {
+                for (i, json) in jsons.into_iter().enumerate() {
                     println!("== {i} ==");
-                    println!("{}", o.to_json().unwrap());
+                    println!("{json}");
                 }
             }
             Err(e) => {
@@ -216,7 +285,7 @@ impl Pfb {
                     let hash = type_descriptor.hash;
                     let symbol = rsz::RSZ_TYPE_MAP
                         .get(&hash)
-                        .map(|t| t.symbol)
+                        .map(|t| &*t.symbol)
                         .unwrap_or_default();
                     println!(
                         " [{}] - {:08X}, {:08X} - {}",
@@ -228,3 +297,105 @@ impl Pfb {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rsz::{RefTableData, RszSerializer};
+    use std::io::Cursor;
+
+    fn table(first: u16) -> RefTableData {
+        RefTableData {
+            vital_tbl_no: first,
+            attack_tbl_no: first + 1,
+            parts_tbl_no: first + 2,
+            core_tbl_no: first + 3,
+            other_tbl_no: first + 4,
+            multi_tbl_no: first + 5,
+        }
+    }
+
+    fn tables_rsz(firsts: &[u16]) -> Rsz {
+        let mut serializer = RszSerializer::new(None);
+        for &first in firsts {
+            serializer.add_root(&table(first)).unwrap();
+        }
+        serializer.finish()
+    }
+
+    // One game object with one component, one ref link, one resource and one child,
+    // laid out by hand
+    fn sample() -> Vec<u8> {
+        let mut file = Cursor::new(vec![]);
+        file.write_magic(b"PFB\0").unwrap();
+        for count in [1, 1, 1, 1, 0] {
+            file.write_u32(count).unwrap();
+        }
+        for offset in [0x44, 0x60, 0x70, 0xA0] {
+            file.write_u64(offset).unwrap();
+        }
+        for v in [0, 0xFFFFFFFF, 1] {
+            file.write_u32(v).unwrap();
+        }
+        file.write_u32(1).unwrap();
+        file.write_u16(2).unwrap();
+        file.write_u16(0).unwrap();
+        file.write_u32(0).unwrap();
+        file.write_u32(0).unwrap();
+        file.write_all(&[0; 0xC]).unwrap();
+        file.write_u64(0x80).unwrap();
+        file.write_u64(0).unwrap();
+        file.write_u32(0x12345678).unwrap();
+        file.write_u32(0).unwrap();
+        file.write_u64(0x8C).unwrap();
+        file.write_u16str("a.tex").unwrap();
+        file.write_u16str("b.user").unwrap();
+        file.write_all(&[0; 6]).unwrap();
+        assert_eq!(file.position(), 0xA0);
+        tables_rsz(&[0x10, 0x20]).write(&mut file).unwrap();
+        file.into_inner()
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = sample();
+        let pfb = Pfb::new(Cursor::new(&bytes)).unwrap();
+        assert_eq!(pfb.game_objects[0].component_count, 1);
+        assert_eq!(pfb.ref_links[0].member_index, 2);
+        assert_eq!(pfb.resource_names, ["a.tex"]);
+        assert_eq!(pfb.children[0].name, "b.user");
+        let mut written = Cursor::new(vec![]);
+        pfb.write(&mut written).unwrap();
+        assert_eq!(written.into_inner(), bytes);
+    }
+
+    #[test]
+    fn write_modified() {
+        let mut pfb = Pfb::new(Cursor::new(sample())).unwrap();
+        let mut serializer = RszSerializer::new(None);
+        serializer.add_hints(&pfb.rsz);
+        for root in pfb.rsz.deserialize(None).unwrap() {
+            let first = root.downcast_ref::<RefTableData>().unwrap().vital_tbl_no;
+            let value = RefTableData {
+                multi_tbl_no: 0x1234,
+                ..table(first)
+            };
+            serializer.add_root(&value).unwrap();
+        }
+        pfb.rsz = serializer.finish();
+
+        let mut written = Cursor::new(vec![]);
+        pfb.write(&mut written).unwrap();
+        let pfb = Pfb::new(Cursor::new(written.into_inner())).unwrap();
+        assert_eq!(pfb.game_objects[0].parent_index, None);
+        assert_eq!(pfb.ref_links[0].node_index, 1);
+        assert_eq!(pfb.children[0].hash, 0x12345678);
+        let roots = pfb.rsz.deserialize(None).unwrap();
+        let values: Vec<_> = roots
+            .iter()
+            .map(|root| root.downcast_ref::<RefTableData>().unwrap())
+            .map(|value| (value.vital_tbl_no, value.multi_tbl_no))
+            .collect();
+        assert_eq!(values, [(0x10, 0x1234), (0x20, 0x1234)]);
+    }
+}
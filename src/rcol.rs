@@ -8,7 +8,7 @@ use nalgebra_glm::*;
 use std::collections::HashSet;
 use std::convert::{TryFrom, TryInto};
 use std::io::{Cursor, Read, Seek, SeekFrom, Write};
-use std::rc::*;
+use std::sync::Arc;
 
 pub enum UserData {
     RszRootIndex(usize),
@@ -31,9 +31,9 @@ impl UserData {
         }
     }
 
-    pub fn downcast<T: 'static>(self) -> Option<T> {
+    pub fn downcast<T: 'static + Send + Sync>(self) -> Option<T> {
         if let UserData::Data(data) = self {
-            Rc::try_unwrap(data.downcast().ok()?).ok()
+            Arc::try_unwrap(data.downcast().ok()?).ok()
         } else {
             panic!();
         }
@@ -13,6 +13,37 @@ macro_rules! rsz_inner {
     }
 }
 
+#[macro_export]
+macro_rules! rsz_inner_write {
+    ($self:ident, $rsz:ident, $($field_name:ident : $field_type:ty,)*) => {{
+        $(
+            <$field_type>::field_to_rsz(&$self.$field_name, $rsz).context(stringify!($field_name))?;
+        )*
+        Ok(())
+    }}
+}
+
+#[macro_export]
+macro_rules! rsz_inner_sample {
+    ($sample:ident, $($field_name:ident : $field_type:ty,)*) => {
+        Self {
+            $(
+                $field_name: <$field_type>::field_sample($sample),
+            )*
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! rsz_inner_skip {
+    ($rsz:ident, $($field_name:ident : $field_type:ty,)*) => {{
+        $(
+            <$field_type>::field_skip($rsz).context(stringify!($field_name))?;
+        )*
+        Ok(())
+    }}
+}
+
 #[macro_export]
 macro_rules! rsz_inner_trait {
     (rsz($symbol:tt $(,path=$singleton:literal)? $(,$vhash:literal=$version:literal)*),
@@ -20,10 +51,27 @@ macro_rules! rsz_inner_trait {
         impl $crate::rsz::FromRsz for $struct_name {
             const SYMBOL: &'static str = $symbol;
             const VERSIONS: &'static [(u32, u32)] = &[$(($vhash, $version)),*];
+            const ALIGN: u64 = $crate::rsz::first_align(&[$(<$field_type>::FIELD_ALIGN),*]);
             #[allow(unused_variables)]
             fn from_rsz(rsz: &mut $crate::rsz::RszDeserializer) -> Result<Self> {
                 $crate::rsz_inner!(rsz, $($field_name : $field_type,)*)
             }
+            #[cfg(test)]
+            #[allow(unused_variables)]
+            fn sample(sample: $crate::rsz::RszSample) -> Self {
+                $crate::rsz_inner_sample!(sample, $($field_name : $field_type,)*)
+            }
+            #[allow(unused_variables)]
+            fn skip(rsz: &mut $crate::rsz::RszDeserializer) -> Result<()> {
+                $crate::rsz_inner_skip!(rsz, $($field_name : $field_type,)*)
+            }
+        }
+
+        impl $crate::rsz::ToRsz for $struct_name {
+            #[allow(unused_variables)]
+            fn to_rsz(&self, rsz: &mut $crate::rsz::RszSerializer) -> Result<()> {
+                $crate::rsz_inner_write!(self, rsz, $($field_name : $field_type,)*)
+            }
         }
 
         $(impl $crate::rsz::SingletonUser for $struct_name {
@@ -37,10 +85,27 @@ macro_rules! rsz_inner_trait {
 
     (rsz(), $struct_name:ident, $($field_name:ident : $field_type:ty,)*) => {
         impl $crate::rsz::FieldFromRsz for $struct_name {
+            const FIELD_ALIGN: u64 = $crate::rsz::first_align(&[$(<$field_type>::FIELD_ALIGN),*]);
             #[allow(unused_variables)]
             fn field_from_rsz(rsz: &mut $crate::rsz::RszDeserializer) -> Result<Self> {
                 $crate::rsz_inner!(rsz, $($field_name : $field_type,)*)
             }
+            #[cfg(test)]
+            #[allow(unused_variables)]
+            fn field_sample(sample: $crate::rsz::RszSample) -> Self {
+                $crate::rsz_inner_sample!(sample, $($field_name : $field_type,)*)
+            }
+            #[allow(unused_variables)]
+            fn field_skip(rsz: &mut $crate::rsz::RszDeserializer) -> Result<()> {
+                $crate::rsz_inner_skip!(rsz, $($field_name : $field_type,)*)
+            }
+        }
+
+        impl $crate::rsz::FieldToRsz for $struct_name {
+            #[allow(unused_variables)]
+            fn field_to_rsz(&self, rsz: &mut $crate::rsz::RszSerializer) -> Result<()> {
+                $crate::rsz_inner_write!(self, rsz, $($field_name : $field_type,)*)
+            }
         }
     }
 }
@@ -58,6 +123,7 @@ macro_rules! rsz_struct {
         }
     ) => {
         $(#[$outer_meta])*
+        #[derive(Deserialize)]
         $outer_vis struct $struct_name {
             $(
                 $(#[$inner_meta])* #[allow(dead_code)]
@@ -109,7 +175,7 @@ macro_rules! rsz_enum {
             $( $variant:ident $(($field:ty))? = $value:literal $(..= $end_value:literal)? ),*$(,)?
         }
     ) => {
-        $(#[$outer_meta])* #[allow(clippy::enum_variant_names)]
+        $(#[$outer_meta])* #[derive(Deserialize)] #[allow(clippy::enum_variant_names)]
         $outer_vis enum $enum_name {
             $( $variant $(($field))?, )*
         }
@@ -137,10 +203,30 @@ macro_rules! rsz_enum {
         }
 
         impl $crate::rsz::FieldFromRsz for $enum_name {
+            const FIELD_ALIGN: u64 = <$base>::FIELD_ALIGN;
             fn field_from_rsz(rsz: &mut $crate::rsz::RszDeserializer) -> Result<Self> {
                 let raw = <$base>::field_from_rsz(rsz)?;
                 Self::from_raw(raw)
             }
+            #[cfg(test)]
+            fn field_sample(_: $crate::rsz::RszSample) -> Self {
+                Self::from_raw([$($value),*][0]).unwrap()
+            }
+            fn field_skip(rsz: &mut $crate::rsz::RszDeserializer) -> Result<()> {
+                <$base>::field_skip(rsz)
+            }
+        }
+
+        impl $crate::rsz::FieldToRsz for $enum_name {
+            fn field_to_rsz(&self, rsz: &mut $crate::rsz::RszSerializer) -> Result<()> {
+                let raw: $base = match self {
+                    $(
+                        $crate::rsz_enum_arm_rev_left!(i,$enum_name, $variant, $value $(, $end_value)?)
+                        => $crate::rsz_enum_arm_rev_right!(i, $value $(, $end_value)?),
+                    )*
+                };
+                raw.field_to_rsz(rsz)
+            }
         }
     };
 }
@@ -153,7 +239,7 @@ macro_rules! rsz_sumtype {
             $( $variant:ident($variant_type:ty) ),*$(,)?
         }
     ) => {
-        $(#[$outer_meta])* #[allow(clippy::enum_variant_names)]
+        $(#[$outer_meta])* #[derive(Deserialize)] #[allow(clippy::enum_variant_names)]
         $outer_vis enum $enum_name {
             $( $variant($variant_type), )*
         }
@@ -169,6 +255,32 @@ macro_rules! rsz_sumtype {
                 )*
                 bail!("No matching type for sum type {}", stringify!($enum_name))
             }
+            #[cfg(test)]
+            fn field_sample(sample: $crate::rsz::RszSample) -> Self {
+                // The first variant is the one that is tried first when reading
+                let samples: &[fn($crate::rsz::RszSample) -> Self] = &[$(
+                    |sample| $enum_name::$variant(<$variant_type>::field_sample(sample))
+                ),*];
+                samples[0](sample)
+            }
+            fn field_skip(rsz: &mut $crate::rsz::RszDeserializer) -> Result<()> {
+                let current = rsz.cursor.stream_position()?;
+                $(
+                    if <$variant_type>::field_skip(rsz).is_ok() {
+                        return Ok(())
+                    }
+                    rsz.cursor.seek(SeekFrom::Start(current))?;
+                )*
+                bail!("No matching type for sum type {}", stringify!($enum_name))
+            }
+        }
+
+        impl $crate::rsz::FieldToRsz for $enum_name {
+            fn field_to_rsz(&self, rsz: &mut $crate::rsz::RszSerializer) -> Result<()> {
+                match self {
+                    $( $enum_name::$variant(v) => v.field_to_rsz(rsz), )*
+                }
+            }
         }
     }
 }
@@ -181,7 +293,7 @@ macro_rules! rsz_sumuser {
             $( $variant:ident($variant_type:ty) ),*$(,)?
         }
     ) => {
-        $(#[$outer_meta])* #[allow(clippy::enum_variant_names)]
+        $(#[$outer_meta])* #[derive(Deserialize)] #[allow(clippy::enum_variant_names)]
         $outer_vis enum $enum_name {
             $( $variant($variant_type), )*
         }
@@ -190,12 +302,18 @@ macro_rules! rsz_sumuser {
             fn from_any(any: AnyRsz) -> Result<Self> {
                 $(
                     if any.symbol() == <$variant_type>::SYMBOL {
-                        return Ok($enum_name::$variant(Rc::try_unwrap(any.downcast().unwrap()).map_err(|_| anyhow!("Shared user node"))?))
+                        return Ok($enum_name::$variant(Arc::try_unwrap(any.downcast().unwrap()).map_err(|_| anyhow!("Shared user node"))?))
                     }
                 )*
                 bail!("No matching type for {}", any.symbol())
             }
         }
+
+        impl $crate::rsz::ExternRoot for $enum_name {
+            fn root() -> Option<(&'static str, &'static [(u32, u32)])> {
+                None
+            }
+        }
     }
 }
 
@@ -209,19 +327,46 @@ macro_rules! rsz_bitflags {
     ) => {
         bitflags! {
             $(#[$outer_meta])*
-            #[derive(Serialize, Clone, Debug, Hash, PartialEq, Eq)]
-            #[serde(into = "Vec<&'static str>")]
+            #[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq)]
+            #[serde(into = "Vec<&'static str>", try_from = "Vec<String>")]
             pub struct $name : $base {
                 $( const $field_name = $field_value; )*
             }
         }
         impl $crate::rsz::FieldFromRsz for $name {
+            const FIELD_ALIGN: u64 = <$base>::FIELD_ALIGN;
             fn field_from_rsz(rsz: &mut $crate::rsz::RszDeserializer) -> Result<Self> {
                 let value = <$base>::field_from_rsz(rsz)?;
                 <$name>::from_bits(value).with_context(|| {
                     format!("Unknown bit flag {:08X} for {}", value, stringify!($name))
                 })
             }
+            #[cfg(test)]
+            fn field_sample(_: $crate::rsz::RszSample) -> Self {
+                <$name>::all()
+            }
+            fn field_skip(rsz: &mut $crate::rsz::RszDeserializer) -> Result<()> {
+                <$base>::field_skip(rsz)
+            }
+        }
+
+        impl $crate::rsz::FieldToRsz for $name {
+            fn field_to_rsz(&self, rsz: &mut $crate::rsz::RszSerializer) -> Result<()> {
+                self.bits().field_to_rsz(rsz)
+            }
+        }
+
+        impl TryFrom<Vec<String>> for $name {
+            type Error = anyhow::Error;
+            fn try_from(v: Vec<String>) -> Result<$name> {
+                let mut result = <$name>::empty();
+                for flag in v {
+                    result |= <$name>::from_name(&flag).with_context(|| {
+                        format!("Unknown bit flag {} for {}", flag, stringify!($name))
+                    })?;
+                }
+                Ok(result)
+            }
         }
 
         impl From<$name> for Vec<&'static str> {
@@ -244,13 +389,28 @@ macro_rules! rsz_newtype {
         $outer_vis:vis struct $name:ident($inner_vis:vis $base:ty);
     ) => (
         $(#[$outer_meta])*
+        #[derive(Deserialize)]
         $outer_vis struct $name($inner_vis $base);
 
         impl $crate::rsz::FieldFromRsz for $name {
+            const FIELD_ALIGN: u64 = <$base>::FIELD_ALIGN;
             fn field_from_rsz(rsz: &mut $crate::rsz::RszDeserializer) -> Result<Self> {
                 let raw = <$base>::field_from_rsz(rsz)?;
                 Ok($name(raw + $offset))
             }
+            #[cfg(test)]
+            fn field_sample(sample: $crate::rsz::RszSample) -> Self {
+                $name(<$base>::field_sample(sample) + $offset)
+            }
+            fn field_skip(rsz: &mut $crate::rsz::RszDeserializer) -> Result<()> {
+                <$base>::field_skip(rsz)
+            }
+        }
+
+        impl $crate::rsz::FieldToRsz for $name {
+            fn field_to_rsz(&self, rsz: &mut $crate::rsz::RszSerializer) -> Result<()> {
+                (self.0 - $offset).field_to_rsz(rsz)
+            }
         }
     )
 }
@@ -296,67 +456,219 @@ impl FieldFromRsz for bool {
             _ => bail!("Invalid bool"),
         }
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        true
+    }
+}
+
+impl FieldToRsz for bool {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.write_bool(*self)
+    }
 }
 
 impl FieldFromRsz for u8 {
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.read_u8()
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        0x12
+    }
+}
+
+impl FieldToRsz for u8 {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.write_u8(*self)
+    }
 }
 
 impl FieldFromRsz for u16 {
+    const FIELD_ALIGN: u64 = 2;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(2)?;
         rsz.read_u16()
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        0x1234
+    }
+}
+
+impl FieldToRsz for u16 {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.align(2);
+        rsz.write_u16(*self)
+    }
 }
 
 impl FieldFromRsz for u32 {
+    const FIELD_ALIGN: u64 = 4;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(4)?;
         rsz.read_u32()
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        0x12345678
+    }
+}
+
+impl FieldToRsz for u32 {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.align(4);
+        rsz.write_u32(*self)
+    }
 }
 
 impl FieldFromRsz for u64 {
+    const FIELD_ALIGN: u64 = 8;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(8)?;
         rsz.read_u64()
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        0x123456789ABCDEF0
+    }
+}
+
+impl FieldToRsz for u64 {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.align(8);
+        rsz.write_u64(*self)
+    }
 }
 
 impl FieldFromRsz for i8 {
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.read_i8()
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        -0x12
+    }
+}
+
+impl FieldToRsz for i8 {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.write_i8(*self)
+    }
 }
 
 impl FieldFromRsz for i16 {
+    const FIELD_ALIGN: u64 = 2;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(2)?;
         rsz.read_i16()
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        -0x1234
+    }
+}
+
+impl FieldToRsz for i16 {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.align(2);
+        rsz.write_i16(*self)
+    }
 }
 
 impl FieldFromRsz for i32 {
+    const FIELD_ALIGN: u64 = 4;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(4)?;
         rsz.read_i32()
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        -0x12345678
+    }
+}
+
+impl FieldToRsz for i32 {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.align(4);
+        rsz.write_i32(*self)
+    }
 }
 
 impl FieldFromRsz for i64 {
+    const FIELD_ALIGN: u64 = 8;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(8)?;
         rsz.read_i64()
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        -0x123456789ABCDEF0
+    }
+}
+
+impl FieldToRsz for i64 {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.align(8);
+        rsz.write_i64(*self)
+    }
 }
 
 impl FieldFromRsz for f32 {
+    const FIELD_ALIGN: u64 = 4;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(4)?;
         rsz.read_f32()
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        1.5
+    }
+}
+
+impl FieldToRsz for f32 {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.align(4);
+        rsz.write_f32(*self)
+    }
+}
+
+impl FieldFromRsz for f64 {
+    const FIELD_ALIGN: u64 = 8;
+
+    fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
+        rsz.cursor.seek_align_up(8)?;
+        rsz.read_f64()
+    }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        2.5
+    }
+}
+
+impl FieldToRsz for f64 {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.align(8);
+        rsz.write_f64(*self)
+    }
 }
 
 // A wrapper of f32 that has bit-equality semantics
@@ -364,10 +676,24 @@ impl FieldFromRsz for f32 {
 pub struct MeqF32(pub f32);
 
 impl FieldFromRsz for MeqF32 {
+    const FIELD_ALIGN: u64 = 4;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(4)?;
         Ok(MeqF32(rsz.read_f32()?))
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        MeqF32(1.5)
+    }
+}
+
+impl FieldToRsz for MeqF32 {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.align(4);
+        rsz.write_f32(self.0)
+    }
 }
 
 impl std::cmp::PartialEq for MeqF32 {
@@ -405,45 +731,162 @@ impl Serialize for MeqF32 {
     }
 }
 
-#[derive(Debug, Serialize, Clone)]
+impl<'de> Deserialize<'de> for MeqF32 {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        f32::deserialize(deserializer).map(MeqF32)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Aligner<const ALIGN: u64>;
 
 impl<const ALIGN: u64> FieldFromRsz for Aligner<ALIGN> {
+    const FIELD_ALIGN: u64 = ALIGN;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(ALIGN)?;
         Ok(Aligner)
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        Aligner
+    }
+}
+
+impl<const ALIGN: u64> FieldToRsz for Aligner<ALIGN> {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.align(ALIGN);
+        Ok(())
+    }
 }
 
 impl<T: FromRsz + 'static> FieldFromRsz for T {
+    const FIELD_ALIGN: u64 = 4;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(4)?;
         rsz.get_child()
     }
+
+    #[cfg(test)]
+    fn field_sample(sample: RszSample) -> Self {
+        T::sample(sample.child::<T>())
+    }
+
+    fn field_skip(rsz: &mut RszDeserializer) -> Result<()> {
+        rsz.cursor.seek_align_up(4)?;
+        rsz.read_u32()?;
+        Ok(())
+    }
+}
+
+impl<T: ToRsz + 'static> FieldToRsz for T {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        let index = rsz.add_child(self)?;
+        rsz.align(4);
+        rsz.write_u32(index)
+    }
 }
 
 impl<T: FromRsz + 'static> FieldFromRsz for Option<T> {
+    const FIELD_ALIGN: u64 = 4;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(4)?;
         rsz.get_child_opt()
     }
+
+    #[cfg(test)]
+    fn field_sample(sample: RszSample) -> Self {
+        sample.nested().then(|| T::sample(sample.child::<T>()))
+    }
+
+    fn field_skip(rsz: &mut RszDeserializer) -> Result<()> {
+        rsz.cursor.seek_align_up(4)?;
+        rsz.read_u32()?;
+        Ok(())
+    }
 }
 
-impl<T: FromRsz + 'static> FieldFromRsz for Rc<T> {
+impl<T: ToRsz + 'static> FieldToRsz for Option<T> {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        let index = match self {
+            Some(child) => rsz.add_child(child)?,
+            None => 0,
+        };
+        rsz.align(4);
+        rsz.write_u32(index)
+    }
+}
+
+impl<T: FromRsz + 'static> FieldFromRsz for Arc<T> {
+    const FIELD_ALIGN: u64 = 4;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(4)?;
-        rsz.get_child_rc()
+        rsz.get_child_arc()
+    }
+
+    #[cfg(test)]
+    fn field_sample(sample: RszSample) -> Self {
+        Arc::new(T::sample(sample.child::<T>()))
+    }
+
+    fn field_skip(rsz: &mut RszDeserializer) -> Result<()> {
+        rsz.cursor.seek_align_up(4)?;
+        rsz.read_u32()?;
+        Ok(())
+    }
+}
+
+impl<T: ToRsz + 'static> FieldToRsz for Arc<T> {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        let index = rsz.add_child_arc(self)?;
+        rsz.align(4);
+        rsz.write_u32(index)
     }
 }
 
-impl<T: FromRsz + 'static> FieldFromRsz for Option<Rc<T>> {
+impl<T: FromRsz + 'static> FieldFromRsz for Option<Arc<T>> {
+    const FIELD_ALIGN: u64 = 4;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(4)?;
-        rsz.get_child_rc_opt()
+        rsz.get_child_arc_opt()
+    }
+
+    #[cfg(test)]
+    fn field_sample(sample: RszSample) -> Self {
+        sample
+            .nested()
+            .then(|| Arc::new(T::sample(sample.child::<T>())))
+    }
+
+    fn field_skip(rsz: &mut RszDeserializer) -> Result<()> {
+        rsz.cursor.seek_align_up(4)?;
+        rsz.read_u32()?;
+        Ok(())
+    }
+}
+
+impl<T: ToRsz + 'static> FieldToRsz for Option<Arc<T>> {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        let index = match self {
+            Some(child) => rsz.add_child_arc(child)?,
+            None => 0,
+        };
+        rsz.align(4);
+        rsz.write_u32(index)
     }
 }
 
 impl<T: FieldFromRsz + 'static> FieldFromRsz for Vec<T> {
+    const FIELD_ALIGN: u64 = 4;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(4)?;
         let count = rsz.read_u32()?;
@@ -451,9 +894,46 @@ impl<T: FieldFromRsz + 'static> FieldFromRsz for Vec<T> {
             .map(|_| T::field_from_rsz(rsz))
             .collect::<Result<Vec<_>>>()
     }
+
+    #[cfg(test)]
+    fn field_sample(sample: RszSample) -> Self {
+        if sample.nested() {
+            vec![T::field_sample(sample)]
+        } else {
+            vec![]
+        }
+    }
+
+    fn field_skip(rsz: &mut RszDeserializer) -> Result<()> {
+        rsz.cursor.seek_align_up(4)?;
+        let count = rsz.read_u32()?;
+        for _ in 0..count {
+            T::field_skip(rsz)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: FieldToRsz + 'static> FieldToRsz for Vec<T> {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        self[..].field_to_rsz(rsz)
+    }
+}
+
+impl<T: FieldToRsz + 'static> FieldToRsz for [T] {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.align(4);
+        rsz.write_u32(u32::try_from(self.len())?)?;
+        for item in self {
+            item.field_to_rsz(rsz)?;
+        }
+        Ok(())
+    }
 }
 
 impl FieldFromRsz for Vec<()> {
+    const FIELD_ALIGN: u64 = 4;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(4)?;
         let count = rsz.read_u32()?;
@@ -462,23 +942,76 @@ impl FieldFromRsz for Vec<()> {
         }
         Ok(vec![])
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        vec![]
+    }
+}
+
+impl FieldToRsz for Vec<()> {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        if !self.is_empty() {
+            bail!("Placeholder array not empty")
+        }
+        rsz.align(4);
+        rsz.write_u32(0)
+    }
 }
 
 impl<T: FieldFromRsz + 'static, const N: usize> FieldFromRsz for [T; N] {
+    const FIELD_ALIGN: u64 = 4;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         Vec::<T>::field_from_rsz(rsz)?
             .try_into()
             .map_err(|v: Vec<T>| anyhow!("Expected array size {}, found {}", N, v.len()))
     }
+
+    #[cfg(test)]
+    fn field_sample(sample: RszSample) -> Self {
+        std::array::from_fn(|_| T::field_sample(sample))
+    }
+
+    fn field_skip(rsz: &mut RszDeserializer) -> Result<()> {
+        Vec::<T>::field_skip(rsz)
+    }
+}
+
+impl<T: FieldToRsz + 'static, const N: usize> FieldToRsz for [T; N] {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        self[..].field_to_rsz(rsz)
+    }
 }
 
 impl FieldFromRsz for String {
+    const FIELD_ALIGN: u64 = 4;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         Option::<String>::field_from_rsz(rsz)?.context("Null String")
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        "sample".to_owned()
+    }
+}
+
+impl FieldToRsz for String {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.align(4);
+        let utf16: Vec<u16> = self.encode_utf16().collect();
+        rsz.write_u32(u32::try_from(utf16.len() + 1)?)?;
+        for c in utf16 {
+            rsz.write_u16(c)?;
+        }
+        rsz.write_u16(0)
+    }
 }
 
 impl FieldFromRsz for Option<String> {
+    const FIELD_ALIGN: u64 = 4;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(4)?;
         let count = rsz.read_u32()?;
@@ -493,15 +1026,49 @@ impl FieldFromRsz for Option<String> {
         }
         Ok(Some(String::from_utf16(&utf16)?))
     }
+
+    #[cfg(test)]
+    fn field_sample(sample: RszSample) -> Self {
+        Some(String::field_sample(sample))
+    }
+}
+
+impl FieldToRsz for Option<String> {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        match self {
+            Some(s) => s.field_to_rsz(rsz),
+            None => {
+                rsz.align(4);
+                rsz.write_u32(0)
+            }
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Flatten<T>(pub T);
 
 impl<T: FromRsz> FieldFromRsz for Flatten<T> {
+    const FIELD_ALIGN: u64 = T::ALIGN;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         Ok(Flatten(T::from_rsz(rsz)?))
     }
+
+    #[cfg(test)]
+    fn field_sample(sample: RszSample) -> Self {
+        Flatten(T::sample(sample))
+    }
+
+    fn field_skip(rsz: &mut RszDeserializer) -> Result<()> {
+        T::skip(rsz)
+    }
+}
+
+impl<T: ToRsz> FieldToRsz for Flatten<T> {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        self.0.to_rsz(rsz)
+    }
 }
 
 impl<T> Deref for Flatten<T> {
@@ -512,7 +1079,7 @@ impl<T> Deref for Flatten<T> {
     }
 }
 
-#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[serde(transparent)]
 pub struct Versioned<T, const MIN: u32, const MAX: u32 = 0xFFFFFFFF>(pub Option<T>);
 
@@ -525,6 +1092,35 @@ impl<T: FieldFromRsz, const MIN: u32, const MAX: u32> FieldFromRsz for Versioned
             None
         }))
     }
+
+    #[cfg(test)]
+    fn field_sample(sample: RszSample) -> Self {
+        Versioned(
+            (MIN..=MAX)
+                .contains(&sample.version)
+                .then(|| T::field_sample(sample)),
+        )
+    }
+
+    fn field_skip(rsz: &mut RszDeserializer) -> Result<()> {
+        let version = rsz.version();
+        if version >= MIN && version <= MAX {
+            T::field_skip(rsz)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: FieldToRsz, const MIN: u32, const MAX: u32> FieldToRsz for Versioned<T, MIN, MAX> {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        let version = rsz.version();
+        match (&self.0, version >= MIN && version <= MAX) {
+            (Some(v), true) => v.field_to_rsz(rsz),
+            (None, false) => Ok(()),
+            (Some(_), false) => bail!("Unexpected versioned field for version {}", version),
+            (None, true) => bail!("Missing versioned field for version {}", version),
+        }
+    }
 }
 
 #[macro_export]
@@ -536,6 +1132,7 @@ macro_rules! rsz_versioned_choice {
         }
     ) => {
         $(#[$outer_meta])*
+        #[derive(Deserialize)]
         $outer_vis enum $enum_name {
             $( $variant($field),)*
         }
@@ -548,6 +1145,30 @@ macro_rules! rsz_versioned_choice {
                     _ => bail!("Unknown version for {}: {}", stringify!($enum_name), version)
                 })
             }
+            #[cfg(test)]
+            fn field_sample(sample: RszSample) -> Self {
+                match sample.version {
+                    $($version => $enum_name::$variant(<$field>::field_sample(sample)),)*
+                    version => panic!("No variant of {} for version {}", stringify!($enum_name), version)
+                }
+            }
+            fn field_skip(rsz: &mut RszDeserializer) -> Result<()> {
+                let version = rsz.version();
+                match version {
+                    $($version => <$field>::field_skip(rsz),)*
+                    _ => bail!("Unknown version for {}: {}", stringify!($enum_name), version)
+                }
+            }
+        }
+
+        impl FieldToRsz for $enum_name {
+            fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+                let version = rsz.version();
+                match self {
+                    $($enum_name::$variant(v) if matches!(version, $version) => v.field_to_rsz(rsz),)*
+                    _ => bail!("Mismatched version for {}: {}", stringify!($enum_name), version)
+                }
+            }
         }
     }
 }
@@ -567,19 +1188,46 @@ where
     arr[..].serialize(s)
 }
 
-#[derive(Debug, Serialize, Clone, Copy, Hash, PartialEq, Eq)]
-#[serde(into = "String")]
+pub fn de_arr<'de, D, T: Deserialize<'de>, const N: usize>(d: D) -> Result<[T; N], D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let v = Vec::<T>::deserialize(d)?;
+    let len = v.len();
+    v.try_into()
+        .map_err(|_| de::Error::invalid_length(len, &format!("an array of {N}").as_str()))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Hash, PartialEq, Eq)]
+#[serde(into = "String", try_from = "String")]
 pub struct Guid {
     pub bytes: [u8; 16],
 }
 
 impl FieldFromRsz for Guid {
+    const FIELD_ALIGN: u64 = 8;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         let mut bytes = [0; 16];
         rsz.cursor.seek_align_up(8)?;
         rsz.read_exact(&mut bytes)?;
         Ok(Guid { bytes })
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        Guid {
+            bytes: std::array::from_fn(|i| i as u8),
+        }
+    }
+}
+
+impl FieldToRsz for Guid {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.align(8);
+        rsz.write_all(&self.bytes)?;
+        Ok(())
+    }
 }
 
 impl From<Guid> for String {
@@ -606,32 +1254,98 @@ impl From<Guid> for String {
     }
 }
 
+impl TryFrom<String> for Guid {
+    type Error = anyhow::Error;
+    fn try_from(s: String) -> Result<Guid> {
+        let hex: String = s.chars().filter(|&c| c != '-').collect();
+        if hex.len() != 32 {
+            bail!("Invalid GUID {}", s)
+        }
+        let mut text_bytes = [0; 16];
+        for (i, byte) in text_bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+                .with_context(|| format!("Invalid GUID {}", s))?;
+        }
+        let mut bytes = text_bytes;
+        bytes[0..4].reverse();
+        bytes[4..6].reverse();
+        bytes[6..8].reverse();
+        Ok(Guid { bytes })
+    }
+}
+
 impl FieldFromRsz for Quat {
+    const FIELD_ALIGN: u64 = 16;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(16)?;
         let v = rsz.read_f32vec4()?;
         Ok(Quat::from(v))
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        Quat::new(1.0, 2.0, 3.0, 4.0)
+    }
+}
+
+impl FieldToRsz for Quat {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.align(16);
+        rsz.write_f32vec4(&self.coords)
+    }
 }
 
 impl FieldFromRsz for Vec4 {
+    const FIELD_ALIGN: u64 = 16;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(16)?;
         let v = rsz.read_f32vec4()?;
         Ok(v)
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        vec4(1.0, 2.0, 3.0, 4.0)
+    }
+}
+
+impl FieldToRsz for Vec4 {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.align(16);
+        rsz.write_f32vec4(self)
+    }
 }
 
 impl FieldFromRsz for Vec3 {
+    const FIELD_ALIGN: u64 = 16;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(16)?;
         let v = rsz.read_f32vec3()?;
         rsz.cursor.seek_align_up(16)?;
         Ok(v)
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        vec3(1.0, 2.0, 3.0)
+    }
+}
+
+impl FieldToRsz for Vec3 {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.align(16);
+        rsz.write_f32vec3(self)?;
+        rsz.align(16);
+        Ok(())
+    }
 }
 
 impl FieldFromRsz for IVec3 {
+    const FIELD_ALIGN: u64 = 4;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(4)?;
         let x = rsz.read_i32()?;
@@ -639,21 +1353,65 @@ impl FieldFromRsz for IVec3 {
         let z = rsz.read_i32()?;
         Ok(vec3(x, y, z))
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        vec3(1, 2, 3)
+    }
+}
+
+impl FieldToRsz for IVec3 {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.align(4);
+        rsz.write_i32(self.x)?;
+        rsz.write_i32(self.y)?;
+        rsz.write_i32(self.z)
+    }
 }
 
 impl FieldFromRsz for Vec2 {
+    const FIELD_ALIGN: u64 = 16;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(16)?;
         let v = rsz.read_f32vec2()?;
         rsz.cursor.seek_align_up(16)?;
         Ok(v)
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        vec2(1.0, 2.0)
+    }
+}
+
+impl FieldToRsz for Vec2 {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.align(16);
+        rsz.write_f32vec2(self)?;
+        rsz.align(16);
+        Ok(())
+    }
 }
 
 impl FieldFromRsz for Mat4x4 {
+    const FIELD_ALIGN: u64 = 16;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(16)?;
         let v = rsz.read_f32m4x4()?;
         Ok(v)
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        Mat4x4::from_fn(|row, column| (row * 4 + column) as f32)
+    }
+}
+
+impl FieldToRsz for Mat4x4 {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        rsz.align(16);
+        rsz.write_f32m4x4(self)
+    }
 }
@@ -0,0 +1,197 @@
+use super::*;
+use serde_json::Value;
+
+// A difference between two versions of the same RSZ data.
+// The path addresses the field, e.g. `param[12].atk_val`. A missing value means the field
+// or element only exists on the other side.
+#[derive(Debug)]
+pub struct RszDiff {
+    pub path: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl std::fmt::Display for RszDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.path,
+            self.old.as_deref().unwrap_or("(none)"),
+            self.new.as_deref().unwrap_or("(none)")
+        )
+    }
+}
+
+fn field_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+fn push_diff(diffs: &mut Vec<RszDiff>, path: &str, old: Option<&Value>, new: Option<&Value>) {
+    diffs.push(RszDiff {
+        path: path.to_owned(),
+        old: old.map(|v| v.to_string()),
+        new: new.map(|v| v.to_string()),
+    })
+}
+
+fn diff_value(diffs: &mut Vec<RszDiff>, path: &str, old: &Value, new: &Value) {
+    match (old, new) {
+        (Value::Object(old_map), Value::Object(new_map)) => {
+            for (key, old_value) in old_map {
+                let path = field_path(path, key);
+                match new_map.get(key) {
+                    Some(new_value) => diff_value(diffs, &path, old_value, new_value),
+                    None => push_diff(diffs, &path, Some(old_value), None),
+                }
+            }
+            for (key, new_value) in new_map {
+                if !old_map.contains_key(key) {
+                    let path = field_path(path, key);
+                    push_diff(diffs, &path, None, Some(new_value));
+                }
+            }
+        }
+        (Value::Array(old_list), Value::Array(new_list)) => {
+            for i in 0..old_list.len().max(new_list.len()) {
+                let path = format!("{path}[{i}]");
+                match (old_list.get(i), new_list.get(i)) {
+                    (Some(old_value), Some(new_value)) => {
+                        diff_value(diffs, &path, old_value, new_value)
+                    }
+                    (old_value, new_value) => push_diff(diffs, &path, old_value, new_value),
+                }
+            }
+        }
+        (old, new) => {
+            if old != new {
+                push_diff(diffs, path, Some(old), Some(new))
+            }
+        }
+    }
+}
+
+// Reports changed byte ranges of instances that couldn't be decoded
+fn diff_raw(diffs: &mut Vec<RszDiff>, path: &str, old: &RawRsz, new: &RawRsz) {
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02X}")).collect()
+    }
+
+    if old.hash != new.hash || old.crc != new.crc {
+        diffs.push(RszDiff {
+            path: field_path(path, "type"),
+            old: Some(format!("{:08X}, {:08X}", old.hash, old.crc)),
+            new: Some(format!("{:08X}, {:08X}", new.hash, new.crc)),
+        })
+    }
+
+    let len = old.data.len().max(new.data.len());
+    let mut i = 0;
+    while i < len {
+        if old.data.get(i) == new.data.get(i) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < len && old.data.get(i) != new.data.get(i) {
+            i += 1;
+        }
+        let range =
+            |data: &[u8]| (start < data.len()).then(|| hex(&data[start..i.min(data.len())]));
+        diffs.push(RszDiff {
+            path: field_path(path, &format!("data[{start:#X}..{i:#X}]")),
+            old: range(&old.data),
+            new: range(&new.data),
+        })
+    }
+}
+
+/// Compares two versions of the same RSZ data field by field.
+/// Instances that fail to decode, such as unregistered types, are compared as raw bytes.
+/// Also returns the decoding diagnostics of both sides, as the comparison is incomplete
+/// when there are any.
+pub fn diff_rsz(
+    old: &Rsz,
+    new: &Rsz,
+    version_hint: Option<u32>,
+) -> Result<(Vec<RszDiff>, Vec<String>)> {
+    let (old_roots, old_diagnostics) = old.deserialize_lenient(version_hint)?;
+    let (new_roots, new_diagnostics) = new.deserialize_lenient(version_hint)?;
+    let diagnostics = old_diagnostics
+        .iter()
+        .map(|diagnostic| format!("old: {diagnostic}"))
+        .chain(
+            new_diagnostics
+                .iter()
+                .map(|diagnostic| format!("new: {diagnostic}")),
+        )
+        .collect();
+
+    // Roots are left without data if decoding couldn't resume after a failed instance.
+    // Compare the whole data blocks instead, so that changes aren't missed.
+    let skipped = |roots: &[AnyRsz]| {
+        roots.iter().any(|root| {
+            root.downcast_ref::<RawRsz>()
+                .is_some_and(|raw| raw.data.is_empty())
+        })
+    };
+    if skipped(&old_roots) || skipped(&new_roots) {
+        let block = |rsz: &Rsz| RawRsz {
+            hash: 0,
+            crc: 0,
+            data: rsz.data.clone(),
+        };
+        let mut diffs = vec![];
+        diff_raw(&mut diffs, "", &block(old), &block(new));
+        return Ok((diffs, diagnostics));
+    }
+
+    // Single-rooted data, like most USER files, is addressed from its fields directly
+    let single = old_roots.len() == 1 && new_roots.len() == 1;
+
+    let mut diffs = vec![];
+    for i in 0..old_roots.len().max(new_roots.len()) {
+        let path = if single {
+            String::new()
+        } else {
+            format!("[{i}]")
+        };
+        let to_value =
+            |root: &AnyRsz| serde_json::to_value(root).context("Failed to convert to json");
+        match (old_roots.get(i), new_roots.get(i)) {
+            (Some(old_root), Some(new_root)) => {
+                if let (Some(old_raw), Some(new_raw)) = (
+                    old_root.downcast_ref::<RawRsz>(),
+                    new_root.downcast_ref::<RawRsz>(),
+                ) {
+                    diff_raw(&mut diffs, &path, old_raw, new_raw);
+                    continue;
+                }
+                if old_root.symbol() != new_root.symbol() {
+                    diffs.push(RszDiff {
+                        path: field_path(&path, "type"),
+                        old: Some(old_root.symbol().to_owned()),
+                        new: Some(new_root.symbol().to_owned()),
+                    });
+                    continue;
+                }
+                diff_value(
+                    &mut diffs,
+                    &path,
+                    &to_value(old_root)?,
+                    &to_value(new_root)?,
+                );
+            }
+            (old_root, new_root) => {
+                let old_value = old_root.map(to_value).transpose()?;
+                let new_value = new_root.map(to_value).transpose()?;
+                push_diff(&mut diffs, &path, old_value.as_ref(), new_value.as_ref());
+            }
+        }
+    }
+    Ok((diffs, diagnostics))
+}
@@ -0,0 +1,346 @@
+use super::*;
+use nalgebra_glm::*;
+
+// Field layouts for types that are not modelled by rsz_struct!,
+// so that they can still be decoded into a tree of dynamic values.
+// Layouts are generated from TDB, which only describes managed fields,
+// so native types are not included.
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RszFieldKind {
+    Bool,
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    I64,
+    U64,
+    F32,
+    F64,
+    String,
+    Vec2,
+    Vec3,
+    Vec4,
+    Quat,
+    Mat4,
+    Guid,
+    // Child instance or extern user file
+    Object,
+    // Value type laid out inline
+    Struct(Vec<RszSchemaField>),
+    Array(Box<RszFieldKind>),
+    // Type without known layout
+    Unknown(String),
+}
+
+impl RszFieldKind {
+    // Alignment of the first byte read, matching read_value
+    fn align(&self) -> u64 {
+        match self {
+            RszFieldKind::Bool | RszFieldKind::I8 | RszFieldKind::U8 => bool::FIELD_ALIGN,
+            RszFieldKind::I16 | RszFieldKind::U16 => u16::FIELD_ALIGN,
+            RszFieldKind::I32 | RszFieldKind::U32 | RszFieldKind::F32 => u32::FIELD_ALIGN,
+            RszFieldKind::I64 | RszFieldKind::U64 | RszFieldKind::F64 => u64::FIELD_ALIGN,
+            RszFieldKind::String | RszFieldKind::Object | RszFieldKind::Array(_) => 4,
+            RszFieldKind::Vec2 => Vec2::FIELD_ALIGN,
+            RszFieldKind::Vec3 => Vec3::FIELD_ALIGN,
+            RszFieldKind::Vec4 => Vec4::FIELD_ALIGN,
+            RszFieldKind::Quat => Quat::FIELD_ALIGN,
+            RszFieldKind::Mat4 => Mat4x4::FIELD_ALIGN,
+            RszFieldKind::Guid => Guid::FIELD_ALIGN,
+            RszFieldKind::Struct(fields) => fields.first().map_or(1, |field| field.kind.align()),
+            RszFieldKind::Unknown(_) => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RszSchemaField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_name: String,
+    pub kind: RszFieldKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RszSchemaType {
+    pub crc: u32,
+    pub fields: Vec<RszSchemaField>,
+}
+
+pub struct RszSchema {
+    types: BTreeMap<String, RszSchemaType>,
+    // Built once per schema and keyed by hash and CRC,
+    // so that instances of other CRCs are not decoded with the layout
+    type_infos: HashMap<(u32, u32), Arc<RszTypeInfo>>,
+}
+
+impl RszSchema {
+    pub fn new(types: BTreeMap<String, RszSchemaType>) -> RszSchema {
+        let type_infos = types
+            .iter()
+            .map(|(symbol, schema_type)| {
+                let crc = schema_type.crc;
+                let type_info = RszTypeInfo {
+                    deserializer: dynamic_deserializer,
+                    to_json: rsz_to_json::<DynRsz>,
+                    as_serialize: rsz_as_serialize::<DynRsz>,
+                    to_json_typed: |_| bail!("Dynamic RSZ node can't be written as typed JSON"),
+                    from_json: |_, _| bail!("Dynamic RSZ node can't be read from JSON"),
+                    debug: rsz_debug::<DynRsz>,
+                    serializer: dynamic_serializer,
+                    versions: HashMap::from([(crc, 0)]),
+                    versions_raw: Cow::Owned(vec![(crc, 0)]),
+                    align: schema_type
+                        .fields
+                        .first()
+                        .map_or(1, |field| field.kind.align()),
+                    skip: |rsz, type_info| dynamic_deserializer(rsz, type_info).map(|_| ()),
+                    symbol: Cow::Owned(symbol.clone()),
+                    #[cfg(test)]
+                    sample: None,
+                };
+                ((hash_as_utf8(symbol), crc), Arc::new(type_info))
+            })
+            .collect();
+        RszSchema { types, type_infos }
+    }
+
+    pub fn load(path: &str) -> Result<RszSchema> {
+        let types = serde_json::from_reader(std::io::BufReader::new(
+            std::fs::File::open(path).context("Failed to open RSZ schema")?,
+        ))
+        .context("Failed to parse RSZ schema")?;
+        Ok(RszSchema::new(types))
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        serde_json::to_writer_pretty(std::fs::File::create(path)?, &self.types)?;
+        Ok(())
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&RszSchemaType> {
+        self.types.get(symbol)
+    }
+
+    pub(super) fn type_info(&self, hash: u32, crc: u32) -> Option<TypeInfoRef> {
+        self.type_infos
+            .get(&(hash, crc))
+            .map(|type_info| TypeInfoRef::Schema(type_info.clone()))
+    }
+}
+
+#[derive(Debug)]
+pub enum RszValue {
+    Bool(bool),
+    I8(i8),
+    U8(u8),
+    I16(i16),
+    U16(u16),
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    String(Option<String>),
+    Vec2(Vec2),
+    Vec3(Vec3),
+    Vec4(Vec4),
+    Quat(Quat),
+    Mat4(Mat4x4),
+    Guid(Guid),
+    Null,
+    Extern(String),
+    Object(AnyRsz),
+    Struct(Vec<(String, RszValue)>),
+    Array(Vec<RszValue>),
+}
+
+impl Serialize for RszValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            RszValue::Bool(v) => v.serialize(serializer),
+            RszValue::I8(v) => v.serialize(serializer),
+            RszValue::U8(v) => v.serialize(serializer),
+            RszValue::I16(v) => v.serialize(serializer),
+            RszValue::U16(v) => v.serialize(serializer),
+            RszValue::I32(v) => v.serialize(serializer),
+            RszValue::U32(v) => v.serialize(serializer),
+            RszValue::I64(v) => v.serialize(serializer),
+            RszValue::U64(v) => v.serialize(serializer),
+            RszValue::F32(v) => v.serialize(serializer),
+            RszValue::F64(v) => v.serialize(serializer),
+            RszValue::String(v) => v.serialize(serializer),
+            RszValue::Vec2(v) => v.serialize(serializer),
+            RszValue::Vec3(v) => v.serialize(serializer),
+            RszValue::Vec4(v) => v.serialize(serializer),
+            RszValue::Quat(v) => v.serialize(serializer),
+            RszValue::Mat4(v) => v.serialize(serializer),
+            RszValue::Guid(v) => v.serialize(serializer),
+            RszValue::Null => serializer.serialize_none(),
+            RszValue::Extern(path) => {
+                serializer.serialize_newtype_variant("ExternUser", 0, "Path", path)
+            }
+            RszValue::Object(v) => v.serialize(serializer),
+            RszValue::Struct(fields) => serialize_fields(fields, serializer),
+            RszValue::Array(v) => v.serialize(serializer),
+        }
+    }
+}
+
+fn serialize_fields<S>(fields: &[(String, RszValue)], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    use serde::ser::SerializeMap;
+    let mut map = serializer.serialize_map(Some(fields.len()))?;
+    for (name, value) in fields {
+        map.serialize_entry(name, value)?;
+    }
+    map.end()
+}
+
+// An instance decoded with RszSchema
+#[derive(Debug)]
+pub struct DynRsz {
+    pub fields: Vec<(String, RszValue)>,
+}
+
+impl Serialize for DynRsz {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize_fields(&self.fields, serializer)
+    }
+}
+
+fn read_fields(
+    fields: &[RszSchemaField],
+    rsz: &mut RszDeserializer,
+) -> Result<Vec<(String, RszValue)>> {
+    fields
+        .iter()
+        .map(|field| {
+            let value = read_value(&field.kind, rsz).context(field.name.clone())?;
+            Ok((field.name.clone(), value))
+        })
+        .collect()
+}
+
+fn read_value(kind: &RszFieldKind, rsz: &mut RszDeserializer) -> Result<RszValue> {
+    Ok(match kind {
+        RszFieldKind::Bool => RszValue::Bool(bool::field_from_rsz(rsz)?),
+        RszFieldKind::I8 => RszValue::I8(i8::field_from_rsz(rsz)?),
+        RszFieldKind::U8 => RszValue::U8(u8::field_from_rsz(rsz)?),
+        RszFieldKind::I16 => RszValue::I16(i16::field_from_rsz(rsz)?),
+        RszFieldKind::U16 => RszValue::U16(u16::field_from_rsz(rsz)?),
+        RszFieldKind::I32 => RszValue::I32(i32::field_from_rsz(rsz)?),
+        RszFieldKind::U32 => RszValue::U32(u32::field_from_rsz(rsz)?),
+        RszFieldKind::I64 => RszValue::I64(i64::field_from_rsz(rsz)?),
+        RszFieldKind::U64 => RszValue::U64(u64::field_from_rsz(rsz)?),
+        RszFieldKind::F32 => RszValue::F32(f32::field_from_rsz(rsz)?),
+        RszFieldKind::F64 => RszValue::F64(f64::field_from_rsz(rsz)?),
+        RszFieldKind::String => RszValue::String(Option::<String>::field_from_rsz(rsz)?),
+        RszFieldKind::Vec2 => RszValue::Vec2(Vec2::field_from_rsz(rsz)?),
+        RszFieldKind::Vec3 => RszValue::Vec3(Vec3::field_from_rsz(rsz)?),
+        RszFieldKind::Vec4 => RszValue::Vec4(Vec4::field_from_rsz(rsz)?),
+        RszFieldKind::Quat => RszValue::Quat(Quat::field_from_rsz(rsz)?),
+        RszFieldKind::Mat4 => RszValue::Mat4(Mat4x4::field_from_rsz(rsz)?),
+        RszFieldKind::Guid => RszValue::Guid(Guid::field_from_rsz(rsz)?),
+        RszFieldKind::Object => {
+            rsz.cursor.seek_align_up(4)?;
+            let index = rsz.read_u32()?;
+            if index == 0 {
+                return Ok(RszValue::Null);
+            }
+            match rsz.child_slot(index)? {
+                NodeSlot::Extern(path) => RszValue::Extern(path.clone()),
+                slot => RszValue::Object(slot.get_instance()?.clone()),
+            }
+        }
+        RszFieldKind::Struct(fields) => RszValue::Struct(read_fields(fields, rsz)?),
+        RszFieldKind::Array(kind) => {
+            rsz.cursor.seek_align_up(4)?;
+            let count = rsz.read_u32()?;
+            RszValue::Array(
+                (0..count)
+                    .map(|_| read_value(kind, rsz))
+                    .collect::<Result<Vec<_>>>()?,
+            )
+        }
+        RszFieldKind::Unknown(type_name) => bail!("Unknown layout for type {}", type_name),
+    })
+}
+
+fn write_value(value: &RszValue, rsz: &mut RszSerializer) -> Result<()> {
+    match value {
+        RszValue::Bool(v) => v.field_to_rsz(rsz),
+        RszValue::I8(v) => v.field_to_rsz(rsz),
+        RszValue::U8(v) => v.field_to_rsz(rsz),
+        RszValue::I16(v) => v.field_to_rsz(rsz),
+        RszValue::U16(v) => v.field_to_rsz(rsz),
+        RszValue::I32(v) => v.field_to_rsz(rsz),
+        RszValue::U32(v) => v.field_to_rsz(rsz),
+        RszValue::I64(v) => v.field_to_rsz(rsz),
+        RszValue::U64(v) => v.field_to_rsz(rsz),
+        RszValue::F32(v) => v.field_to_rsz(rsz),
+        RszValue::F64(v) => v.field_to_rsz(rsz),
+        RszValue::String(v) => v.field_to_rsz(rsz),
+        RszValue::Vec2(v) => v.field_to_rsz(rsz),
+        RszValue::Vec3(v) => v.field_to_rsz(rsz),
+        RszValue::Vec4(v) => v.field_to_rsz(rsz),
+        RszValue::Quat(v) => v.field_to_rsz(rsz),
+        RszValue::Mat4(v) => v.field_to_rsz(rsz),
+        RszValue::Guid(v) => v.field_to_rsz(rsz),
+        RszValue::Null => {
+            rsz.align(4);
+            rsz.write_u32(0)
+        }
+        RszValue::Extern(path) => {
+            rsz.align(4);
+            let index = rsz.add_extern::<()>(path)?;
+            rsz.write_u32(index)
+        }
+        RszValue::Object(node) => {
+            let index = rsz.add_child_any(node)?;
+            rsz.align(4);
+            rsz.write_u32(index)
+        }
+        RszValue::Struct(fields) => write_fields(fields, rsz),
+        RszValue::Array(values) => {
+            rsz.align(4);
+            rsz.write_u32(u32::try_from(values.len())?)?;
+            for value in values {
+                write_value(value, rsz)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn write_fields(fields: &[(String, RszValue)], rsz: &mut RszSerializer) -> Result<()> {
+    for (name, value) in fields {
+        write_value(value, rsz).context(name.clone())?;
+    }
+    Ok(())
+}
+
+fn dynamic_deserializer(rsz: &mut RszDeserializer, type_info: &TypeInfoRef) -> Result<AnyRsz> {
+    let schema = rsz.schema.context("No RSZ schema")?;
+    let layout = schema
+        .get(&type_info.symbol)
+        .context("Type not found in RSZ schema")?;
+    let fields = read_fields(&layout.fields, rsz)?;
+    Ok(AnyRsz::new(DynRsz { fields }, type_info.clone()))
+}
+
+fn dynamic_serializer(any: &dyn Any, rsz: &mut RszSerializer) -> Result<()> {
+    write_fields(&any.downcast_ref::<DynRsz>().unwrap().fields, rsz)
+}
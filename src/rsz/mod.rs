@@ -11,7 +11,9 @@ mod condition_damage_preset;
 mod custom_buildup;
 mod data_base;
 mod data_tune;
+mod diff;
 mod dlc;
+mod dynamic;
 mod ecological;
 mod facility;
 mod item;
@@ -42,7 +44,9 @@ pub use condition_damage_preset::*;
 pub use custom_buildup::*;
 pub use data_base::*;
 pub use data_tune::*;
+pub use diff::*;
 pub use dlc::*;
+pub use dynamic::*;
 pub use ecological::*;
 pub use facility::*;
 pub use item::*;
@@ -60,19 +64,22 @@ pub use scene::*;
 pub use skill::*;
 pub use weapon::*;
 
+use crate::align::*;
 use crate::file_ext::*;
 use crate::hash::*;
 use anyhow::{anyhow, bail, Context, Result};
 use bitflags::*;
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use serde::*;
 use std::any::*;
-use std::collections::{BTreeMap, HashMap};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::{TryFrom, TryInto};
 use std::fmt::Debug;
-use std::io::{Cursor, Read, Seek, SeekFrom};
-use std::ops::Deref;
-use std::rc::*;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::ops::{Deref, Range};
+use std::sync::Arc;
 
 /****
 
@@ -115,6 +122,8 @@ enum NodeSlot {
     None,
     Extern(String),
     Instance(AnyRsz),
+    // Taken by value by a parent, with the type info kept for error messages
+    Taken(TypeInfoRef),
 }
 
 impl NodeSlot {
@@ -128,6 +137,10 @@ impl NodeSlot {
     fn get_instance(&self) -> Result<&AnyRsz> {
         match self {
             NodeSlot::Instance(rsz) => Ok(rsz),
+            NodeSlot::Taken(type_info) => bail!(
+                "The {} node is already taken by value. Shared nodes should be referenced as Arc<T>",
+                type_info.symbol
+            ),
             _ => bail!("The node slot doesn't contain instance: {:?}", self),
         }
     }
@@ -144,6 +157,56 @@ impl NodeSlot {
     }
 }
 
+// Records how instances reference other nodes while decoding
+#[derive(Default)]
+struct NodeTracker {
+    // Indexes of nodes taken by value
+    taken: Vec<usize>,
+    // Whether the current instance references an instance that failed to decode
+    raw_child: bool,
+}
+
+#[derive(Debug, Clone)]
+enum NodePlan {
+    Extern,
+    // With the entries in NodeTracker::taken for nodes it took by value
+    Decoded { start: u64, taken: Range<usize> },
+    // Failed instance, kept as raw bytes
+    Raw,
+}
+
+// Bounds the number of offsets tried for the next instance after a failed one,
+// when the failed one can't be skipped
+const RESYNC_CANDIDATES: usize = 0x1000;
+// Number of instances that should decode from an offset to accept it as the next instance
+const RESYNC_WINDOW: usize = 4;
+
+struct DecodeContext<'a> {
+    version_hint: Option<u32>,
+    schema: Option<&'a RszSchema>,
+    node_buf: Vec<NodeSlot>,
+    plan: Vec<NodePlan>,
+    tracker: NodeTracker,
+    // None in strict mode
+    diagnostics: Option<Vec<RszDiagnostic>>,
+    // Number of instances decoded, including those discarded while resyncing
+    decodes: usize,
+}
+
+impl<'a> DecodeContext<'a> {
+    fn new(version_hint: Option<u32>, schema: Option<&'a RszSchema>, lenient: bool) -> Self {
+        DecodeContext {
+            version_hint,
+            schema,
+            node_buf: vec![NodeSlot::None],
+            plan: vec![NodePlan::Extern],
+            tracker: NodeTracker::default(),
+            diagnostics: lenient.then(Vec::new),
+            decodes: 0,
+        }
+    }
+}
+
 impl Rsz {
     pub fn new<F: Read + Seek>(mut file: F, base: u64) -> Result<Rsz> {
         file.seek(SeekFrom::Start(base))?;
@@ -235,19 +298,120 @@ impl Rsz {
     }
 
     pub fn deserialize(&self, version_hint: Option<u32>) -> Result<Vec<AnyRsz>> {
-        let mut node_buf: Vec<NodeSlot> = vec![NodeSlot::None];
-        let mut cursor = Cursor::new(&self.data);
-        for (i, &TypeDescriptor { hash, crc }) in self.type_descriptors.iter().enumerate().skip(1) {
-            if let Some(slot_extern) = self.extern_slots.get(&u32::try_from(i)?) {
-                if slot_extern.hash != hash {
-                    bail!("Extern hash mismatch")
-                }
-                node_buf.push(NodeSlot::Extern(slot_extern.path.clone()));
-                continue;
+        self.deserialize_with_schema(version_hint, None)
+    }
+
+    // Types not registered in RSZ_TYPE_MAP are decoded as DynRsz if found in the schema
+    pub fn deserialize_with_schema(
+        &self,
+        version_hint: Option<u32>,
+        schema: Option<&RszSchema>,
+    ) -> Result<Vec<AnyRsz>> {
+        self.deserialize_impl(version_hint, schema, None, None)
+    }
+
+    // Instead of failing, records diagnostics and substitutes RawRsz with the original bytes
+    // for instances that fail. Decoding resumes at the next instance, which starts where the
+    // failed one ends if its size doesn't depend on its data. Otherwise offsets aligned for the
+    // next instance are tried until a few instances decode from one.
+    // Instances referencing a failed one fail as well and are substituted the same way.
+    pub fn deserialize_lenient(
+        &self,
+        version_hint: Option<u32>,
+    ) -> Result<(Vec<AnyRsz>, Vec<RszDiagnostic>)> {
+        let mut diagnostics = vec![];
+        let result = self.deserialize_impl(version_hint, None, Some(&mut diagnostics), None)?;
+        Ok((result, diagnostics))
+    }
+
+    /// Decodes and converts roots to JSON, one string per root, with the `$type` of each instance.
+    /// Instances written more than once are written as `{"$id": index, "$value": ...}` at the
+    /// first occurrence and as `{"$ref": index}` afterwards, where index is the node index.
+    /// Only instances referenced as AnyRsz take part, typed fields are written in place.
+    pub fn to_json_dag(
+        &self,
+        version_hint: Option<u32>,
+        schema: Option<&RszSchema>,
+    ) -> Result<Vec<String>> {
+        let mut indexes = HashMap::new();
+        let roots = self.deserialize_impl(version_hint, schema, None, Some(&mut indexes))?;
+        DAG_STATE.with(|state| {
+            *state.borrow_mut() = Some(DagState {
+                indexes,
+                counts: HashMap::new(),
+                counting: true,
+                written: HashSet::new(),
+            })
+        });
+        let result = (|| {
+            for root in &roots {
+                serde_json::to_writer(std::io::sink(), root)
+                    .context("Failed to convert to json")?;
             }
+            DAG_STATE.with(|state| state.borrow_mut().as_mut().unwrap().counting = false);
+            roots
+                .iter()
+                .map(|root| serde_json::to_string_pretty(root).context("Failed to convert to json"))
+                .collect()
+        })();
+        DAG_STATE.with(|state| *state.borrow_mut() = None);
+        result
+    }
+
+    fn deserialize_instance(
+        node_buf: &mut [NodeSlot],
+        cursor: &mut Cursor<&Vec<u8>>,
+        type_info: &TypeInfoRef,
+        crc: u32,
+        version_hint: Option<u32>,
+        schema: Option<&RszSchema>,
+        tracker: &mut NodeTracker,
+    ) -> Result<AnyRsz> {
+        let version = if type_info.versions.is_empty() {
+            version_hint.unwrap_or(0)
+        } else {
+            *type_info
+                .versions
+                .get(&crc)
+                .with_context(|| format!("Unknown type CRC {:08X}", crc))?
+        };
+        tracker.raw_child = false;
+        let mut rsz_deserializer = RszDeserializer {
+            node_buf,
+            cursor,
+            version,
+            schema,
+            tracker,
+        };
+        (type_info.deserializer)(&mut rsz_deserializer, type_info)
+    }
+
+    fn type_info(hash: u32, crc: u32, schema: Option<&RszSchema>) -> Option<TypeInfoRef> {
+        RSZ_TYPE_MAP
+            .get(&hash)
+            .map(TypeInfoRef::Static)
+            .or_else(|| schema.and_then(|schema| schema.type_info(hash, crc)))
+    }
 
-            let pos = cursor.tell().unwrap();
-            let type_info = RSZ_TYPE_MAP.get(&hash).with_context(|| {
+    fn symbol(descriptor: &TypeDescriptor, schema: Option<&RszSchema>) -> String {
+        let &TypeDescriptor { hash, crc } = descriptor;
+        Self::type_info(hash, crc, schema)
+            .map_or_else(|| format!("{hash:08X}"), |t| t.symbol.to_string())
+    }
+
+    // Decodes the instance at the index starting from pos, and returns where it ends
+    fn decode_node(
+        &self,
+        context: &mut DecodeContext,
+        index: usize,
+        pos: u64,
+    ) -> (Result<AnyRsz>, u64) {
+        context.decodes += 1;
+        let TypeDescriptor { hash, crc } = self.type_descriptors[index];
+        let mut cursor = Cursor::new(&self.data);
+        cursor.set_position(pos);
+        let result = Self::type_info(hash, crc, context.schema)
+            .with_context(|| {
                 let mut buffer = [0; 0x100];
                 let read = cursor.read(&mut buffer).unwrap();
                 format!(
@@ -256,30 +420,296 @@ impl Rsz {
                     pos,
                     &buffer[0..read]
                 )
-            })?;
-            let version = if type_info.versions.is_empty() {
-                version_hint.unwrap_or(0)
-            } else {
-                *type_info.versions.get(&crc).with_context(|| {
-                    format!(
-                        "Unknown type CRC {:08X} for type {:08X} ({}) at {:08X}",
-                        crc, hash, type_info.symbol, pos
-                    )
-                })?
-            };
-            let mut rsz_deserializer = RszDeserializer {
-                node_buf: &mut node_buf,
-                cursor: &mut cursor,
-                version,
+            })
+            .and_then(|type_info| {
+                Self::deserialize_instance(
+                    &mut context.node_buf,
+                    &mut cursor,
+                    &type_info,
+                    crc,
+                    context.version_hint,
+                    context.schema,
+                    &mut context.tracker,
+                )
+            });
+        (result, cursor.position())
+    }
+
+    // The type info and version to decode the instance at the index with
+    fn layout(&self, context: &DecodeContext, index: usize) -> Result<(TypeInfoRef, u32)> {
+        let TypeDescriptor { hash, crc } = self.type_descriptors[index];
+        let type_info = Self::type_info(hash, crc, context.schema)
+            .with_context(|| format!("Unsupported type {:08X}", hash))?;
+        let version = if type_info.versions.is_empty() {
+            context.version_hint.unwrap_or(0)
+        } else {
+            *type_info
+                .versions
+                .get(&crc)
+                .with_context(|| format!("Unknown type CRC {:08X}", crc))?
+        };
+        Ok((type_info, version))
+    }
+
+    fn extern_slot(&self, index: usize) -> Result<Option<NodeSlot>> {
+        let Some(slot_extern) = self.extern_slots.get(&u32::try_from(index)?) else {
+            return Ok(None);
+        };
+        if slot_extern.hash != self.type_descriptors[index].hash {
+            bail!("Extern hash mismatch")
+        }
+        Ok(Some(NodeSlot::Extern(slot_extern.path.clone())))
+    }
+
+    // Pushes the node at the index if it is an extern slot
+    fn push_extern(&self, context: &mut DecodeContext, index: usize) -> Result<bool> {
+        let Some(slot) = self.extern_slot(index)? else {
+            return Ok(false);
+        };
+        context.node_buf.push(slot);
+        context.plan.push(NodePlan::Extern);
+        Ok(true)
+    }
+
+    fn push_decoded(
+        &self,
+        context: &mut DecodeContext,
+        node: AnyRsz,
+        start: u64,
+        taken_len: usize,
+    ) {
+        context.node_buf.push(NodeSlot::Instance(node));
+        context.plan.push(NodePlan::Decoded {
+            start,
+            taken: taken_len..context.tracker.taken.len(),
+        });
+    }
+
+    // Records the failure and pushes a placeholder, which is filled once its end is found
+    fn push_failed(
+        &self,
+        context: &mut DecodeContext,
+        index: usize,
+        start: u64,
+        error: anyhow::Error,
+    ) {
+        let TypeDescriptor { hash, crc } = self.type_descriptors[index];
+        if let Some(diagnostics) = context.diagnostics.as_mut() {
+            diagnostics.push(RszDiagnostic {
+                symbol: Self::symbol(&self.type_descriptors[index], context.schema),
+                index,
+                offset: start,
+                error: format!("{error:#}"),
+            });
+        }
+        context
+            .node_buf
+            .push(NodeSlot::Instance(RawRsz::new_any(hash, crc, vec![])));
+        context.plan.push(NodePlan::Raw);
+    }
+
+    // Decodes all nodes, and returns where the data ends
+    fn plan_nodes(&self, context: &mut DecodeContext) -> Result<u64> {
+        let mut index = 1;
+        let mut pos = 0;
+        while index < self.type_descriptors.len() {
+            if self.push_extern(context, index)? {
+                index += 1;
+                continue;
+            }
+            let taken_len = context.tracker.taken.len();
+            match self.decode_node(context, index, pos) {
+                (Ok(node), end) => {
+                    self.push_decoded(context, node, pos, taken_len);
+                    pos = end;
+                    index += 1;
+                }
+                (Err(error), _) => {
+                    if context.diagnostics.is_none() {
+                        let symbol = Self::symbol(&self.type_descriptors[index], context.schema);
+                        return Err(error.context(format!(
+                            "Error deserializing for type {} at {:08X}, index {}",
+                            symbol, pos, index
+                        )));
+                    }
+                    self.untake(context, taken_len)?;
+                    (index, pos) = self.resync(context, index, pos, error)?;
+                }
+            }
+        }
+        Ok(pos)
+    }
+
+    // Reads past the instance at the index starting from pos, and returns where it ends
+    fn skip_node(&self, context: &mut DecodeContext, index: usize, pos: u64) -> Result<u64> {
+        let (type_info, version) = self.layout(context, index)?;
+        context.decodes += 1;
+        let mut cursor = Cursor::new(&self.data);
+        cursor.set_position(pos);
+        let mut rsz_deserializer = RszDeserializer {
+            node_buf: &mut context.node_buf,
+            cursor: &mut cursor,
+            version,
+            schema: context.schema,
+            tracker: &mut context.tracker,
+        };
+        (type_info.skip)(&mut rsz_deserializer, &type_info)?;
+        Ok(cursor.position())
+    }
+
+    // Pushes the instance at the index that failed at pos, and returns the next instance and
+    // where it starts. The failed instance is skipped over if its layout is known. Otherwise,
+    // or if nothing decodes from there, offsets aligned for the next instance are tried, and
+    // the first one from which a few instances decode is taken. Following instances without
+    // a known layout can't be found, so they are failed as well and left empty.
+    fn resync(
+        &self,
+        context: &mut DecodeContext,
+        index: usize,
+        pos: u64,
+        error: anyhow::Error,
+    ) -> Result<(usize, u64)> {
+        let data_len = u64::try_from(self.data.len())?;
+        self.push_failed(context, index, pos, error);
+        let skipped = self.skip_node(context, index, pos).ok();
+        let mut next = index + 1;
+        let end = 'search: loop {
+            if next == self.type_descriptors.len() {
+                break data_len;
+            }
+            if self.push_extern(context, next)? {
+                next += 1;
+                continue;
+            }
+            let align = match self.layout(context, next) {
+                Ok((type_info, _)) => type_info.align,
+                Err(error) => {
+                    self.push_failed(context, next, pos, error);
+                    next += 1;
+                    continue;
+                }
             };
-            let node =
-                (type_info.deserializer)(&mut rsz_deserializer, type_info).with_context(|| {
-                    format!(
-                        "Error deserializing for type {} at {:08X}, index {}",
-                        type_info.symbol, pos, i
-                    )
-                })?;
-            node_buf.push(NodeSlot::Instance(node));
+
+            let candidates = skipped
+                .into_iter()
+                .chain((align_up(pos, align)..=data_len).step_by(usize::try_from(align)?))
+                .filter(|&candidate| candidate <= data_len)
+                .take(RESYNC_CANDIDATES);
+            for candidate in candidates {
+                if self.probe(context, next, candidate)? {
+                    break 'search candidate;
+                }
+            }
+
+            // The next instance can't be found. Keep the rest of data in the failed instance
+            break data_len;
+        };
+
+        let TypeDescriptor { hash, crc } = self.type_descriptors[index];
+        let data = self.data[usize::try_from(pos)?..usize::try_from(end)?].to_vec();
+        context.node_buf[index] = NodeSlot::Instance(RawRsz::new_any(hash, crc, data));
+        Ok((next, end))
+    }
+
+    // Decodes up to RESYNC_WINDOW instances from the index starting from pos, then discards them.
+    // Returns whether they decode
+    fn probe(&self, context: &mut DecodeContext, mut index: usize, mut pos: u64) -> Result<bool> {
+        let data_len = u64::try_from(self.data.len())?;
+        let buf_len = context.node_buf.len();
+        let taken_len = context.tracker.taken.len();
+        let mut decoded = 0;
+        let accepted = loop {
+            if index == self.type_descriptors.len() {
+                break pos == data_len;
+            }
+            if decoded == RESYNC_WINDOW {
+                break true;
+            }
+            if self.push_extern(context, index)? {
+                index += 1;
+                continue;
+            }
+            let node_taken_len = context.tracker.taken.len();
+            match self.decode_node(context, index, pos) {
+                (Ok(node), end) => {
+                    self.push_decoded(context, node, pos, node_taken_len);
+                    pos = end;
+                }
+                // It fails wherever it starts, so only its layout is checked
+                (Err(_), _) if context.tracker.raw_child => {
+                    let Ok(end) = self.skip_node(context, index, pos) else {
+                        break false;
+                    };
+                    let TypeDescriptor { hash, crc } = self.type_descriptors[index];
+                    context
+                        .node_buf
+                        .push(NodeSlot::Instance(RawRsz::new_any(hash, crc, vec![])));
+                    context.plan.push(NodePlan::Raw);
+                    pos = end;
+                }
+                // An instance without a known layout is left to its own resync
+                (Err(_), _) => break decoded != 0 && self.layout(context, index).is_err(),
+            }
+            index += 1;
+            decoded += 1;
+        };
+        context.node_buf.truncate(buf_len);
+        context.plan.truncate(buf_len);
+        self.untake(context, taken_len)?;
+        Ok(accepted)
+    }
+
+    // Decodes again the nodes taken by value since taken_len by a failed or discarded decode
+    fn untake(&self, context: &mut DecodeContext, taken_len: usize) -> Result<()> {
+        for index in context.tracker.taken.split_off(taken_len) {
+            if index < context.node_buf.len() {
+                self.restore(context, index)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Decodes the node at the index again, after restoring the nodes it took by value
+    fn restore(&self, context: &mut DecodeContext, index: usize) -> Result<()> {
+        let NodePlan::Decoded { start, taken } = context.plan[index].clone() else {
+            bail!("Node {index} taken by value is not decoded")
+        };
+        for i in taken {
+            self.restore(context, context.tracker.taken[i])?;
+        }
+        let taken_len = context.tracker.taken.len();
+        let node = self.decode_node(context, index, start).0.with_context(|| {
+            format!("Failed to decode node {index} again after a failed instance")
+        })?;
+        context.tracker.taken.truncate(taken_len);
+        context.node_buf[index] = NodeSlot::Instance(node);
+        Ok(())
+    }
+
+    fn deserialize_impl(
+        &self,
+        version_hint: Option<u32>,
+        schema: Option<&RszSchema>,
+        mut diagnostics: Option<&mut Vec<RszDiagnostic>>,
+        node_indexes: Option<&mut HashMap<*const (), usize>>,
+    ) -> Result<Vec<AnyRsz>> {
+        let mut context = DecodeContext::new(version_hint, schema, diagnostics.is_some());
+        let end = self.plan_nodes(&mut context)?;
+        let failed = context
+            .plan
+            .iter()
+            .any(|plan| matches!(plan, NodePlan::Raw));
+        if let (Some(diagnostics), Some(new)) = (diagnostics.as_deref_mut(), context.diagnostics) {
+            diagnostics.extend(new);
+        }
+        let mut node_buf = context.node_buf;
+
+        if let Some(node_indexes) = node_indexes {
+            for (i, node) in node_buf.iter().enumerate() {
+                if let NodeSlot::Instance(node) = node {
+                    node_indexes.insert(Arc::as_ptr(&node.any) as *const (), i);
+                }
+            }
         }
 
         let result = self
@@ -293,18 +723,47 @@ impl Rsz {
             })
             .collect::<Result<Vec<_>>>()?;
 
+        // Failed instances are expected to be left over
+        if failed {
+            return Ok(result);
+        }
+
         for (i, node) in node_buf.into_iter().enumerate() {
             if let NodeSlot::Instance(node) = node {
-                if Rc::strong_count(&node.any) == 1 {
-                    bail!("Left over node {} ({})", i, node.symbol())
+                if Arc::strong_count(&node.any) == 1 {
+                    let error = format!("Left over node {} ({})", i, node.symbol());
+                    let NodePlan::Decoded { start, .. } = context.plan[i] else {
+                        unreachable!()
+                    };
+                    match diagnostics.as_deref_mut() {
+                        None => bail!(error),
+                        Some(diagnostics) => diagnostics.push(RszDiagnostic {
+                            symbol: node.symbol().to_owned(),
+                            index: i,
+                            offset: start,
+                            error,
+                        }),
+                    }
                 }
             }
         }
 
-        let mut leftover = vec![];
-        cursor.read_to_end(&mut leftover)?;
+        let leftover = &self.data[usize::try_from(end)?..];
         if !leftover.is_empty() {
-            bail!("Left over data {leftover:?}");
+            let error = format!("Left over data {leftover:?}");
+            match diagnostics {
+                None => bail!(error),
+                // Most likely the last instance is not fully decoded
+                Some(diagnostics) => {
+                    let index = self.type_descriptors.len() - 1;
+                    diagnostics.push(RszDiagnostic {
+                        symbol: Self::symbol(&self.type_descriptors[index], schema),
+                        index,
+                        offset: end,
+                        error,
+                    })
+                }
+            }
         }
 
         Ok(result)
@@ -336,20 +795,141 @@ impl Rsz {
                 if print_all
                     || (!type_info.versions.contains_key(&td.crc) && !type_info.versions.is_empty())
                 {
-                    crc_mismatches.insert(type_info.symbol, td.crc);
+                    crc_mismatches.insert(&type_info.symbol, td.crc);
                 }
             }
         }
     }
+
+    // Serializes the deserialized roots again and checks that the result is identical to this block
+    pub fn verify_round_trip(&self, roots: &[AnyRsz], version_hint: Option<u32>) -> Result<()> {
+        let mut serializer = RszSerializer::new(version_hint);
+        serializer.add_hints(self);
+        for root in roots {
+            serializer.add_root_any(root)?;
+        }
+        let mut buf = Cursor::new(vec![]);
+        serializer.finish().write(&mut buf)?;
+        let rsz = Rsz::new(Cursor::new(buf.into_inner()), 0)?;
+
+        if rsz.roots != self.roots {
+            bail!("Roots mismatch: {:?} vs {:?}", rsz.roots, self.roots)
+        }
+        if let Some((i, (a, b))) = rsz
+            .type_descriptors
+            .iter()
+            .zip(&self.type_descriptors)
+            .enumerate()
+            .find(|(_, (a, b))| a != b)
+        {
+            bail!("Type descriptor mismatch at {}: {:?} vs {:?}", i, a, b)
+        }
+        if rsz.type_descriptors.len() != self.type_descriptors.len() {
+            bail!(
+                "Type descriptor count mismatch: {} vs {}",
+                rsz.type_descriptors.len(),
+                self.type_descriptors.len()
+            )
+        }
+        for (slot, Extern { hash, path }) in &self.extern_slots {
+            match rsz.extern_slots.get(slot) {
+                Some(e) if e.hash == *hash && e.path == *path => (),
+                _ => bail!("Extern slot mismatch at {}", slot),
+            }
+        }
+        if rsz.extern_slots.len() != self.extern_slots.len() {
+            bail!("Extern slot count mismatch")
+        }
+        if rsz.data != self.data {
+            let pos = rsz
+                .data
+                .iter()
+                .zip(&self.data)
+                .position(|(a, b)| a != b)
+                .unwrap_or(rsz.data.len().min(self.data.len()));
+            bail!("Data mismatch at {:08X}", pos)
+        }
+        Ok(())
+    }
+
+    pub fn write<F: Write + Seek>(&self, mut file: F) -> Result<()> {
+        let base = file.stream_position()?;
+        let type_descriptor_offset = 0x30 + u64::try_from(self.roots.len())? * 4;
+        let string_table_offset = align_up(
+            base + type_descriptor_offset + u64::try_from(self.type_descriptors.len())? * 8,
+            16,
+        ) - base;
+
+        let mut extern_slots: Vec<_> = self.extern_slots.iter().collect();
+        extern_slots.sort_by_key(|(&slot, _)| slot);
+
+        let mut string_offset = string_table_offset + u64::try_from(extern_slots.len())? * 0x10;
+        let mut extern_slot_info = vec![];
+        for (&slot, Extern { hash, path }) in &extern_slots {
+            extern_slot_info.push((slot, *hash, string_offset));
+            string_offset += u64::try_from(path.encode_utf16().count() + 1)? * 2;
+        }
+        let data_offset = align_up(base + string_offset, 16) - base;
+
+        file.write_magic(b"RSZ\0")?;
+        file.write_u32(0x10)?;
+        file.write_u32(u32::try_from(self.roots.len())?)?;
+        file.write_u32(u32::try_from(self.type_descriptors.len())?)?;
+        file.write_u32(u32::try_from(extern_slots.len())?)?;
+        file.write_u32(0)?;
+        file.write_u64(type_descriptor_offset)?;
+        file.write_u64(data_offset)?;
+        file.write_u64(string_table_offset)?;
+
+        for &root in &self.roots {
+            file.write_u32(root)?;
+        }
+
+        for &TypeDescriptor { hash, crc } in &self.type_descriptors {
+            file.write_u32(hash)?;
+            file.write_u32(crc)?;
+        }
+
+        file.write_align_up(16)?;
+        for (slot, hash, offset) in extern_slot_info {
+            file.write_u32(slot)?;
+            file.write_u32(hash)?;
+            file.write_u64(offset)?;
+        }
+        for (_, Extern { path, .. }) in extern_slots {
+            file.write_u16str(path)?;
+        }
+
+        file.write_align_up(16)?;
+        file.write_all(&self.data)?;
+
+        Ok(())
+    }
 }
 
 pub struct RszDeserializer<'a, 'b> {
     node_buf: &'a mut [NodeSlot],
     cursor: &'a mut Cursor<&'b Vec<u8>>,
     version: u32,
+    schema: Option<&'a RszSchema>,
+    tracker: &'a mut NodeTracker,
 }
 
 impl<'a, 'b> RszDeserializer<'a, 'b> {
+    fn child_slot(&mut self, index: u32) -> Result<&mut NodeSlot> {
+        let index = usize::try_from(index)?;
+        let slot = self
+            .node_buf
+            .get(index)
+            .context("Child index out of bound")?;
+        if let NodeSlot::Instance(node) = slot {
+            if node.downcast_ref::<RawRsz>().is_some() {
+                self.tracker.raw_child = true;
+            }
+        }
+        Ok(&mut self.node_buf[index])
+    }
+
     pub fn get_extern_opt(&mut self) -> Result<Option<&str>> {
         let index = self.cursor.read_u32()?;
         if index == 0 {
@@ -366,26 +946,27 @@ impl<'a, 'b> RszDeserializer<'a, 'b> {
         self.get_extern_opt()?.context("Null extern")
     }
 
-    pub fn get_child_opt<T: 'static>(&mut self) -> Result<Option<T>> {
+    pub fn get_child_opt<T: 'static + Send + Sync>(&mut self) -> Result<Option<T>> {
         let index = self.cursor.read_u32()?;
         if index == 0 {
             return Ok(None);
         }
-        let slot = self
-            .node_buf
-            .get_mut(usize::try_from(index)?)
-            .context("Child index out of bound")?;
-
+        let slot = self.child_slot(index)?;
         let slot_inner = slot.get_instance()?;
-        if Rc::strong_count(&slot_inner.any) != 1 {
-            bail!("Shared node")
+        let type_info = slot_inner.type_info.clone();
+        if Arc::strong_count(&slot_inner.any) != 1 {
+            bail!(
+                "The {} node is shared and can't be taken by value. Shared nodes should be referenced as Arc<T>",
+                type_info.symbol
+            )
         }
-        let node: Rc<T> = slot_inner.clone().downcast()?;
-        slot.take_instance()?;
-        Ok(Some(Rc::try_unwrap(node).map_err(|_| ()).unwrap()))
+        let node: Arc<T> = slot_inner.clone().downcast()?;
+        *slot = NodeSlot::Taken(type_info);
+        self.tracker.taken.push(usize::try_from(index)?);
+        Ok(Some(Arc::try_unwrap(node).map_err(|_| ()).unwrap()))
     }
 
-    pub fn get_child<T: 'static>(&mut self) -> Result<T> {
+    pub fn get_child<T: 'static + Send + Sync>(&mut self) -> Result<T> {
         self.get_child_opt()?.context("Null child")
     }
 
@@ -394,12 +975,7 @@ impl<'a, 'b> RszDeserializer<'a, 'b> {
         if index == 0 {
             return Ok(None);
         }
-        let node = self
-            .node_buf
-            .get_mut(usize::try_from(index)?)
-            .context("Child index out of bound")?
-            .get_instance()?
-            .clone();
+        let node = self.child_slot(index)?.get_instance()?.clone();
         Ok(Some(node))
     }
 
@@ -407,7 +983,7 @@ impl<'a, 'b> RszDeserializer<'a, 'b> {
         self.get_child_any_opt()?.context("Null child")
     }
 
-    pub fn get_child_rc_opt<T: 'static>(&mut self) -> Result<Option<Rc<T>>> {
+    pub fn get_child_arc_opt<T: 'static + Send + Sync>(&mut self) -> Result<Option<Arc<T>>> {
         if let Some(child) = self.get_child_any_opt()? {
             Ok(Some(child.downcast()?))
         } else {
@@ -415,7 +991,7 @@ impl<'a, 'b> RszDeserializer<'a, 'b> {
         }
     }
 
-    pub fn get_child_rc<T: 'static>(&mut self) -> Result<Rc<T>> {
+    pub fn get_child_arc<T: 'static + Send + Sync>(&mut self) -> Result<Arc<T>> {
         self.get_child_any()?.downcast()
     }
 
@@ -430,10 +1006,281 @@ impl<'a, 'b> Read for RszDeserializer<'a, 'b> {
     }
 }
 
+pub struct RszSerializer {
+    roots: Vec<u32>,
+    extern_slots: HashMap<u32, Extern>,
+    type_descriptors: Vec<TypeDescriptor>,
+    data: Vec<u8>,
+    // Pending instance data as (alignment, bytes) pieces. Alignment is relative to the data
+    // start, so the layout is only resolved when the instance is placed after its children.
+    node: Vec<(u64, Vec<u8>)>,
+    version: u32,
+    version_hint: Option<u32>,
+    crc_hints: HashMap<u32, u32>,
+    extern_hints: HashMap<String, TypeDescriptor>,
+    extern_indexs: HashMap<String, u32>,
+    shared_indexs: HashMap<*const (), u32>,
+}
+
+impl RszSerializer {
+    pub fn new(version_hint: Option<u32>) -> RszSerializer {
+        RszSerializer {
+            roots: vec![],
+            extern_slots: HashMap::new(),
+            type_descriptors: vec![TypeDescriptor { hash: 0, crc: 0 }],
+            data: vec![],
+            node: vec![],
+            version: 0,
+            version_hint,
+            crc_hints: HashMap::new(),
+            extern_hints: HashMap::new(),
+            extern_indexs: HashMap::new(),
+            shared_indexs: HashMap::new(),
+        }
+    }
+
+    // Reuses type CRCs and extern types from an existing RSZ block,
+    // so that unmodified data is written back identically.
+    pub fn add_hints(&mut self, rsz: &Rsz) {
+        for td in &rsz.type_descriptors {
+            self.crc_hints.insert(td.hash, td.crc);
+        }
+        for (&slot, Extern { hash, path }) in &rsz.extern_slots {
+            let crc = rsz
+                .type_descriptors
+                .get(slot as usize)
+                .map(|td| td.crc)
+                .unwrap_or_default();
+            self.extern_hints
+                .insert(path.clone(), TypeDescriptor { hash: *hash, crc });
+        }
+    }
+
+    fn type_version(&self, hash: u32, symbol: &str, versions: &[(u32, u32)]) -> Result<(u32, u32)> {
+        if let Some(&crc) = self.crc_hints.get(&hash) {
+            if versions.is_empty() {
+                return Ok((crc, self.version_hint.unwrap_or(0)));
+            }
+            if let Some(&(_, version)) = versions.iter().find(|(c, _)| *c == crc) {
+                return Ok((crc, version));
+            }
+        }
+
+        let mut best: Option<(u32, u32)> = None;
+        for &(crc, version) in versions {
+            if self.version_hint.is_some_and(|hint| version > hint) {
+                continue;
+            }
+            match best {
+                Some((_, best_version)) if best_version >= version => (),
+                _ => best = Some((crc, version)),
+            }
+        }
+        best.with_context(|| {
+            format!(
+                "No known CRC for type {} with version hint {:?}",
+                symbol, self.version_hint
+            )
+        })
+    }
+
+    fn add_instance(
+        &mut self,
+        hash: u32,
+        symbol: &str,
+        versions: &[(u32, u32)],
+        write: impl FnOnce(&mut RszSerializer) -> Result<()>,
+    ) -> Result<u32> {
+        let (crc, version) = self.type_version(hash, symbol, versions)?;
+        let parent_node = std::mem::replace(&mut self.node, vec![(1, vec![])]);
+        let parent_version = std::mem::replace(&mut self.version, version);
+        let result = write(self);
+        let node = std::mem::replace(&mut self.node, parent_node);
+        self.version = parent_version;
+        let index = u32::try_from(self.type_descriptors.len())?;
+        result
+            .with_context(|| format!("Error serializing for type {}, index {}", symbol, index))?;
+
+        for (align, bytes) in node {
+            self.data
+                .resize(align_up(self.data.len(), align as usize), 0);
+            self.data.extend(bytes);
+        }
+        self.type_descriptors.push(TypeDescriptor { hash, crc });
+        Ok(index)
+    }
+
+    pub fn add_child<T: ToRsz>(&mut self, value: &T) -> Result<u32> {
+        // Prefer the registry, which includes versions loaded at runtime
+        let versions = RSZ_TYPE_MAP
+            .get(&T::type_hash())
+            .map_or(T::VERSIONS, |type_info| &type_info.versions_raw);
+        self.add_instance(T::type_hash(), T::SYMBOL, versions, |rsz| value.to_rsz(rsz))
+    }
+
+    pub fn add_child_arc<T: ToRsz>(&mut self, value: &Arc<T>) -> Result<u32> {
+        let key = Arc::as_ptr(value) as *const ();
+        if let Some(&index) = self.shared_indexs.get(&key) {
+            return Ok(index);
+        }
+        let index = self.add_child(&**value)?;
+        self.shared_indexs.insert(key, index);
+        Ok(index)
+    }
+
+    pub fn add_child_any(&mut self, value: &AnyRsz) -> Result<u32> {
+        let key = Arc::as_ptr(&value.any) as *const ();
+        if let Some(&index) = self.shared_indexs.get(&key) {
+            return Ok(index);
+        }
+        let type_info = &value.type_info;
+        let index = self.add_instance(
+            hash_as_utf8(&type_info.symbol),
+            &type_info.symbol,
+            &type_info.versions_raw,
+            |rsz| (type_info.serializer)(&*value.any, rsz),
+        )?;
+        self.shared_indexs.insert(key, index);
+        Ok(index)
+    }
+
+    // The slot type is the root type of the USER file. It is taken from the base file hints
+    // if available, so that unmodified data is written back identically.
+    pub fn add_extern<T: ExternRoot>(&mut self, path: &str) -> Result<u32> {
+        if let Some(&index) = self.extern_indexs.get(path) {
+            return Ok(index);
+        }
+        let (hash, crc) = if let Some(&TypeDescriptor { hash, crc }) = self.extern_hints.get(path) {
+            (hash, crc)
+        } else {
+            let (symbol, versions) = T::root().with_context(|| {
+                format!(
+                    "Unknown root type for extern {}. Use a typed ExternUser<T>",
+                    path
+                )
+            })?;
+            let hash = hash_as_utf8(symbol);
+            let versions = RSZ_TYPE_MAP
+                .get(&hash)
+                .map_or(versions, |type_info| &type_info.versions_raw);
+            let (crc, _) = self.type_version(hash, symbol, versions)?;
+            (hash, crc)
+        };
+        let index = u32::try_from(self.type_descriptors.len())?;
+        self.type_descriptors.push(TypeDescriptor { hash, crc });
+        self.extern_slots.insert(
+            index,
+            Extern {
+                hash,
+                path: path.to_owned(),
+            },
+        );
+        self.extern_indexs.insert(path.to_owned(), index);
+        Ok(index)
+    }
+
+    #[cfg(test)]
+    pub fn add_root<T: ToRsz>(&mut self, value: &T) -> Result<()> {
+        let index = self.add_child(value)?;
+        self.roots.push(index);
+        Ok(())
+    }
+
+    pub fn add_root_any(&mut self, value: &AnyRsz) -> Result<()> {
+        let index = self.add_child_any(value)?;
+        self.roots.push(index);
+        Ok(())
+    }
+
+    pub fn align(&mut self, align: u64) {
+        self.node.push((align, vec![]));
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn finish(self) -> Rsz {
+        Rsz {
+            roots: self.roots,
+            extern_slots: self.extern_slots,
+            type_descriptors: self.type_descriptors,
+            data: self.data,
+        }
+    }
+}
+
+impl Write for RszSerializer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let (_, data) = self
+            .node
+            .last_mut()
+            .ok_or_else(|| std::io::Error::other("Writing outside of an instance"))?;
+        data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RszDiagnostic {
+    pub symbol: String,
+    pub index: usize,
+    pub offset: u64,
+    pub error: String,
+}
+
+impl std::fmt::Display for RszDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at index {}, offset {:08X}: {}",
+            self.symbol, self.index, self.offset, self.error
+        )
+    }
+}
+
+// An instance that failed to decode in lenient mode
+#[derive(Debug, Serialize)]
+pub struct RawRsz {
+    pub hash: u32,
+    pub crc: u32,
+    pub data: Vec<u8>,
+}
+
+impl RawRsz {
+    fn new_any(hash: u32, crc: u32, data: Vec<u8>) -> AnyRsz {
+        AnyRsz::new(
+            RawRsz { hash, crc, data },
+            TypeInfoRef::Static(&RAW_RSZ_TYPE_INFO),
+        )
+    }
+}
+
+static RAW_RSZ_TYPE_INFO: Lazy<RszTypeInfo> = Lazy::new(|| RszTypeInfo {
+    deserializer: |_, _| bail!("Raw RSZ node can't be deserialized"),
+    to_json: rsz_to_json::<RawRsz>,
+    as_serialize: rsz_as_serialize::<RawRsz>,
+    to_json_typed: |_| bail!("Raw RSZ node can't be written as typed JSON"),
+    from_json: |_, _| bail!("Raw RSZ node can't be read from JSON"),
+    debug: rsz_debug::<RawRsz>,
+    serializer: |_, _| bail!("Raw RSZ node can't be serialized"),
+    versions: HashMap::new(),
+    versions_raw: Cow::Borrowed(&[]),
+    align: 1,
+    skip: |_, _| bail!("Raw RSZ node can't be skipped"),
+    symbol: Cow::Borrowed("<raw>"),
+    #[cfg(test)]
+    sample: None,
+});
+
 #[derive(Clone)]
 pub struct AnyRsz {
-    any: Rc<dyn Any>,
-    type_info: &'static RszTypeInfo,
+    any: Arc<dyn Any + Send + Sync>,
+    type_info: TypeInfoRef,
 }
 
 impl Debug for AnyRsz {
@@ -442,18 +1289,121 @@ impl Debug for AnyRsz {
     }
 }
 
+impl Serialize for AnyRsz {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let visit = dag_visit(Arc::as_ptr(&self.any) as *const ());
+        serialize_dag_node(
+            visit,
+            self.symbol(),
+            (self.type_info.as_serialize)(&*self.any),
+            serializer,
+        )
+    }
+}
+
+struct DagState {
+    // Node index of each decoded instance
+    indexes: HashMap<*const (), usize>,
+    // Number of times each node is written, counted in a first pass
+    counts: HashMap<usize, usize>,
+    counting: bool,
+    written: HashSet<usize>,
+}
+
+thread_local! {
+    // Set while to_json_dag is writing, as nested instances are reached through Serialize
+    static DAG_STATE: RefCell<Option<DagState>> = const { RefCell::new(None) };
+}
+
+enum DagVisit {
+    Untracked,
+    Typed,
+    First(usize),
+    Again(usize),
+}
+
+fn dag_visit(ptr: *const ()) -> DagVisit {
+    DAG_STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let Some(state) = state.as_mut() else {
+            return DagVisit::Untracked;
+        };
+        let Some(&index) = state.indexes.get(&ptr) else {
+            return DagVisit::Typed;
+        };
+        if state.counting {
+            let count = state.counts.entry(index).or_insert(0);
+            *count += 1;
+            // Children of a node are only counted once
+            return if *count == 1 {
+                DagVisit::Typed
+            } else {
+                DagVisit::Again(index)
+            };
+        }
+        if state.counts.get(&index).is_some_and(|&count| count > 1) {
+            if state.written.insert(index) {
+                DagVisit::First(index)
+            } else {
+                DagVisit::Again(index)
+            }
+        } else {
+            DagVisit::Typed
+        }
+    })
+}
+
+fn serialize_dag_node<S: Serializer, T: Serialize + ?Sized>(
+    visit: DagVisit,
+    symbol: &str,
+    value: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    use serde::ser::SerializeMap;
+
+    #[derive(Serialize)]
+    struct Typed<'a, T: ?Sized> {
+        #[serde(rename = "$type")]
+        symbol: &'a str,
+        #[serde(flatten)]
+        value: &'a T,
+    }
+
+    match visit {
+        DagVisit::Untracked => value.serialize(serializer),
+        DagVisit::Typed => Typed { symbol, value }.serialize(serializer),
+        DagVisit::First(id) => {
+            let mut map = serializer.serialize_map(Some(2))?;
+            map.serialize_entry("$id", &id)?;
+            map.serialize_entry("$value", &Typed { symbol, value })?;
+            map.end()
+        }
+        DagVisit::Again(id) => {
+            let mut map = serializer.serialize_map(Some(1))?;
+            map.serialize_entry("$ref", &id)?;
+            map.end()
+        }
+    }
+}
+
 impl AnyRsz {
-    pub fn new<T: Any + Serialize + Debug>(v: T, type_info: &'static RszTypeInfo) -> AnyRsz {
-        let any = Rc::new(v);
+    pub fn new<T: Any + Send + Sync + Serialize + Debug>(v: T, type_info: TypeInfoRef) -> AnyRsz {
+        let any = Arc::new(v);
         AnyRsz { any, type_info }
     }
 
-    pub fn downcast<T: Any>(self) -> Result<Rc<T>> {
-        let symbol = self.type_info.symbol;
+    pub fn downcast<T: Any + Send + Sync>(self) -> Result<Arc<T>> {
         match self.any.downcast() {
             Ok(b) => Ok(b),
             Err(_) => {
-                bail!("Expected {}, found {}", type_name::<T>(), symbol)
+                bail!(
+                    "Expected {}, found {}",
+                    type_name::<T>(),
+                    self.type_info.symbol
+                )
             }
         }
     }
@@ -466,18 +1416,74 @@ impl AnyRsz {
         (self.type_info.to_json)(&*self.any)
     }
 
-    pub fn symbol(&self) -> &'static str {
-        self.type_info.symbol
+    pub fn symbol(&self) -> &str {
+        &self.type_info.symbol
+    }
+
+    // Writes the type symbol as `$type` along with the fields, so that it can be read back by
+    // from_json.
+    pub fn to_json_typed(&self) -> Result<String> {
+        (self.type_info.to_json_typed)(&*self.any)
+    }
+
+    // Reads an instance from a JSON object in the format of to_json_typed or Rsz::to_json_dag.
+    // Instances with `$id` are recorded in shared, so that a `$ref` to them, also from later
+    // roots, is read back as the same instance and written as a single node.
+    pub fn from_json(
+        value: serde_json::Value,
+        shared: &mut HashMap<u64, AnyRsz>,
+    ) -> Result<AnyRsz> {
+        let serde_json::Value::Object(mut map) = value else {
+            bail!("Expected a JSON object for an RSZ instance")
+        };
+        if let Some(id) = map.remove("$ref") {
+            let id = id.as_u64().context("Expected an integer for $ref")?;
+            return shared
+                .get(&id)
+                .cloned()
+                .with_context(|| format!("$ref {} before its $id", id));
+        }
+        if let Some(id) = map.remove("$id") {
+            let id = id.as_u64().context("Expected an integer for $id")?;
+            let value = map.remove("$value").context("Missing $value for $id")?;
+            let node = Self::from_json(value, shared)?;
+            if shared.insert(id, node.clone()).is_some() {
+                bail!("Duplicate $id {}", id)
+            }
+            return Ok(node);
+        }
+        let symbol = match map.remove("$type") {
+            Some(serde_json::Value::String(symbol)) => symbol,
+            _ => bail!("Missing $type for an RSZ instance"),
+        };
+        let type_info = RSZ_TYPE_MAP
+            .get(&hash_as_utf8(&symbol))
+            .with_context(|| format!("Unsupported type {}", symbol))?;
+        (type_info.from_json)(
+            serde_json::Value::Object(map),
+            &TypeInfoRef::Static(type_info),
+        )
+        .with_context(|| format!("Failed to read {} from JSON", symbol))
     }
 }
 
-pub trait FromRsz: Sized {
+pub trait FromRsz: Sized + Send + Sync {
     fn from_rsz(rsz: &mut RszDeserializer) -> Result<Self>;
+    #[cfg(test)]
+    fn sample(sample: RszSample) -> Self;
     const SYMBOL: &'static str;
     const VERSIONS: &'static [(u32, u32)];
+    // Alignment of the first field, which is where an instance starts. 1 if it is not known
+    const ALIGN: u64 = 1;
     fn type_hash() -> u32 {
         hash_as_utf8(Self::SYMBOL)
     }
+    // Reads past an instance without checking its values or the instances it references
+    fn skip(rsz: &mut RszDeserializer) -> Result<()>;
+}
+
+pub trait ToRsz: FromRsz {
+    fn to_rsz(&self, rsz: &mut RszSerializer) -> Result<()>;
 }
 
 pub trait SingletonUser: Sized {
@@ -487,22 +1493,125 @@ pub trait SingletonUser: Sized {
 }
 
 trait FieldFromRsz: Sized {
+    // Alignment of the first byte read
+    const FIELD_ALIGN: u64 = 1;
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self>;
+    #[cfg(test)]
+    fn field_sample(sample: RszSample) -> Self;
+    // Reads past the field without checking its value or the instances it references
+    fn field_skip(rsz: &mut RszDeserializer) -> Result<()> {
+        Self::field_from_rsz(rsz).map(|_| ())
+    }
+}
+
+const fn first_align(aligns: &[u64]) -> u64 {
+    match aligns {
+        [first, ..] => *first,
+        [] => 1,
+    }
+}
+
+/// Parameters to build a sample value of a type for round-trip tests
+#[cfg(test)]
+#[derive(Debug, Clone, Copy)]
+pub struct RszSample {
+    pub version: u32,
+    pub version_hint: Option<u32>,
+    depth: u32,
+}
+
+#[cfg(test)]
+impl RszSample {
+    // Optional and repeated children stop at this depth, so that recursive types terminate
+    const MAX_DEPTH: u32 = 3;
+    pub const EXTERN_PATH: &'static str = "test/extern.user";
+
+    pub fn new(version: u32, version_hint: Option<u32>) -> RszSample {
+        RszSample {
+            version,
+            version_hint,
+            depth: 0,
+        }
+    }
+
+    // Children are sampled in the version that the serializer picks for them
+    pub fn child<T: FromRsz>(self) -> RszSample {
+        let version = if T::VERSIONS.is_empty() {
+            self.version_hint.unwrap_or(0)
+        } else {
+            T::VERSIONS
+                .iter()
+                .map(|&(_, version)| version)
+                .filter(|&version| !self.version_hint.is_some_and(|hint| version > hint))
+                .max()
+                .unwrap_or_else(|| panic!("No version of {} to sample", T::SYMBOL))
+        };
+        RszSample {
+            version,
+            version_hint: self.version_hint,
+            depth: self.depth + 1,
+        }
+    }
+
+    pub fn nested(self) -> bool {
+        self.depth < Self::MAX_DEPTH
+    }
+}
+
+trait FieldToRsz {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()>;
 }
 
 pub struct RszTypeInfo {
-    deserializer: fn(&mut RszDeserializer, type_info: &'static RszTypeInfo) -> Result<AnyRsz>,
+    deserializer: fn(&mut RszDeserializer, type_info: &TypeInfoRef) -> Result<AnyRsz>,
     to_json: fn(&dyn Any) -> Result<String>,
+    as_serialize: fn(&dyn Any) -> &dyn erased_serde::Serialize,
+    to_json_typed: fn(&dyn Any) -> Result<String>,
+    from_json: fn(serde_json::Value, type_info: &TypeInfoRef) -> Result<AnyRsz>,
     debug: fn(&dyn Any, &mut std::fmt::Formatter) -> std::fmt::Result,
+    serializer: fn(&dyn Any, &mut RszSerializer) -> Result<()>,
     versions: HashMap<u32, u32>,
-    pub symbol: &'static str,
+    versions_raw: Cow<'static, [(u32, u32)]>,
+    align: u64,
+    skip: fn(&mut RszDeserializer, type_info: &TypeInfoRef) -> Result<()>,
+    pub symbol: Cow<'static, str>,
+    #[cfg(test)]
+    sample: Option<fn(RszSample) -> AnyRsz>,
+}
+
+// Registered types have static type infos, while types decoded dynamically have ones owned by
+// the RszSchema they are built from
+#[derive(Clone)]
+pub enum TypeInfoRef {
+    Static(&'static RszTypeInfo),
+    Schema(Arc<RszTypeInfo>),
+}
+
+impl Deref for TypeInfoRef {
+    type Target = RszTypeInfo;
+    fn deref(&self) -> &RszTypeInfo {
+        match self {
+            TypeInfoRef::Static(type_info) => type_info,
+            TypeInfoRef::Schema(type_info) => type_info,
+        }
+    }
+}
+
+impl Debug for TypeInfoRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.symbol)
+    }
 }
 
 fn rsz_deserializer<T: 'static + FromRsz + Serialize + Debug>(
     rsz: &mut RszDeserializer,
-    type_info: &'static RszTypeInfo,
+    type_info: &TypeInfoRef,
 ) -> Result<AnyRsz> {
-    Ok(AnyRsz::new(T::from_rsz(rsz)?, type_info))
+    Ok(AnyRsz::new(T::from_rsz(rsz)?, type_info.clone()))
+}
+
+fn rsz_serializer<T: 'static + ToRsz>(any: &dyn Any, rsz: &mut RszSerializer) -> Result<()> {
+    any.downcast_ref::<T>().unwrap().to_rsz(rsz)
 }
 
 fn rsz_to_json<T: 'static + Serialize>(any: &dyn Any) -> Result<String> {
@@ -510,6 +1619,40 @@ fn rsz_to_json<T: 'static + Serialize>(any: &dyn Any) -> Result<String> {
         .context("Failed to convert to json")
 }
 
+fn rsz_as_serialize<T: 'static + Serialize>(any: &dyn Any) -> &dyn erased_serde::Serialize {
+    any.downcast_ref::<T>().unwrap()
+}
+
+fn rsz_to_json_typed<T: 'static + FromRsz + Serialize>(any: &dyn Any) -> Result<String> {
+    #[derive(Serialize)]
+    struct Typed<'a, T> {
+        #[serde(rename = "$type")]
+        symbol: &'static str,
+        #[serde(flatten)]
+        value: &'a T,
+    }
+    serde_json::to_string_pretty(&Typed {
+        symbol: T::SYMBOL,
+        value: any.downcast_ref::<T>().unwrap(),
+    })
+    .context("Failed to convert to json")
+}
+
+fn rsz_from_json<T: 'static + FromRsz + de::DeserializeOwned + Serialize + Debug>(
+    value: serde_json::Value,
+    type_info: &TypeInfoRef,
+) -> Result<AnyRsz> {
+    Ok(AnyRsz::new(T::deserialize(value)?, type_info.clone()))
+}
+
+#[cfg(test)]
+fn rsz_sample<T: 'static + FromRsz + Serialize + Debug>(sample: RszSample) -> AnyRsz {
+    AnyRsz::new(
+        T::sample(sample),
+        TypeInfoRef::Static(&RSZ_TYPE_MAP[&T::type_hash()]),
+    )
+}
+
 fn rsz_debug<T: 'static + Debug>(any: &dyn Any, f: &mut std::fmt::Formatter) -> std::fmt::Result {
     std::fmt::Debug::fmt(any.downcast_ref::<T>().unwrap(), f)
 }
@@ -520,11 +1663,41 @@ pub trait FromUser: Sized {
 
 impl<T: 'static + FromRsz> FromUser for T {
     fn from_any(any: AnyRsz) -> Result<Self> {
-        Rc::try_unwrap(any.downcast()?).map_err(|_| anyhow!("Shared user node"))
+        Arc::try_unwrap(any.downcast()?)
+            .map_err(|_| anyhow!("Shared user node. Shared nodes should be loaded as Arc<T>"))
     }
 }
 
-#[derive(Debug, Serialize, Clone)]
+impl<T: 'static + FromRsz> FromUser for Arc<T> {
+    fn from_any(any: AnyRsz) -> Result<Self> {
+        any.downcast()
+    }
+}
+
+/// Root type of a USER file referenced by ExternUser, if known
+pub trait ExternRoot {
+    fn root() -> Option<(&'static str, &'static [(u32, u32)])>;
+}
+
+impl ExternRoot for () {
+    fn root() -> Option<(&'static str, &'static [(u32, u32)])> {
+        None
+    }
+}
+
+impl<T: FromRsz> ExternRoot for T {
+    fn root() -> Option<(&'static str, &'static [(u32, u32)])> {
+        Some((T::SYMBOL, T::VERSIONS))
+    }
+}
+
+impl<T: FromRsz> ExternRoot for Arc<T> {
+    fn root() -> Option<(&'static str, &'static [(u32, u32)])> {
+        Some((T::SYMBOL, T::VERSIONS))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum ExternUser<T> {
     Path(String),
     Loaded(T),
@@ -563,30 +1736,78 @@ impl<T: FromUser> ExternUser<T> {
 }
 
 impl<T> FieldFromRsz for ExternUser<T> {
+    const FIELD_ALIGN: u64 = 4;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(4)?;
         let extern_path = rsz.get_extern()?.to_owned();
         Ok(ExternUser::Path(extern_path))
     }
+
+    #[cfg(test)]
+    fn field_sample(_: RszSample) -> Self {
+        ExternUser::Path(RszSample::EXTERN_PATH.to_owned())
+    }
 }
 
 impl<T> FieldFromRsz for Option<ExternUser<T>> {
+    const FIELD_ALIGN: u64 = 4;
+
     fn field_from_rsz(rsz: &mut RszDeserializer) -> Result<Self> {
         rsz.cursor.seek_align_up(4)?;
         let extern_path = rsz.get_extern_opt()?;
         Ok(extern_path.map(|p| ExternUser::Path(p.to_owned())))
     }
+
+    #[cfg(test)]
+    fn field_sample(sample: RszSample) -> Self {
+        Some(ExternUser::field_sample(sample))
+    }
 }
 
-pub fn register<T: 'static + FromRsz + Serialize + Debug>(m: &mut HashMap<u32, RszTypeInfo>) {
+impl<T: ExternRoot> FieldToRsz for ExternUser<T> {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        let ExternUser::Path(path) = self else {
+            bail!("Cannot serialize a loaded ExternUser")
+        };
+        rsz.align(4);
+        let index = rsz.add_extern::<T>(path)?;
+        rsz.write_u32(index)
+    }
+}
+
+impl<T: ExternRoot> FieldToRsz for Option<ExternUser<T>> {
+    fn field_to_rsz(&self, rsz: &mut RszSerializer) -> Result<()> {
+        match self {
+            Some(extern_user) => extern_user.field_to_rsz(rsz),
+            None => {
+                rsz.align(4);
+                rsz.write_u32(0)
+            }
+        }
+    }
+}
+
+pub fn register<T: 'static + ToRsz + Serialize + de::DeserializeOwned + Debug>(
+    m: &mut HashMap<u32, RszTypeInfo>,
+) {
     let hash = T::type_hash();
 
     let package = RszTypeInfo {
         deserializer: rsz_deserializer::<T>,
         to_json: rsz_to_json::<T>,
+        as_serialize: rsz_as_serialize::<T>,
+        to_json_typed: rsz_to_json_typed::<T>,
+        from_json: rsz_from_json::<T>,
         debug: rsz_debug::<T>,
+        serializer: rsz_serializer::<T>,
         versions: T::VERSIONS.iter().copied().collect(),
-        symbol: T::SYMBOL,
+        versions_raw: Cow::Borrowed(T::VERSIONS),
+        align: T::ALIGN,
+        skip: |rsz, _| T::skip(rsz),
+        symbol: Cow::Borrowed(T::SYMBOL),
+        #[cfg(test)]
+        sample: Some(rsz_sample::<T>),
     };
 
     let old = m.insert(hash, package);
@@ -595,6 +1816,58 @@ pub fn register<T: 'static + FromRsz + Serialize + Debug>(m: &mut HashMap<u32, R
     }
 }
 
+// symbol -> CRC -> version, to be merged into RSZ_TYPE_MAP
+static RSZ_EXTRA_VERSIONS: OnceCell<HashMap<u32, Vec<(u32, u32)>>> = OnceCell::new();
+
+/// Loads a versions file, in the format of `{"symbol": {"CRC in hex": version}}`.
+/// This must be called before RSZ_TYPE_MAP is accessed.
+pub fn load_versions(path: &str) -> Result<()> {
+    if Lazy::get(&RSZ_TYPE_MAP).is_some() {
+        bail!("RSZ_TYPE_MAP is already initialized")
+    }
+    let file: BTreeMap<String, BTreeMap<String, u32>> =
+        serde_json::from_reader(std::io::BufReader::new(std::fs::File::open(path)?))
+            .with_context(|| format!("Failed to parse versions file {path}"))?;
+    let mut versions = HashMap::new();
+    for (symbol, crcs) in file {
+        let crcs = crcs
+            .into_iter()
+            .map(|(crc, version)| {
+                let crc = u32::from_str_radix(crc.trim_start_matches("0x"), 16)
+                    .with_context(|| format!("Invalid CRC {crc} for {symbol}"))?;
+                Ok((crc, version))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        versions.insert(hash_as_utf8(&symbol), crcs);
+    }
+    if RSZ_EXTRA_VERSIONS.set(versions).is_err() {
+        bail!("Versions file is already loaded")
+    }
+    Ok(())
+}
+
+/// Saves all known versions, plus `new_crcs` assigned with `version`, as a versions file.
+pub fn save_versions(path: &str, new_crcs: &BTreeMap<&str, u32>, version: u32) -> Result<()> {
+    let mut file: BTreeMap<&str, BTreeMap<String, u32>> = BTreeMap::new();
+    for type_info in RSZ_TYPE_MAP.values() {
+        if type_info.versions.is_empty() {
+            continue;
+        }
+        let crcs = file.entry(&type_info.symbol).or_default();
+        for (crc, version) in &type_info.versions {
+            crcs.insert(format!("{crc:08X}"), *version);
+        }
+    }
+    for (&symbol, &crc) in new_crcs {
+        file.entry(symbol)
+            .or_default()
+            .entry(format!("{crc:08X}"))
+            .or_insert(version);
+    }
+    std::fs::write(path, serde_json::to_string_pretty(&file)?)?;
+    Ok(())
+}
+
 pub static RSZ_TYPE_MAP: Lazy<HashMap<u32, RszTypeInfo>> = Lazy::new(|| {
     let mut m = HashMap::new();
 
@@ -1333,5 +2606,416 @@ pub static RSZ_TYPE_MAP: Lazy<HashMap<u32, RszTypeInfo>> = Lazy::new(|| {
     m.extend(ec::ec_type_map());
     m.extend(fg::fg_type_map());
 
+    if let Some(extra_versions) = RSZ_EXTRA_VERSIONS.get() {
+        for (hash, crcs) in extra_versions {
+            // Types unknown to this build can't make use of the versions
+            let Some(type_info) = m.get_mut(hash) else {
+                continue;
+            };
+            let versions = type_info.versions_raw.to_mut();
+            for &(crc, version) in crcs {
+                type_info.versions.insert(crc, version);
+                match versions.iter_mut().find(|(c, _)| *c == crc) {
+                    Some(entry) => entry.1 = version,
+                    None => versions.push((crc, version)),
+                }
+            }
+        }
+    }
+
     m
 });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_registered_types() {
+        let extern_hash = hash_as_utf8("test.ExternData");
+        let mut errors = vec![];
+        let mut checked = 0;
+        for (&hash, type_info) in RSZ_TYPE_MAP.iter() {
+            // The root is pinned to each CRC, and children take their latest version
+            let versions = if type_info.versions_raw.is_empty() {
+                &[(0, 0)]
+            } else {
+                &*type_info.versions_raw
+            };
+            for &(crc, version) in versions {
+                let value = (type_info.sample.unwrap())(RszSample::new(version, None));
+                let result = (|| {
+                    let mut serializer = RszSerializer::new(None);
+                    // Unversioned types read back with any CRC
+                    for (&hash, type_info) in RSZ_TYPE_MAP.iter() {
+                        if type_info.versions_raw.is_empty() {
+                            serializer.crc_hints.insert(hash, 0);
+                        }
+                    }
+                    serializer.crc_hints.insert(hash, crc);
+                    serializer.extern_hints.insert(
+                        RszSample::EXTERN_PATH.to_owned(),
+                        descriptor(extern_hash, 0),
+                    );
+                    serializer.add_root_any(&value)?;
+                    let mut buf = Cursor::new(vec![]);
+                    serializer.finish().write(&mut buf)?;
+                    let rsz = Rsz::new(Cursor::new(buf.into_inner()), 0)?;
+                    let roots = rsz.deserialize(None)?;
+                    if serde_json::to_string(&roots[0])? != serde_json::to_string(&value)? {
+                        bail!("Value mismatch")
+                    }
+                    rsz.verify_round_trip(&roots, None)?;
+
+                    // Skipping an instance should end where decoding it does
+                    let mut context = DecodeContext::new(None, None, false);
+                    let end = rsz.plan_nodes(&mut context)?;
+                    let starts: Vec<_> = context
+                        .plan
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(index, plan)| match plan {
+                            NodePlan::Decoded { start, .. } => Some((index, *start)),
+                            _ => None,
+                        })
+                        .collect();
+                    for (i, &(index, start)) in starts.iter().enumerate() {
+                        let decoded_end = starts.get(i + 1).map_or(end, |&(_, next)| next);
+                        let skipped_end = rsz.skip_node(&mut context, index, start)?;
+                        if skipped_end != decoded_end {
+                            bail!("Skipped node {index} to {skipped_end:X} instead of {decoded_end:X}")
+                        }
+                        // And so should starting from the alignment of the instance
+                        let aligned = align_up(start, rsz.layout(&context, index)?.0.align);
+                        if rsz.skip_node(&mut context, index, aligned)? != decoded_end {
+                            bail!("Node {index} is not aligned to {aligned:X}")
+                        }
+                    }
+                    Ok(())
+                })();
+                match result {
+                    Ok(()) => checked += 1,
+                    Err(e) => errors.push(format!("{} {crc:08X}: {e:#}", type_info.symbol)),
+                }
+            }
+        }
+        assert!(errors.is_empty(), "{errors:#?}");
+        assert!(checked >= RSZ_TYPE_MAP.len());
+    }
+
+    #[test]
+    fn dynamic_field_order() {
+        let field = |name: &str| RszSchemaField {
+            name: name.to_owned(),
+            type_name: "System.UInt32".to_owned(),
+            kind: RszFieldKind::U32,
+        };
+        let new_schema = |crc| {
+            let mut types = BTreeMap::new();
+            types.insert(
+                "test.Dynamic".to_owned(),
+                RszSchemaType {
+                    crc,
+                    fields: vec![field("_Z"), field("_A")],
+                },
+            );
+            RszSchema::new(types)
+        };
+        let schema = new_schema(0x12345678);
+        let hash = hash_as_utf8("test.Dynamic");
+
+        // Type infos are built once by the schema, and only match the CRC it describes
+        let (Some(TypeInfoRef::Schema(a)), Some(TypeInfoRef::Schema(b))) = (
+            schema.type_info(hash, 0x12345678),
+            schema.type_info(hash, 0x12345678),
+        ) else {
+            panic!("Missing dynamic type info")
+        };
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(schema.type_info(hash, 0x87654321).is_none());
+
+        // Schemas of other versions keep their own type infos
+        let other = new_schema(0x87654321);
+        assert_eq!(
+            other.type_info(hash, 0x87654321).unwrap().versions_raw[..],
+            [(0x87654321, 0)]
+        );
+        assert!(schema.type_info(hash, 0x12345678).is_some());
+
+        let rsz = Rsz {
+            roots: vec![1],
+            extern_slots: HashMap::new(),
+            type_descriptors: vec![
+                TypeDescriptor { hash: 0, crc: 0 },
+                TypeDescriptor {
+                    hash,
+                    crc: 0x12345678,
+                },
+            ],
+            data: [1u32, 2].iter().flat_map(|v| v.to_le_bytes()).collect(),
+        };
+        let roots = rsz.deserialize_with_schema(None, Some(&schema)).unwrap();
+        assert_eq!(
+            serde_json::to_string(&roots[0]).unwrap(),
+            r#"{"_Z":1,"_A":2}"#
+        );
+    }
+
+    #[test]
+    fn write_children_and_externs() {
+        let kinds = |path: &str| RandomMysteryDifficultyRateListDataDifficultyDataKinds {
+            nando_ref_table: ExternUser::Path(path.to_owned()),
+        };
+        let value = RandomMysteryDifficultyRateListDataDifficultyData {
+            nand_kinds_data: vec![kinds("a.user"), kinds("b.user"), kinds("a.user")],
+        };
+
+        // Extern types are derived from ExternUser<T> without a base file
+        let mut serializer = RszSerializer::new(None);
+        serializer.add_root(&value).unwrap();
+        let rsz = serializer.finish();
+        let mut buf = Cursor::new(vec![]);
+        rsz.write(&mut buf).unwrap();
+        let bytes = buf.into_inner();
+
+        let rsz = Rsz::new(Cursor::new(&bytes), 0).unwrap();
+        assert_eq!(rsz.extern_slots.len(), 2);
+        for slot in rsz.extern_slots.keys() {
+            assert_eq!(
+                rsz.type_descriptors[*slot as usize],
+                TypeDescriptor {
+                    hash: RandomMysteryDifficultyRateKindData::type_hash(),
+                    crc: 0xF436E0EE,
+                }
+            );
+        }
+
+        let root: RandomMysteryDifficultyRateListDataDifficultyData =
+            rsz.deserialize_single(None).unwrap();
+        let paths: Vec<_> = root
+            .nand_kinds_data
+            .iter()
+            .map(|kinds| match &kinds.nando_ref_table {
+                ExternUser::Path(path) => path.as_str(),
+                ExternUser::Loaded(_) => unreachable!(),
+            })
+            .collect();
+        assert_eq!(paths, ["a.user", "b.user", "a.user"]);
+
+        let mut serializer = RszSerializer::new(None);
+        serializer.add_root(&root).unwrap();
+        let mut buf = Cursor::new(vec![]);
+        serializer.finish().write(&mut buf).unwrap();
+        assert_eq!(buf.into_inner(), bytes);
+    }
+
+    fn table_data(first: u16) -> Vec<u8> {
+        (first..first + 6).flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn descriptor(hash: u32, crc: u32) -> TypeDescriptor {
+        TypeDescriptor { hash, crc }
+    }
+
+    #[test]
+    fn lenient_resumes_after_failed_instance() {
+        let unknown = hash_as_utf8("test.Unknown");
+        let garbage = [0xFF; 6];
+        let mut data = table_data(0x1110);
+        data.extend(garbage);
+        data.extend(table_data(0x2220));
+        data.extend([0; 2]);
+        for v in [2, 1, 3] {
+            data.extend(u32::to_le_bytes(v));
+        }
+        let rsz = Rsz {
+            roots: vec![4],
+            extern_slots: HashMap::new(),
+            type_descriptors: vec![
+                descriptor(0, 0),
+                descriptor(RefTableData::type_hash(), 0x56E82E4A),
+                descriptor(unknown, 0),
+                descriptor(RefTableData::type_hash(), 0x56E82E4A),
+                descriptor(RefDifficultyTable::type_hash(), 0xCA57CD6D),
+            ],
+            data,
+        };
+        assert!(rsz.deserialize(None).is_err());
+
+        let (roots, diagnostics) = rsz.deserialize_lenient(None).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].index, 2);
+        assert_eq!(diagnostics[0].offset, 12);
+        let root = roots[0].downcast_ref::<RefDifficultyTable>().unwrap();
+        let firsts: Vec<_> = root.ref_rate_table.iter().map(|t| t.vital_tbl_no).collect();
+        assert_eq!(firsts, [0x1110, 0x2220]);
+        assert_eq!(root.ref_rate_table[1].multi_tbl_no, 0x2225);
+    }
+
+    #[test]
+    fn lenient_resync_cost() {
+        // Three unknown instances in a row, then a table laid out where only its real start
+        // decodes along with the instance after it
+        let unknowns = ["test.Unknown0", "test.Unknown1", "test.Unknown2"].map(hash_as_utf8);
+        let mut data = table_data(0x1110);
+        data.extend([0xFF; 5 + 7 + 6]);
+        data.extend(table_data(0x2220));
+        data.extend([0; 2]);
+        for v in [2, 1, 5] {
+            data.extend(u32::to_le_bytes(v));
+        }
+        let rsz = Rsz {
+            roots: vec![6],
+            extern_slots: HashMap::new(),
+            type_descriptors: vec![
+                descriptor(0, 0),
+                descriptor(RefTableData::type_hash(), 0x56E82E4A),
+                descriptor(unknowns[0], 0),
+                descriptor(unknowns[1], 0),
+                descriptor(unknowns[2], 0),
+                descriptor(RefTableData::type_hash(), 0x56E82E4A),
+                descriptor(RefDifficultyTable::type_hash(), 0xCA57CD6D),
+            ],
+            data,
+        };
+
+        let mut context = DecodeContext::new(None, None, true);
+        let end = rsz.plan_nodes(&mut context).unwrap();
+        assert_eq!(end, 56);
+        let diagnostics = context.diagnostics.unwrap();
+        let offsets: Vec<_> = diagnostics.iter().map(|d| (d.index, d.offset)).collect();
+        assert_eq!(offsets, [(2, 12), (3, 12), (4, 12)]);
+        // The unknown instances are resynced once together. The ten offsets from 12 to 30
+        // aligned for the table are tried by decoding the table and the instance after it.
+        // On top of that, one skip, one restore of the table taken by value while probing,
+        // and the two table instances and the failed instance in the main pass
+        assert_eq!(context.decodes, 26);
+        let NodeSlot::Instance(raw) = &context.node_buf[2] else {
+            panic!()
+        };
+        assert_eq!(raw.downcast_ref::<RawRsz>().unwrap().data, [0xFF; 18]);
+        let NodeSlot::Instance(root) = &context.node_buf[6] else {
+            panic!()
+        };
+        let root = root.downcast_ref::<RefDifficultyTable>().unwrap();
+        let firsts: Vec<_> = root.ref_rate_table.iter().map(|t| t.vital_tbl_no).collect();
+        assert_eq!(firsts, [0x1110, 0x2220]);
+    }
+
+    #[test]
+    fn lenient_keeps_bytes_of_dependent_instances() {
+        let unknown = hash_as_utf8("test.Unknown");
+        let mut data = vec![0xFF; 6];
+        data.extend([0; 2]);
+        for v in [1, 1] {
+            data.extend(u32::to_le_bytes(v));
+        }
+        data.extend(table_data(0x3330));
+        let rsz = Rsz {
+            roots: vec![1, 2, 3],
+            extern_slots: HashMap::new(),
+            type_descriptors: vec![
+                descriptor(0, 0),
+                descriptor(unknown, 0),
+                descriptor(RefDifficultyTable::type_hash(), 0xCA57CD6D),
+                descriptor(RefTableData::type_hash(), 0x56E82E4A),
+            ],
+            data: data.clone(),
+        };
+
+        let (roots, diagnostics) = rsz.deserialize_lenient(None).unwrap();
+        let indexes: Vec<_> = diagnostics.iter().map(|d| d.index).collect();
+        assert_eq!(indexes, [1, 2]);
+        assert_eq!(diagnostics[0].symbol, format!("{unknown:08X}"));
+        assert_eq!(diagnostics[1].symbol, RefDifficultyTable::SYMBOL);
+        // The dependent instance is skipped by its layout, which tells where the unknown one ends
+        let unknown_raw = roots[0].downcast_ref::<RawRsz>().unwrap();
+        let raw = roots[1].downcast_ref::<RawRsz>().unwrap();
+        assert_eq!(raw.hash, RefDifficultyTable::type_hash());
+        assert_eq!(unknown_raw.data, data[..8]);
+        assert_eq!(raw.data, data[8..16]);
+        let table = roots[2].downcast_ref::<RefTableData>().unwrap();
+        assert_eq!(table.vital_tbl_no, 0x3330);
+    }
+
+    #[test]
+    fn dag_json_by_node_index() {
+        let field = |name: &str, kind| RszSchemaField {
+            name: name.to_owned(),
+            type_name: "x".to_owned(),
+            kind,
+        };
+        let mut types = BTreeMap::new();
+        types.insert(
+            "test.Child".to_owned(),
+            RszSchemaType {
+                crc: 1,
+                fields: vec![field("_V", RszFieldKind::U32)],
+            },
+        );
+        types.insert(
+            "test.Parent".to_owned(),
+            RszSchemaType {
+                crc: 2,
+                fields: vec![
+                    field("_A", RszFieldKind::Object),
+                    field("_B", RszFieldKind::Object),
+                    field("_C", RszFieldKind::Object),
+                ],
+            },
+        );
+        let schema = RszSchema::new(types);
+        let rsz = Rsz {
+            roots: vec![3],
+            extern_slots: HashMap::new(),
+            type_descriptors: vec![
+                descriptor(0, 0),
+                descriptor(hash_as_utf8("test.Child"), 1),
+                descriptor(hash_as_utf8("test.Child"), 1),
+                descriptor(hash_as_utf8("test.Parent"), 2),
+            ],
+            data: [7u32, 8, 2, 1, 2]
+                .iter()
+                .flat_map(|v| v.to_le_bytes())
+                .collect(),
+        };
+
+        let json = rsz.to_json_dag(None, Some(&schema)).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json[0]).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "$type": "test.Parent",
+                "_A": {"$id": 2, "$value": {"$type": "test.Child", "_V": 8}},
+                "_B": {"$type": "test.Child", "_V": 7},
+                "_C": {"$ref": 2},
+            })
+        );
+    }
+
+    #[test]
+    fn from_json_resolves_references() {
+        let table = serde_json::json!({
+            "$type": RefTableData::SYMBOL,
+            "vital_tbl_no": 1,
+            "attack_tbl_no": 2,
+            "parts_tbl_no": 3,
+            "core_tbl_no": 4,
+            "other_tbl_no": 5,
+            "multi_tbl_no": 6,
+        });
+        let mut shared = HashMap::new();
+        let first =
+            AnyRsz::from_json(serde_json::json!({"$id": 1, "$value": table}), &mut shared).unwrap();
+        let second = AnyRsz::from_json(serde_json::json!({"$ref": 1}), &mut shared).unwrap();
+        assert!(Arc::ptr_eq(&first.any, &second.any));
+        assert!(AnyRsz::from_json(serde_json::json!({"$ref": 2}), &mut shared).is_err());
+
+        let mut serializer = RszSerializer::new(None);
+        serializer.add_root_any(&first).unwrap();
+        serializer.add_root_any(&second).unwrap();
+        let rsz = serializer.finish();
+        assert_eq!(rsz.roots, [1, 1]);
+        assert_eq!(rsz.type_descriptors.len(), 2);
+    }
+}
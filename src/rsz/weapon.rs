@@ -727,11 +727,11 @@ rsz_struct! {
         pub reload: i32, // snow.data.GameItemEnum.Reload
         pub recoil: i32, // snow.data.GameItemEnum.Recoil
         pub kakusan_type: KakusanType,
-        #[serde(serialize_with = "ser_arr")]
+        #[serde(serialize_with = "ser_arr", deserialize_with = "de_arr")]
         pub bullet_equip_flag_list: [bool; 52],
-        #[serde(serialize_with = "ser_arr")]
+        #[serde(serialize_with = "ser_arr", deserialize_with = "de_arr")]
         pub bullet_num_list: [u32; 52],
-        #[serde(serialize_with = "ser_arr")]
+        #[serde(serialize_with = "ser_arr", deserialize_with = "de_arr")]
         pub bullet_type_list: [ShootType; 52],
     }
 }
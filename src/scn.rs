@@ -1,17 +1,18 @@
+use crate::align::*;
 use crate::file_ext::*;
 use crate::pak::*;
 use crate::rsz;
 use crate::rsz::Rsz;
 use crate::user::UserChild;
 use anyhow::{anyhow, bail, Context, Result};
+use rayon::prelude::*;
 use std::collections::BTreeSet;
 use std::collections::HashMap;
-use std::io::{Cursor, Read, Seek};
-use std::rc::Rc;
+use std::io::{Cursor, Read, Seek, Write};
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct ScnGameObject {
-    #[allow(dead_code)]
     guid: rsz::Guid,
     object_index: u32,
     parent_index: Option<u32>, // could be a game object or a folder
@@ -199,7 +200,91 @@ impl Scn {
         })
     }
 
-    pub fn dump(&self) {
+    pub fn write<F: Write + Seek>(&self, mut file: F) -> Result<()> {
+        let folder_list_offset = 0x40 + u64::try_from(self.game_objects.len())? * 0x20;
+        let resource_list_offset = align_up(
+            folder_list_offset + u64::try_from(self.folders.len())? * 8,
+            16,
+        );
+        let prefab_list_offset = align_up(
+            resource_list_offset + u64::try_from(self.resource_names.len())? * 8,
+            16,
+        );
+        let child_list_offset = align_up(
+            prefab_list_offset + u64::try_from(self.prefab_paths.len())? * 8,
+            16,
+        );
+        let mut string_offset = child_list_offset + u64::try_from(self.children.len())? * 0x10;
+        let mut resource_name_offsets = vec![];
+        for name in &self.resource_names {
+            resource_name_offsets.push(string_offset);
+            string_offset += u64::try_from(name.encode_utf16().count() + 1)? * 2;
+        }
+        let mut prefab_offsets = vec![];
+        for path in &self.prefab_paths {
+            prefab_offsets.push(string_offset);
+            string_offset += u64::try_from(path.encode_utf16().count() + 1)? * 2;
+        }
+        let mut child_name_offsets = vec![];
+        for child in &self.children {
+            child_name_offsets.push(string_offset);
+            string_offset += u64::try_from(child.name.encode_utf16().count() + 1)? * 2;
+        }
+        let rsz_offset = align_up(string_offset, 16);
+
+        file.write_magic(b"SCN\0")?;
+        file.write_u32(u32::try_from(self.game_objects.len())?)?;
+        file.write_u32(u32::try_from(self.resource_names.len())?)?;
+        file.write_u32(u32::try_from(self.folders.len())?)?;
+        file.write_u32(u32::try_from(self.prefab_paths.len())?)?;
+        file.write_u32(u32::try_from(self.children.len())?)?;
+        file.write_u64(folder_list_offset)?;
+        file.write_u64(resource_list_offset)?;
+        file.write_u64(prefab_list_offset)?;
+        file.write_u64(child_list_offset)?;
+        file.write_u64(rsz_offset)?;
+
+        for game_object in &self.game_objects {
+            file.write_all(&game_object.guid.bytes)?;
+            file.write_u32(game_object.object_index)?;
+            file.write_u32(game_object.parent_index.unwrap_or(0xFFFFFFFF))?;
+            file.write_u32(game_object.component_count)?;
+            file.write_u32(game_object.prefab_index.unwrap_or(0xFFFFFFFF))?;
+        }
+
+        for folder in &self.folders {
+            file.write_u32(folder.folder_object_index)?;
+            file.write_u32(folder.parent_index.unwrap_or(0xFFFFFFFF))?;
+        }
+
+        file.write_align_up(16)?;
+        for offset in resource_name_offsets {
+            file.write_u64(offset)?;
+        }
+        file.write_align_up(16)?;
+        for offset in prefab_offsets {
+            file.write_u64(offset)?;
+        }
+        file.write_align_up(16)?;
+        for (child, offset) in self.children.iter().zip(child_name_offsets) {
+            file.write_u32(child.hash)?;
+            file.write_u32(0)?;
+            file.write_u64(offset)?;
+        }
+        for name in &self.resource_names {
+            file.write_u16str(name)?;
+        }
+        for path in &self.prefab_paths {
+            file.write_u16str(path)?;
+        }
+        for child in &self.children {
+            file.write_u16str(&child.name)?;
+        }
+        file.write_align_up(16)?;
+        self.rsz.write(file)
+    }
+
+    pub fn dump(&self, schema: Option<&rsz::RszSchema>) {
         println!("Game objects:");
         for n in &self.game_objects {
             println!(
@@ -241,11 +326,11 @@ impl Scn {
             println!("{i:4} -> {root:4}")
         }
         println!();
-        match self.rsz.deserialize(None) {
-            Ok(objects) => {
-                for (i, o) in objects.into_iter().enumerate() {
+        match self.rsz.to_json_dag(None, schema) {
+            Ok(jsons) => {
+                for (i, json) in jsons.into_iter().enumerate() {
                     println!("== {i} ==");
-                    println!("{}", o.to_json().unwrap());
+                    println!("{json}");
                 }
             }
             Err(e) => {
@@ -255,7 +340,7 @@ impl Scn {
                     let hash = type_descriptor.hash;
                     let symbol = rsz::RSZ_TYPE_MAP
                         .get(&hash)
-                        .map(|t| t.symbol)
+                        .map(|t| &*t.symbol)
                         .unwrap_or_default();
                     println!(
                         " [{}] - {:08X}, {:08X} - {}",
@@ -272,7 +357,7 @@ impl Scn {
 pub struct GameObject {
     pub object: rsz::GameObject,
     pub components: Vec<rsz::AnyRsz>,
-    pub prefab: Option<Rc<String>>,
+    pub prefab: Option<Arc<String>>,
     pub children: Vec<GameObject>,
 }
 
@@ -378,19 +463,19 @@ pub struct Scene {
 }
 
 impl Scene {
-    pub fn new<F: Read + Seek>(pak: &mut PakReader<F>, path: &str) -> Result<Scene> {
+    pub fn new<F: Read + Seek + Send>(pak: &PakReader<F>, path: &str) -> Result<Scene> {
         let index = pak.find_file(path)?;
         let content = pak.read_file(index)?;
         let scn = Scn::new(Cursor::new(content))?;
         let mut data: Vec<Option<rsz::AnyRsz>> =
             scn.rsz.deserialize(None)?.into_iter().map(Some).collect();
-        let prefabs: Vec<Rc<String>> = scn.prefab_paths.into_iter().map(Rc::new).collect();
+        let prefabs: Vec<Arc<String>> = scn.prefab_paths.into_iter().map(Arc::new).collect();
 
         let mut orphans: HashMap<Option<u32>, Vec<GameObject>> = HashMap::new();
         let mut orphan_folders: HashMap<Option<u32>, Vec<Folder>> = HashMap::new();
 
         for go in scn.game_objects.into_iter().rev() {
-            let object: Rc<rsz::GameObject> = data
+            let object: Arc<rsz::GameObject> = data
                 .get_mut(usize::try_from(go.object_index)?)
                 .context("game object index out of bound")?
                 .take()
@@ -398,7 +483,7 @@ impl Scene {
                 .downcast()
                 .context("GameObject type mismatch")?;
             let object: rsz::GameObject =
-                Rc::try_unwrap(object).map_err(|_| anyhow!("Shared node"))?;
+                Arc::try_unwrap(object).map_err(|_| anyhow!("Shared node"))?;
             let components: Vec<rsz::AnyRsz> = (go.object_index + 1
                 ..=go.object_index + go.component_count)
                 .map(|i| {
@@ -408,9 +493,9 @@ impl Scene {
                         .context("component data already taken")
                 })
                 .collect::<Result<_>>()?;
-            let prefab: Option<Rc<String>> = go
+            let prefab: Option<Arc<String>> = go
                 .prefab_index
-                .map(|i| -> Result<Rc<String>> {
+                .map(|i| -> Result<Arc<String>> {
                     Ok(prefabs
                         .get(usize::try_from(i)?)
                         .context("prefab index out of bound")?
@@ -438,19 +523,35 @@ impl Scene {
                 .push(game_object);
         }
 
+        // Subscenes are independent files, so they are loaded in parallel
+        let subscene_paths = scn
+            .folders
+            .iter()
+            .filter_map(|f| {
+                let folder = data
+                    .get(usize::try_from(f.folder_object_index).ok()?)?
+                    .as_ref()?
+                    .downcast_ref::<rsz::Folder>()?;
+                let path = folder.path.as_ref().filter(|p| !p.is_empty())?;
+                Some((f.folder_object_index, path.clone()))
+            })
+            .collect::<Vec<_>>();
+        let mut subscenes: HashMap<u32, Result<Scene>> = subscene_paths
+            .into_par_iter()
+            .map(|(index, path)| (index, Scene::new(pak, &path)))
+            .collect();
+
         for f in scn.folders.into_iter().rev() {
-            let folder: Rc<rsz::Folder> = data
+            let folder: Arc<rsz::Folder> = data
                 .get_mut(usize::try_from(f.folder_object_index)?)
                 .context("folder index out of bound")?
                 .take()
                 .context("folder data already taken")?
                 .downcast()
                 .context("Folder type mismatch")?;
-            let folder: rsz::Folder = Rc::try_unwrap(folder).map_err(|_| anyhow!("Shared node"))?;
-            let subscene = folder
-                .path
-                .as_ref()
-                .and_then(|p| (!p.is_empty()).then(|| Scene::new(pak, p)));
+            let folder: rsz::Folder =
+                Arc::try_unwrap(folder).map_err(|_| anyhow!("Shared node"))?;
+            let subscene = subscenes.remove(&f.folder_object_index);
             let children = orphans.remove(&Some(f.folder_object_index)).map_or_else(
                 Vec::new,
                 |mut children: Vec<GameObject>| {
@@ -532,3 +633,107 @@ impl Scene {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rsz::{RefTableData, RszSerializer};
+
+    fn table(first: u16) -> RefTableData {
+        RefTableData {
+            vital_tbl_no: first,
+            attack_tbl_no: first + 1,
+            parts_tbl_no: first + 2,
+            core_tbl_no: first + 3,
+            other_tbl_no: first + 4,
+            multi_tbl_no: first + 5,
+        }
+    }
+
+    fn tables_rsz(firsts: &[u16]) -> Rsz {
+        let mut serializer = RszSerializer::new(None);
+        for &first in firsts {
+            serializer.add_root(&table(first)).unwrap();
+        }
+        serializer.finish()
+    }
+
+    // One game object with one component and a prefab, one folder, one resource and one child,
+    // laid out by hand
+    fn sample() -> Vec<u8> {
+        let mut file = Cursor::new(vec![]);
+        file.write_magic(b"SCN\0").unwrap();
+        for count in [1, 1, 1, 1, 1] {
+            file.write_u32(count).unwrap();
+        }
+        for offset in [0x60, 0x70, 0x80, 0x90, 0xD0] {
+            file.write_u64(offset).unwrap();
+        }
+        file.write_all(&std::array::from_fn::<u8, 16, _>(|i| i as u8))
+            .unwrap();
+        for v in [0, 0xFFFFFFFF, 1, 0] {
+            file.write_u32(v).unwrap();
+        }
+        file.write_u32(2).unwrap();
+        file.write_u32(0xFFFFFFFF).unwrap();
+        file.write_u64(0).unwrap();
+        file.write_u64(0xA0).unwrap();
+        file.write_u64(0).unwrap();
+        file.write_u64(0xAC).unwrap();
+        file.write_u64(0).unwrap();
+        file.write_u32(0x12345678).unwrap();
+        file.write_u32(0).unwrap();
+        file.write_u64(0xB8).unwrap();
+        file.write_u16str("a.tex").unwrap();
+        file.write_u16str("c.pfb").unwrap();
+        file.write_u16str("b.user").unwrap();
+        file.write_all(&[0; 10]).unwrap();
+        assert_eq!(file.position(), 0xD0);
+        tables_rsz(&[0x10, 0x20, 0x30]).write(&mut file).unwrap();
+        file.into_inner()
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = sample();
+        let scn = Scn::new(Cursor::new(&bytes)).unwrap();
+        assert_eq!(scn.game_objects[0].prefab_index, Some(0));
+        assert_eq!(scn.folders[0].folder_object_index, 2);
+        assert_eq!(scn.resource_names, ["a.tex"]);
+        assert_eq!(scn.prefab_paths, ["c.pfb"]);
+        assert_eq!(scn.children[0].name, "b.user");
+        let mut written = Cursor::new(vec![]);
+        scn.write(&mut written).unwrap();
+        assert_eq!(written.into_inner(), bytes);
+    }
+
+    #[test]
+    fn write_modified() {
+        let mut scn = Scn::new(Cursor::new(sample())).unwrap();
+        let mut serializer = RszSerializer::new(None);
+        serializer.add_hints(&scn.rsz);
+        for root in scn.rsz.deserialize(None).unwrap() {
+            let first = root.downcast_ref::<RefTableData>().unwrap().vital_tbl_no;
+            let value = RefTableData {
+                multi_tbl_no: 0x1234,
+                ..table(first)
+            };
+            serializer.add_root(&value).unwrap();
+        }
+        scn.rsz = serializer.finish();
+
+        let mut written = Cursor::new(vec![]);
+        scn.write(&mut written).unwrap();
+        let scn = Scn::new(Cursor::new(written.into_inner())).unwrap();
+        assert_eq!(scn.game_objects[0].guid.bytes[15], 15);
+        assert_eq!(scn.prefab_paths, ["c.pfb"]);
+        assert_eq!(scn.children[0].hash, 0x12345678);
+        let roots = scn.rsz.deserialize(None).unwrap();
+        let values: Vec<_> = roots
+            .iter()
+            .map(|root| root.downcast_ref::<RefTableData>().unwrap())
+            .map(|value| (value.vital_tbl_no, value.multi_tbl_no))
+            .collect();
+        assert_eq!(values, [(0x10, 0x1234), (0x20, 0x1234), (0x30, 0x1234)]);
+    }
+}
@@ -1,18 +1,20 @@
 use crate::bitfield::*;
 use crate::file_ext::*;
 use crate::hash::*;
+use crate::rsz::{RszFieldKind, RszSchema, RszSchemaField, RszSchemaType};
 use anyhow::{bail, Context, Result};
 use bitflags::*;
 use serde::*;
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::convert::{TryFrom, TryInto};
+use std::fmt::Write as _;
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::PathBuf;
 
 bitflags! {
-    #[derive(Serialize, Clone, Debug, PartialEq, Eq, Copy)]
-    #[serde(into = "u16")]
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Copy)]
+    #[serde(into = "u16", from = "u16")]
     struct FieldAttribute: u16 {
         const PRIVATE_SCOPE            = 0x0000;
         const PRIVATE                  = 0x0001;
@@ -44,9 +46,15 @@ impl From<FieldAttribute> for u16 {
     }
 }
 
+impl From<u16> for FieldAttribute {
+    fn from(v: u16) -> Self {
+        FieldAttribute::from_bits_retain(v)
+    }
+}
+
 bitflags! {
-    #[derive(Serialize, Clone, Debug, PartialEq, Eq, Copy)]
-    #[serde(into = "u16")]
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Copy)]
+    #[serde(into = "u16", from = "u16")]
     struct ParamAttribute: u16 {
         const IN                = 0x0001;
         const OUT               = 0x0002;
@@ -64,9 +72,15 @@ impl From<ParamAttribute> for u16 {
     }
 }
 
+impl From<u16> for ParamAttribute {
+    fn from(v: u16) -> Self {
+        ParamAttribute::from_bits_retain(v)
+    }
+}
+
 bitflags! {
-    #[derive(Serialize, Clone, Debug, PartialEq, Eq, Copy)]
-    #[serde(into = "u16")]
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Copy)]
+    #[serde(into = "u16", from = "u16")]
     struct MethodAttribute: u16 {
         const PRIVATE_SCOPE            = 0x0000;
         const PRIVATE                  = 0x0001;
@@ -99,9 +113,15 @@ impl From<MethodAttribute> for u16 {
     }
 }
 
+impl From<u16> for MethodAttribute {
+    fn from(v: u16) -> Self {
+        MethodAttribute::from_bits_retain(v)
+    }
+}
+
 bitflags! {
-    #[derive(Serialize, Clone, Debug, PartialEq, Eq, Copy)]
-    #[serde(into = "u32")]
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Copy)]
+    #[serde(into = "u32", from = "u32")]
     struct TypeFlag: u32 {
         const NOT_PUBLIC           = 0x00000000;
         const PUBLIC               = 0x00000001;
@@ -157,9 +177,15 @@ impl From<TypeFlag> for u32 {
     }
 }
 
+impl From<u32> for TypeFlag {
+    fn from(v: u32) -> Self {
+        TypeFlag::from_bits_retain(v)
+    }
+}
+
 bitflags! {
-    #[derive(Serialize, Clone, Debug, PartialEq, Eq, Copy)]
-    #[serde(into = "u16")]
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Copy)]
+    #[serde(into = "u16", from = "u16")]
     struct MethodImplFlag: u16 {
         const CODE_TYPE_MASK              = 0x0003;
         const IL                          = 0x0000;
@@ -189,9 +215,15 @@ impl From<MethodImplFlag> for u16 {
     }
 }
 
+impl From<u16> for MethodImplFlag {
+    fn from(v: u16) -> Self {
+        MethodImplFlag::from_bits_retain(v)
+    }
+}
+
 bitflags! {
-    #[derive(Serialize, Clone, Debug, PartialEq, Eq, Copy)]
-    #[serde(into = "u16")]
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Copy)]
+    #[serde(into = "u16", from = "u16")]
     struct PropertyFlag: u16 {
         const SPECIAL_NAME    = 0x0200;
         const RT_SPECIAL_NAME = 0x0400;
@@ -205,6 +237,12 @@ impl From<PropertyFlag> for u16 {
     }
 }
 
+impl From<u16> for PropertyFlag {
+    fn from(v: u16) -> Self {
+        PropertyFlag::from_bits_retain(v)
+    }
+}
+
 fn display_property_flag(flags: PropertyFlag) -> String {
     let mut s = String::new();
     if flags.contains(PropertyFlag::SPECIAL_NAME) {
@@ -233,6 +271,95 @@ fn display_param_modifier(param_modifier: u32, return_pos: bool) -> String {
     format!("[{return_pos}{tag}]")
 }
 
+const C_KEYWORDS: &[&str] = &[
+    "alignas",
+    "alignof",
+    "auto",
+    "bool",
+    "break",
+    "case",
+    "catch",
+    "char",
+    "class",
+    "const",
+    "continue",
+    "default",
+    "delete",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "explicit",
+    "export",
+    "extern",
+    "false",
+    "float",
+    "for",
+    "friend",
+    "goto",
+    "if",
+    "inline",
+    "int",
+    "long",
+    "mutable",
+    "namespace",
+    "new",
+    "noexcept",
+    "nullptr",
+    "operator",
+    "private",
+    "protected",
+    "public",
+    "register",
+    "restrict",
+    "return",
+    "short",
+    "signed",
+    "sizeof",
+    "static",
+    "struct",
+    "switch",
+    "template",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typedef",
+    "typename",
+    "union",
+    "unsigned",
+    "using",
+    "virtual",
+    "void",
+    "volatile",
+    "while",
+];
+
+fn c_identifier(name: &str) -> String {
+    let mut identifier: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if identifier.is_empty() || identifier.starts_with(|c: char| c.is_ascii_digit()) {
+        identifier.insert(0, '_');
+    }
+    if C_KEYWORDS.contains(&identifier.as_str()) {
+        identifier.push('_');
+    }
+    identifier
+}
+
+// Different names can become the same after sanitization, or can be overloaded
+fn unique_c_name(used: &mut HashSet<String>, name: String) -> String {
+    if used.insert(name.clone()) {
+        return name;
+    }
+    (1..)
+        .map(|i| format!("{name}_{i}"))
+        .find(|candidate| used.insert(candidate.clone()))
+        .unwrap()
+}
+
 fn display_type_flags(flags: TypeFlag) -> String {
     let mut s = String::new();
 
@@ -578,13 +705,13 @@ fn analyze_bits<F: Read + Seek>(mut file: F, offset: u64, count: u32, item_byte_
 }
 */
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 enum TypeParent {
     Namespace(String),
     OuterType(usize),
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 struct GenericParam {
     name: String,
     ti_constraint: Option<usize>,
@@ -593,7 +720,7 @@ struct GenericParam {
     flags: u32,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 enum Generics {
     Template {
         params: Vec<GenericParam>,
@@ -604,19 +731,19 @@ enum Generics {
     },
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 struct Interface {
     ti: usize,
     vtable_slot_start: u32,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 enum ValueInfo {
     String(String),
     Bytes(Vec<u8>),
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 struct FieldInfo {
     name: String,
     ti: usize,
@@ -627,7 +754,7 @@ struct FieldInfo {
     attributes: Vec<AttributeInfo>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 struct ParamInfo {
     name: String,
     ti: usize,
@@ -639,7 +766,7 @@ struct ParamInfo {
     attributes: Vec<AttributeInfo>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 struct MethodInfo {
     name: String,
     runtime_address: u64,
@@ -654,7 +781,7 @@ struct MethodInfo {
     params: Vec<ParamInfo>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 struct PropertyInfo {
     name: String,
     mi_get: Option<usize>,
@@ -664,14 +791,14 @@ struct PropertyInfo {
     attributes: Vec<AttributeInfo>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 struct EventInfo {
     name: String,
     mi_add: usize,
     mi_remove: usize,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 enum Arg {
     Byte(u8),
     SByte(i8),
@@ -690,13 +817,13 @@ enum Arg {
     Array(Vec<Arg>),
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 struct NamedArg {
     name: String,
     arg: Arg,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 struct AttributeInfo {
     ti_attribute: usize,
     mi_default_ctor: usize,
@@ -704,7 +831,7 @@ struct AttributeInfo {
     named_args: Vec<NamedArg>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 enum VtableSlotMethod {
     #[serde(rename = "mi")]
     Mi(usize),
@@ -712,14 +839,14 @@ enum VtableSlotMethod {
     Monomorphized(usize),
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 struct VtableSlot {
     ti: usize,
     #[serde(flatten)]
     method: VtableSlotMethod,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 struct TypeInfo {
     name: String,
     full_name: String,
@@ -748,6 +875,7 @@ struct TypeInfo {
     flags: TypeFlag,
 
     hash: u32,
+    crc: u32,
     assembly: usize,
     mi_default_ctor: Option<usize>,
     attributes: Vec<AttributeInfo>,
@@ -773,15 +901,86 @@ struct TypeInfo {
     runtime_len: usize,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 struct AssemblyInfo {
     name: String,
     full_path: String,
     dll_name: String,
 }
 
-#[derive(Serialize)]
-struct Tdb {
+// TDB versions whose table layouts are known.
+// Other RE Engine games and builds use neighbouring versions with different layouts.
+const SUPPORTED_VERSIONS: &[u32] = &[0x45, 0x46, 0x47];
+
+// Parts of the table layouts that differ between TDB versions.
+// Tables not described here are laid out the same in all supported versions.
+#[derive(Clone, Copy)]
+struct TdbLayout {
+    // Width of type instance indices, and of the method and field indices packed next to them.
+    // The same width is used in the interface and template argument lists in the byte pool.
+    type_bits: usize,
+    // Width of the field membership start index in type instances
+    field_start_bits: usize,
+    // Width of the system type in type instances
+    system_type_bits: usize,
+    // Width of the attribute list index in fields. Field attributes take the rest of the word,
+    // and continue in the field membership if they don't fit.
+    field_attribute_list_bits: usize,
+    // Whether fields and properties have attribute lists of their own, which are located in the
+    // header between the attribute lists and the string pool. Otherwise they index into the
+    // attribute lists of types and methods.
+    data_attribute_lists: bool,
+}
+
+impl TdbLayout {
+    fn new(version: u32) -> Option<TdbLayout> {
+        let layout = match version {
+            0x45 => TdbLayout {
+                type_bits: 18,
+                field_start_bits: 19,
+                system_type_bits: 7,
+                field_attribute_list_bits: 16,
+                data_attribute_lists: false,
+            },
+            0x46 => TdbLayout {
+                type_bits: 18,
+                field_start_bits: 19,
+                system_type_bits: 8,
+                field_attribute_list_bits: 16,
+                data_attribute_lists: false,
+            },
+            0x47 => TdbLayout {
+                type_bits: 19,
+                field_start_bits: 20,
+                system_type_bits: 8,
+                field_attribute_list_bits: 17,
+                data_attribute_lists: true,
+            },
+            _ => return None,
+        };
+        Some(layout)
+    }
+}
+
+pub const ANCHOR_LEN: usize = 12;
+
+pub fn is_supported_version(version: u32) -> bool {
+    SUPPORTED_VERSIONS.contains(&version)
+}
+
+/// Returns the version if the bytes look like the start of a TDB header.
+/// Versions without a known layout are also accepted here, so that they can be reported.
+pub fn anchor_version(bytes: &[u8]) -> Option<u32> {
+    let bytes = bytes.get(..ANCHOR_LEN)?;
+    if &bytes[0..4] != b"TDB\0" || bytes[8..12] != [0; 4] {
+        return None;
+    }
+    let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+    (0x40..0x60).contains(&version).then_some(version)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Tdb {
     types: Vec<TypeInfo>,
     intern_strings: Vec<String>,
     assemblies: Vec<AssemblyInfo>,
@@ -801,9 +1000,18 @@ impl Tdb {
             bail!("Wrong magic for TDB file");
         }
 
-        if file.read_u32()? != 0x47 {
-            bail!("Wrong version for TDB file");
-        }
+        let version = file.read_u32()?;
+        let Some(layout) = TdbLayout::new(version) else {
+            bail!(
+                "Unsupported TDB version 0x{version:X}. Supported versions: {}",
+                SUPPORTED_VERSIONS
+                    .iter()
+                    .map(|v| format!("0x{v:X}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        };
+        let type_bits = layout.type_bits;
 
         if file.read_u32()? != 0 {
             bail!("Expected 0");
@@ -821,8 +1029,11 @@ impl Tdb {
         let param_count = file.read_u32()?;
         let attribute_count = file.read_u32()?;
         let constant_count = file.read_u32()?;
-        let (attribute_list_count, data_attribute_list_count) =
-            file.read_u32()?.bit_split((16, 16));
+        let (attribute_list_count, data_attribute_list_count) = if layout.data_attribute_lists {
+            file.read_u32()?.bit_split((16, 16))
+        } else {
+            (file.read_u32()?, 0)
+        };
         let intern_string_count = file.read_u32()?;
         let assembly_count = file.read_u32()?;
         if file.read_u32()? != 0 {
@@ -846,7 +1057,11 @@ impl Tdb {
         let attribute_offset = file.read_u64()?;
         let constant_offset = file.read_u64()?;
         let attribute_list_offset = file.read_u64()?;
-        let data_attribute_list_offset = file.read_u64()?;
+        let data_attribute_list_offset = if layout.data_attribute_lists {
+            Some(file.read_u64()?)
+        } else {
+            None
+        };
         let string_table_offset = file.read_u64()?;
         let heap_offset = file.read_u64()?;
         let intern_string_offset = file.read_u64()?;
@@ -901,6 +1116,7 @@ impl Tdb {
             flags: TypeFlag,
             runtime_len: usize,
             hash: u32,
+            crc: u32,
 
             ctor_method_membership_index: usize,
             method_membership_start_index: usize,
@@ -923,8 +1139,14 @@ impl Tdb {
         let type_instances = (0..type_instance_count)
             .map(|i| {
                 let type_def_address = file.tell()?;
-                let (index, base_type_instance_index, parent_type_instance_index, element_type) =
-                    file.read_u64()?.bit_split((19, 19, 19, 7));
+                let (index, base_type_instance_index, parent_type_instance_index, element_type, _) =
+                    file.read_u64()?.bit_split((
+                        type_bits,
+                        type_bits,
+                        type_bits,
+                        7,
+                        57 - 3 * type_bits,
+                    ));
 
                 if index != u64::from(i) {
                     bail!("Unexpected index");
@@ -935,18 +1157,31 @@ impl Tdb {
                     dearrayize_type_instance_index,
                     type_index,
                     system_type,
-                ) = file.read_u64()?.bit_split((19, 19, 18, 8));
+                    _,
+                ) = file.read_u64()?.bit_split((
+                    type_bits,
+                    type_bits,
+                    18,
+                    layout.system_type_bits,
+                    46 - 2 * type_bits - layout.system_type_bits,
+                ));
 
                 let flags = file.read_u32()?;
                 let runtime_len = file.read_u32()?;
                 let hash = file.read_u32()?;
-                let _crc = file.read_u32()?;
+                let crc = file.read_u32()?;
 
                 let (
                     ctor_method_membership_index,
                     method_membership_start_index,
                     field_membership_start_index,
-                ) = file.read_u64()?.bit_split((22, 22, 20));
+                    _,
+                ) = file.read_u64()?.bit_split((
+                    22,
+                    22,
+                    layout.field_start_bits,
+                    20 - layout.field_start_bits,
+                ));
 
                 let (
                     property_count,
@@ -976,6 +1211,7 @@ impl Tdb {
                     flags: TypeFlag::from_bits(flags).context("Unknown type flag")?,
                     runtime_len: runtime_len.try_into()?,
                     hash,
+                    crc,
 
                     ctor_method_membership_index: ctor_method_membership_index.try_into()?,
                     method_membership_start_index: method_membership_start_index.try_into()?,
@@ -1007,10 +1243,12 @@ impl Tdb {
         let method_memberships = (0..method_membership_count)
             .map(|_| {
                 let (type_instance_index, param_list_offset_lo) =
-                    file.read_u32()?.bit_split((19, 13));
-                let (method_index, param_list_offset_hi) = file.read_u32()?.bit_split((19, 13));
+                    file.read_u32()?.bit_split((type_bits, 32 - type_bits));
+                let (method_index, param_list_offset_hi) =
+                    file.read_u32()?.bit_split((type_bits, 32 - type_bits));
                 let address = file.read_u32()?;
-                let param_list_offset = param_list_offset_lo | (param_list_offset_hi << 13);
+                let param_list_offset =
+                    param_list_offset_lo | (param_list_offset_hi << (32 - type_bits));
 
                 Ok(MethodMembership {
                     type_instance_index: type_instance_index.try_into()?,
@@ -1037,7 +1275,15 @@ impl Tdb {
                     field_type_instance_index,
                     constant_index_hi,
                     attribute_hi,
-                ) = file.read_u64()?.bit_split((19, 19, 19, 6, 1));
+                    _,
+                ) = file.read_u64()?.bit_split((
+                    type_bits,
+                    type_bits,
+                    type_bits,
+                    6,
+                    1,
+                    57 - 3 * type_bits,
+                ));
 
                 Ok(FieldMembership {
                     parent_type_instance_index: parent_type_instance_index.try_into()?,
@@ -1143,7 +1389,10 @@ impl Tdb {
         file.seek_assert_align_up(field_offset, 16)?;
         let fields = (0..field_count)
             .map(|_| {
-                let (attribute_list_index, attributes) = file.read_u32()?.bit_split((17, 15));
+                let (attribute_list_index, attributes) = file.read_u32()?.bit_split((
+                    layout.field_attribute_list_bits,
+                    32 - layout.field_attribute_list_bits,
+                ));
                 let (position, constant_index_low) = file.read_u32()?.bit_split((26, 6));
                 let (name_offset, constant_index_mid) = file.read_u32()?.bit_split((28, 4));
 
@@ -1251,7 +1500,8 @@ impl Tdb {
                 let attribute_list_index = file.read_u16()?;
                 let default_const_index = file.read_u16()?;
                 let (name_offset, modifier) = file.read_u32()?.bit_split((30, 2));
-                let (type_instance_index, attribute) = file.read_u32()?.bit_split((19, 13));
+                let (type_instance_index, attribute) =
+                    file.read_u32()?.bit_split((type_bits, 32 - type_bits));
 
                 Ok(Param {
                     attribute_list_index: attribute_list_index.into(),
@@ -1290,10 +1540,15 @@ impl Tdb {
             .map(|_| file.read_u32())
             .collect::<Result<Vec<_>>>()?;
 
-        file.seek_assert_align_up(data_attribute_list_offset, 16)?;
-        let data_attribute_lists = (0..data_attribute_list_count)
-            .map(|_| file.read_u32())
-            .collect::<Result<Vec<_>>>()?;
+        let data_attribute_lists =
+            if let Some(data_attribute_list_offset) = data_attribute_list_offset {
+                file.seek_assert_align_up(data_attribute_list_offset, 16)?;
+                (0..data_attribute_list_count)
+                    .map(|_| file.read_u32())
+                    .collect::<Result<Vec<_>>>()?
+            } else {
+                attribute_lists.clone()
+            };
 
         file.seek_assert_align_up(string_table_offset, 16)?;
         let mut string_table = vec![0; string_table_len.try_into()?];
@@ -1663,8 +1918,9 @@ impl Tdb {
                         .get(instance.template_argument_list_offset..)
                         .context("template argument list offset out of bound")?;
 
-                    let (template_type_instance_index, arg_count) =
-                        template_argument_list.read_u32()?.bit_split((19, 13));
+                    let (template_type_instance_index, arg_count) = template_argument_list
+                        .read_u32()?
+                        .bit_split((type_bits, 32 - type_bits));
 
                     let template_type_instance_index: usize =
                         template_type_instance_index.try_into()?;
@@ -1695,7 +1951,7 @@ impl Tdb {
                             .map(|chunk| {
                                 let (type_instance_index, flags) =
                                     u32::from_le_bytes(chunk[0..4].try_into().unwrap())
-                                        .bit_split((19, 13));
+                                        .bit_split((type_bits, 32 - type_bits));
                                 let type_instance_index: usize = type_instance_index.try_into()?;
                                 let name_offset =
                                     u32::from_le_bytes(chunk[4..8].try_into().unwrap());
@@ -1723,7 +1979,8 @@ impl Tdb {
                         .chunks(4)
                         .map(|chunk| {
                             let (interface_type_instance_id, vtable_slot_start) =
-                                u32::from_le_bytes(chunk.try_into().unwrap()).bit_split((19, 13));
+                                u32::from_le_bytes(chunk.try_into().unwrap())
+                                    .bit_split((type_bits, 32 - type_bits));
                             let interface_type_instance_id: usize =
                                 interface_type_instance_id.try_into()?;
                             let ti = to_ti(interface_type_instance_id)?;
@@ -1858,6 +2115,7 @@ impl Tdb {
                     element_type: instance.element_type,
                     flags: instance.flags,
                     hash: instance.hash,
+                    crc: instance.crc,
                     assembly: ty.assembly_index,
                     mi_default_ctor: (ctor != 0)
                         .then(|| to_mi_self(ctor, instance_index))
@@ -2109,6 +2367,11 @@ impl Tdb {
         Ok(())
     }
 
+    pub fn load_json(path: &str) -> Result<Tdb> {
+        serde_json::from_reader(std::io::BufReader::new(File::open(path)?))
+            .context("Failed to parse TDB JSON")
+    }
+
     pub fn write_json_split(&self, path: &str) -> Result<()> {
         std::fs::create_dir_all(path)?;
         let chunk = 1000;
@@ -2422,11 +2685,1442 @@ impl Tdb {
 
         Ok(())
     }
+
+    fn c_primitive(&self, ti: usize) -> Option<&'static str> {
+        if self.is_enum(ti) {
+            return None;
+        }
+        Some(match self.types[ti].system_type {
+            13 => "uint8_t",
+            14 => "int8_t",
+            15 | 17 => "uint16_t",
+            16 => "int16_t",
+            18 => "int32_t",
+            19 => "uint32_t",
+            20 | 24 => "int64_t",
+            21 | 25 => "uint64_t",
+            22 => "float",
+            23 => "double",
+            26 => "bool",
+            31 => "void",
+            _ => return None,
+        })
+    }
+
+    // C type of a field, a parameter or a return value.
+    // Reference types are pointers, while value types are embedded.
+    // Returns None for value types that don't have a concrete layout.
+    fn c_type(
+        &self,
+        ti: usize,
+        names: &[String],
+        declared: &mut BTreeSet<usize>,
+    ) -> Option<String> {
+        let type_info = &self.types[ti];
+        if let Some(primitive) = self.c_primitive(ti) {
+            return Some(if type_info.vmobj_type == 5 {
+                primitive.to_owned()
+            } else {
+                format!("{primitive}*")
+            });
+        }
+        if type_info.vmobj_type != 5 {
+            declared.insert(ti);
+            return Some(format!("{}*", names[ti]));
+        }
+        if !self.c_embeddable(ti) {
+            return None;
+        }
+        if !self.is_enum(ti) {
+            declared.insert(ti);
+        }
+        Some(names[ti].clone())
+    }
+
+    // Value types embedded in the type, which need to be defined first
+    fn c_dependencies(&self, ti: usize) -> Vec<usize> {
+        let mut dependencies = vec![];
+        let mut current = Some(ti);
+        while let Some(i) = current {
+            for field in &self.types[i].fields {
+                if !field.flags.contains(FieldAttribute::STATIC) {
+                    dependencies.push(field.ti);
+                }
+            }
+            current = self.types[i].ti_base;
+        }
+        dependencies.extend(self.types[ti].ti_dearray);
+        dependencies.retain(|&dependency| self.c_embeddable(dependency));
+        dependencies
+    }
+
+    fn c_embeddable(&self, ti: usize) -> bool {
+        let type_info = &self.types[ti];
+        type_info.vmobj_type == 5
+            && self.c_primitive(ti).is_none()
+            && !type_info.full_name.contains('!')
+            && !matches!(type_info.generics, Some(Generics::Template { .. }))
+            && type_info.len != 0
+    }
+
+    fn c_visit(&self, ti: usize, visited: &mut [bool], order: &mut Vec<usize>) {
+        if visited[ti] {
+            return;
+        }
+        visited[ti] = true;
+        for dependency in self.c_dependencies(ti) {
+            self.c_visit(dependency, visited, order);
+        }
+        order.push(ti);
+    }
+
+    fn write_c_definition(
+        &self,
+        ti: usize,
+        names: &[String],
+        used_names: &mut HashSet<String>,
+        declared: &mut BTreeSet<usize>,
+        output: &mut String,
+    ) -> Result<()> {
+        let type_info = &self.types[ti];
+        let name = &names[ti];
+
+        if self.is_enum(ti) {
+            let underlying = type_info
+                .fields
+                .iter()
+                .find(|field| !field.flags.contains(FieldAttribute::STATIC))
+                .and_then(|field| self.c_primitive(field.ti))
+                .unwrap_or("int32_t");
+            let suffix = match underlying {
+                "int64_t" => "LL",
+                "uint64_t" => "ULL",
+                _ => "",
+            };
+            writeln!(output, "// {}", type_info.full_name)?;
+            writeln!(output, "typedef {underlying} {name};")?;
+            let constants = self.enum_constants(ti);
+            if !constants.is_empty() {
+                writeln!(output, "enum {{")?;
+                for (constant, value) in constants {
+                    let constant =
+                        unique_c_name(used_names, format!("{name}_{}", c_identifier(constant)));
+                    writeln!(output, "    {constant} = {value}{suffix},")?;
+                }
+                writeln!(output, "}};")?;
+            }
+            writeln!(output)?;
+            return Ok(());
+        }
+
+        // Reference types are preceded by the object header, which is not part of field positions
+        let (size, header_len) = if type_info.vmobj_type == 5 {
+            (type_info.len, 0)
+        } else {
+            (
+                type_info.runtime_len,
+                type_info.runtime_len.saturating_sub(type_info.len),
+            )
+        };
+        if size == 0 && type_info.ti_dearray.is_none() {
+            return Ok(());
+        }
+
+        // (position, length, name, type, byte array length)
+        let mut fields: Vec<(usize, usize, &str, String, Option<usize>)> = vec![];
+        if header_len >= 16 {
+            fields.push((0, 8, "_vtable", "void*".to_owned(), None));
+            fields.push((8, 8, "_lock", "void*".to_owned(), None));
+        }
+        let mut current = Some(ti);
+        while let Some(i) = current {
+            for field in &self.types[i].fields {
+                if field.flags.contains(FieldAttribute::STATIC) {
+                    continue;
+                }
+                let position = field.position as usize + header_len;
+                let field_type = &self.types[field.ti];
+                let len = if field_type.vmobj_type == 5 {
+                    field_type.len
+                } else {
+                    8
+                };
+                fields.push(match self.c_type(field.ti, names, declared) {
+                    Some(c_type) => (position, len, &field.name, c_type, None),
+                    None => (position, len, &field.name, "uint8_t".to_owned(), Some(len)),
+                });
+            }
+            current = self.types[i].ti_base;
+        }
+        fields.sort_by_key(|field| field.0);
+
+        declared.insert(ti);
+        writeln!(output, "// {}", type_info.full_name)?;
+        writeln!(output, "struct {name} {{")?;
+        let mut field_names = HashSet::new();
+        let mut current_pos = 0;
+        for (position, len, field_name, c_type, array) in fields {
+            if len == 0 {
+                continue;
+            }
+            if current_pos > position {
+                writeln!(
+                    output,
+                    "    // Overlapped: {c_type} {field_name}; // 0x{position:04X}"
+                )?;
+                continue;
+            }
+            if current_pos < position {
+                writeln!(
+                    output,
+                    "    uint8_t _pad_{current_pos:04X}[0x{:X}];",
+                    position - current_pos
+                )?;
+                current_pos = position;
+            }
+            let field_name = unique_c_name(&mut field_names, c_identifier(field_name));
+            if let Some(array) = array {
+                writeln!(
+                    output,
+                    "    {c_type} {field_name}[0x{array:X}]; // 0x{position:04X}"
+                )?;
+            } else {
+                writeln!(output, "    {c_type} {field_name}; // 0x{position:04X}")?;
+            }
+            current_pos += len;
+        }
+        if current_pos > size {
+            writeln!(output, "    // Members overflow the type size 0x{size:X}")?;
+        }
+        if current_pos < size {
+            writeln!(
+                output,
+                "    uint8_t _pad_{current_pos:04X}[0x{:X}];",
+                size - current_pos
+            )?;
+        }
+        if let Some(ti_element) = type_info.ti_dearray {
+            writeln!(output, "    int32_t _x;")?;
+            writeln!(output, "    int32_t _y;")?;
+            writeln!(output, "    int32_t _rank;")?;
+            writeln!(output, "    int32_t _count;")?;
+            let element = self
+                .c_type(ti_element, names, declared)
+                .unwrap_or_else(|| "uint8_t".to_owned());
+            writeln!(output, "    {element} _data[];")?;
+        }
+        writeln!(output, "}};")?;
+        writeln!(output)?;
+        Ok(())
+    }
+
+    fn write_c_methods(
+        &self,
+        ti: usize,
+        names: &[String],
+        used_names: &mut HashSet<String>,
+        declared: &mut BTreeSet<usize>,
+        options: &crate::TdbOptions,
+        output: &mut String,
+    ) -> Result<()> {
+        for method in &self.types[ti].methods {
+            if method.runtime_address == 0 {
+                continue;
+            }
+
+            let mut param_names: HashSet<String> = ["_ret", "_ctx", "_this"]
+                .into_iter()
+                .map(str::to_owned)
+                .collect();
+            let mut params = vec![];
+
+            // Value types larger than 8 bytes are returned via a hidden pointer
+            let ret_type = &self.types[method.ret.ti];
+            let ret = match self.c_type(method.ret.ti, names, declared) {
+                Some(c_type)
+                    if ret_type.vmobj_type == 5
+                        && ret_type.len > 8
+                        && self.c_primitive(method.ret.ti).is_none() =>
+                {
+                    params.push(format!("{c_type}* _ret"));
+                    "void".to_owned()
+                }
+                Some(c_type) => c_type,
+                None if ret_type.len > 8 => {
+                    params.push("void* _ret".to_owned());
+                    "void".to_owned()
+                }
+                None if ret_type.len > 0 => "uint64_t".to_owned(),
+                None => "void".to_owned(),
+            };
+
+            params.push("void* _ctx".to_owned());
+            if !method.flags.contains(MethodAttribute::STATIC) {
+                declared.insert(ti);
+                params.push(format!("{}* _this", names[ti]));
+            }
+
+            for param in &method.params {
+                let mut c_type = self
+                    .c_type(param.ti, names, declared)
+                    .unwrap_or_else(|| "void*".to_owned());
+                if param.modifier != 0 {
+                    c_type += "*";
+                }
+                let param_name = unique_c_name(&mut param_names, c_identifier(&param.name));
+                params.push(format!("{c_type} {param_name}"));
+            }
+
+            let name = unique_c_name(
+                used_names,
+                format!("{}_{}", names[ti], c_identifier(&method.name)),
+            );
+            write!(output, "{ret} {name}({});", params.join(", "))?;
+            if !options.no_runtime {
+                write!(output, " // 0x{:08X}", method.runtime_address)?;
+            }
+            writeln!(output)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_header(&self, path: &str, options: &crate::TdbOptions) -> Result<()> {
+        let mut used_names: HashSet<String> = HashSet::new();
+        let names: Vec<String> = self
+            .types
+            .iter()
+            .map(|type_info| unique_c_name(&mut used_names, c_identifier(&type_info.full_name)))
+            .collect();
+
+        let listed: Vec<usize> = self
+            .types
+            .iter()
+            .enumerate()
+            .filter(|&(ti, type_info)| {
+                let full_name = &type_info.full_name;
+                if options.no_compound
+                    && (type_info.ti_dearray.is_some()
+                        || full_name.contains('!')
+                        || full_name.contains('<'))
+                {
+                    return false;
+                }
+                if options.no_system && full_name.starts_with("System.") {
+                    return false;
+                }
+                !full_name.contains('!')
+                    && !matches!(type_info.generics, Some(Generics::Template { .. }))
+                    && self.c_primitive(ti).is_none()
+            })
+            .map(|(ti, _)| ti)
+            .collect();
+
+        // Value types are embedded by value, so they are always defined before their users,
+        // even if they are filtered out themselves.
+        let mut visited = vec![false; self.types.len()];
+        let mut order = vec![];
+        for &ti in &listed {
+            self.c_visit(ti, &mut visited, &mut order);
+            for method in &self.types[ti].methods {
+                for param in std::iter::once(&method.ret).chain(&method.params) {
+                    if self.c_embeddable(param.ti) {
+                        self.c_visit(param.ti, &mut visited, &mut order);
+                    }
+                }
+            }
+        }
+
+        let mut declared = BTreeSet::new();
+        let mut definitions = String::new();
+        for ti in order {
+            self.write_c_definition(ti, &names, &mut used_names, &mut declared, &mut definitions)?;
+        }
+
+        let mut methods = String::new();
+        for &ti in &listed {
+            self.write_c_methods(
+                ti,
+                &names,
+                &mut used_names,
+                &mut declared,
+                options,
+                &mut methods,
+            )?;
+        }
+
+        let mut output = String::new();
+        writeln!(output, "#pragma once")?;
+        writeln!(output)?;
+        writeln!(output, "#include <stdbool.h>")?;
+        writeln!(output, "#include <stdint.h>")?;
+        writeln!(output)?;
+        for ti in declared {
+            writeln!(output, "typedef struct {0} {0};", names[ti])?;
+        }
+        writeln!(output)?;
+        writeln!(output, "#pragma pack(push, 1)")?;
+        writeln!(output)?;
+        output += &definitions;
+        writeln!(output, "#pragma pack(pop)")?;
+        writeln!(output)?;
+        output += &methods;
+
+        std::fs::write(path, output)?;
+        Ok(())
+    }
+
+    fn rsz_fields(&self, fields: &[FieldInfo], depth: usize) -> Vec<RszSchemaField> {
+        fields
+            .iter()
+            .filter(|field| {
+                !field.flags.intersects(
+                    FieldAttribute::STATIC | FieldAttribute::LITERAL | FieldAttribute::NO_SERIALIZE,
+                )
+            })
+            .map(|field| RszSchemaField {
+                name: field.name.clone(),
+                type_name: self.types[field.ti].full_name.clone(),
+                kind: self.rsz_field_kind(field.ti, depth),
+            })
+            .collect()
+    }
+
+    fn rsz_field_kind(&self, ti: usize, depth: usize) -> RszFieldKind {
+        let type_info = &self.types[ti];
+        let full_name = type_info.full_name.as_str();
+        match full_name {
+            "System.Boolean" => return RszFieldKind::Bool,
+            "System.SByte" => return RszFieldKind::I8,
+            "System.Byte" => return RszFieldKind::U8,
+            "System.Int16" => return RszFieldKind::I16,
+            "System.UInt16" | "System.Char" => return RszFieldKind::U16,
+            "System.Int32" => return RszFieldKind::I32,
+            "System.UInt32" => return RszFieldKind::U32,
+            "System.Int64" => return RszFieldKind::I64,
+            "System.UInt64" => return RszFieldKind::U64,
+            "System.Single" => return RszFieldKind::F32,
+            "System.Double" => return RszFieldKind::F64,
+            "System.String" => return RszFieldKind::String,
+            "System.Guid" => return RszFieldKind::Guid,
+            "via.vec2" => return RszFieldKind::Vec2,
+            "via.vec3" => return RszFieldKind::Vec3,
+            "via.vec4" => return RszFieldKind::Vec4,
+            "via.Quaternion" => return RszFieldKind::Quat,
+            "via.mat4" => return RszFieldKind::Mat4,
+            _ => (),
+        }
+
+        if let Some(ti_element) = type_info.ti_dearray {
+            return RszFieldKind::Array(Box::new(self.rsz_field_kind(ti_element, depth)));
+        }
+
+        if let Some(Generics::Constructed {
+            ti_template,
+            ti_args,
+        }) = &type_info.generics
+        {
+            if self.types[*ti_template].full_name == "System.Collections.Generic.List`1" {
+                if let Some(&ti_element) = ti_args.first() {
+                    return RszFieldKind::Array(Box::new(self.rsz_field_kind(ti_element, depth)));
+                }
+            }
+        }
+
+        let base_name = type_info
+            .ti_base
+            .map(|ti_base| self.types[ti_base].full_name.as_str());
+        match base_name {
+            Some("System.Enum") => type_info
+                .fields
+                .iter()
+                .find(|field| !field.flags.contains(FieldAttribute::STATIC))
+                .map(|field| self.rsz_field_kind(field.ti, depth))
+                .unwrap_or_else(|| RszFieldKind::Unknown(full_name.to_owned())),
+            Some("System.ValueType") => {
+                if depth > 16 {
+                    return RszFieldKind::Unknown(full_name.to_owned());
+                }
+                let fields = self.rsz_fields(&type_info.fields, depth + 1);
+                if fields.is_empty() {
+                    RszFieldKind::Unknown(full_name.to_owned())
+                } else {
+                    RszFieldKind::Struct(fields)
+                }
+            }
+            _ => RszFieldKind::Object,
+        }
+    }
+
+    pub fn rsz_schema(&self) -> RszSchema {
+        let mut types = BTreeMap::new();
+        for type_info in &self.types {
+            if type_info.ti_dearray.is_some()
+                || matches!(type_info.generics, Some(Generics::Template { .. }))
+                || type_info
+                    .flags
+                    .intersects(TypeFlag::INTERFACE | TypeFlag::ABSTRACT | TypeFlag::NATIVE_TYPE)
+            {
+                continue;
+            }
+
+            // Base types first. Only managed fields are known.
+            let mut chain = vec![];
+            let mut current = Some(type_info);
+            while let Some(t) = current {
+                if matches!(
+                    t.full_name.as_str(),
+                    "System.ValueType" | "System.Enum" | "System.String"
+                ) {
+                    chain.clear();
+                    break;
+                }
+                if !t.flags.contains(TypeFlag::NATIVE_TYPE) {
+                    chain.push(t);
+                }
+                current = t.ti_base.map(|ti_base| &self.types[ti_base]);
+            }
+            if chain.is_empty() {
+                continue;
+            }
+
+            let fields = chain
+                .iter()
+                .rev()
+                .flat_map(|t| self.rsz_fields(&t.fields, 0))
+                .collect();
+            types.insert(
+                type_info.full_name.clone(),
+                RszSchemaType {
+                    crc: type_info.crc,
+                    fields,
+                },
+            );
+        }
+        RszSchema::new(types)
+    }
+
+    fn rust_type_name(&self, ti: usize) -> String {
+        let type_info = &self.types[ti];
+        let mut name = match type_info.parent {
+            Some(TypeParent::OuterType(ti_outer)) => self.rust_type_name(ti_outer),
+            _ => String::new(),
+        };
+        name.extend(type_info.name.chars().filter(|c| c.is_ascii_alphanumeric()));
+        if name.starts_with(|c: char| c.is_ascii_digit()) {
+            name.insert(0, 'T');
+        }
+        name
+    }
+
+    fn rust_field_name(name: &str) -> String {
+        const KEYWORDS: &[&str] = &[
+            "abstract", "as", "async", "await", "become", "box", "break", "const", "continue",
+            "crate", "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "if",
+            "impl", "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override",
+            "priv", "pub", "ref", "return", "self", "static", "struct", "super", "trait", "true",
+            "try", "type", "typeof", "unsafe", "unsized", "use", "virtual", "where", "while",
+            "yield",
+        ];
+
+        // Auto properties are stored as "<Name>k__BackingField"
+        let name = name
+            .strip_prefix('<')
+            .and_then(|name| name.split_once('>'))
+            .map_or(name, |(name, _)| name);
+        let chars: Vec<char> = name.trim_start_matches('_').chars().collect();
+        let mut result = String::new();
+        for (i, &c) in chars.iter().enumerate() {
+            if c.is_ascii_uppercase() {
+                let prev = i.checked_sub(1).map(|i| chars[i]);
+                let next = chars.get(i + 1);
+                let word_start = match prev {
+                    Some(prev) if prev.is_ascii_lowercase() || prev.is_ascii_digit() => true,
+                    Some(prev) if prev.is_ascii_uppercase() => {
+                        next.is_some_and(|next| next.is_ascii_lowercase())
+                    }
+                    _ => false,
+                };
+                if word_start && !result.ends_with('_') {
+                    result.push('_');
+                }
+                result.push(c.to_ascii_lowercase());
+            } else if c.is_ascii_alphanumeric() {
+                result.push(c);
+            } else {
+                result.push('_');
+            }
+        }
+        if result.is_empty() || result.starts_with(|c: char| c.is_ascii_digit()) {
+            result.insert(0, '_');
+        }
+        if KEYWORDS.contains(&result.as_str()) {
+            result.push('_');
+        }
+        result
+    }
+
+    fn is_user_data(&self, ti: usize) -> bool {
+        let mut current = Some(ti);
+        while let Some(ti) = current {
+            if self.types[ti].full_name == "via.UserData" {
+                return true;
+            }
+            current = self.types[ti].ti_base;
+        }
+        false
+    }
+
+    // Returns the Rust type of a field, and an optional comment for it.
+    // Referenced enums, value types and classes are pushed to the queue for generation.
+    fn rust_field_type(&self, ti: usize, queue: &mut Vec<usize>) -> (String, Option<String>) {
+        let type_info = &self.types[ti];
+        let full_name = type_info.full_name.as_str();
+        let primitive = match full_name {
+            "System.Boolean" => "bool",
+            "System.SByte" => "i8",
+            "System.Byte" => "u8",
+            "System.Int16" => "i16",
+            "System.UInt16" | "System.Char" => "u16",
+            "System.Int32" => "i32",
+            "System.UInt32" => "u32",
+            "System.Int64" => "i64",
+            "System.UInt64" => "u64",
+            "System.Single" => "f32",
+            "System.Double" => "f64",
+            "System.String" => "String",
+            "System.Guid" => "Guid",
+            "via.vec2" => "Vec2",
+            "via.vec3" => "Vec3",
+            "via.vec4" => "Vec4",
+            "via.Quaternion" => "Quat",
+            "via.mat4" => "Mat4x4",
+            _ => "",
+        };
+        if !primitive.is_empty() {
+            return (primitive.to_owned(), None);
+        }
+
+        let ti_element = type_info.ti_dearray.or(match &type_info.generics {
+            Some(Generics::Constructed {
+                ti_template,
+                ti_args,
+            }) if self.types[*ti_template].full_name == "System.Collections.Generic.List`1" => {
+                ti_args.first().copied()
+            }
+            _ => None,
+        });
+        if let Some(ti_element) = ti_element {
+            let (element, comment) = self.rust_field_type(ti_element, queue);
+            return (format!("Vec<{element}>"), comment);
+        }
+
+        match self.rsz_field_kind(ti, 0) {
+            RszFieldKind::Unknown(_) => {
+                return ("()".to_owned(), Some(format!("TODO: {full_name}")));
+            }
+            RszFieldKind::Object => {
+                if self.is_user_data(ti) {
+                    return ("ExternUser<()>".to_owned(), Some(full_name.to_owned()));
+                }
+                if full_name == "System.Object"
+                    || type_info
+                        .flags
+                        .intersects(TypeFlag::INTERFACE | TypeFlag::ABSTRACT)
+                {
+                    return ("()".to_owned(), Some(format!("TODO: {full_name}")));
+                }
+            }
+            _ => (),
+        }
+
+        queue.push(ti);
+        (self.rust_type_name(ti), None)
+    }
+
+    fn write_rsz_enum(&self, ti: usize, output: &mut String) -> Result<()> {
+        let type_info = &self.types[ti];
+        let underlying = type_info
+            .fields
+            .iter()
+            .find(|field| !field.flags.contains(FieldAttribute::STATIC))
+            .context("Enum without value field")?;
+        let (underlying, _) = self.rust_field_type(underlying.ti, &mut vec![]);
+
+        writeln!(output, "rsz_enum! {{")?;
+        writeln!(output, "    #[rsz({underlying})]")?;
+        writeln!(output, "    #[derive(Debug, Serialize)]")?;
+        writeln!(output, "    pub enum {} {{", self.rust_type_name(ti))?;
+        for field in &type_info.fields {
+            if !field.flags.contains(FieldAttribute::LITERAL) {
+                continue;
+            }
+            let Some(ValueInfo::Bytes(b)) = &field.value else {
+                continue;
+            };
+            let value = match (underlying.as_str(), b.as_slice()) {
+                ("i8", &[b0]) => (b0 as i8).to_string(),
+                ("u8", &[b0]) => b0.to_string(),
+                ("i16", b) => i16::from_le_bytes(b.try_into()?).to_string(),
+                ("u16", b) => u16::from_le_bytes(b.try_into()?).to_string(),
+                ("i32", b) => i32::from_le_bytes(b.try_into()?).to_string(),
+                ("u32", b) => u32::from_le_bytes(b.try_into()?).to_string(),
+                ("i64", b) => i64::from_le_bytes(b.try_into()?).to_string(),
+                ("u64", b) => u64::from_le_bytes(b.try_into()?).to_string(),
+                _ => bail!("Unexpected constant for {}", type_info.full_name),
+            };
+            let mut name: String = field
+                .name
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+                .collect();
+            if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+                name.insert(0, 'V');
+            }
+            writeln!(output, "        {name} = {value},")?;
+        }
+        writeln!(output, "    }}")?;
+        writeln!(output, "}}")?;
+        Ok(())
+    }
+
+    fn write_rsz_struct(
+        &self,
+        ti: usize,
+        version: u32,
+        output: &mut String,
+        queue: &mut Vec<usize>,
+    ) -> Result<()> {
+        let type_info = &self.types[ti];
+        let is_value_type = matches!(self.rsz_field_kind(ti, 0), RszFieldKind::Struct(_));
+
+        // Base types first. Only managed fields are known.
+        let mut chain = vec![];
+        let mut current = Some(type_info);
+        while let Some(t) = current {
+            if matches!(t.full_name.as_str(), "System.ValueType" | "System.Object") {
+                break;
+            }
+            if !t.flags.contains(TypeFlag::NATIVE_TYPE) {
+                chain.push(t);
+            }
+            current = t.ti_base.map(|ti_base| &self.types[ti_base]);
+        }
+
+        writeln!(output, "rsz_struct! {{")?;
+        if is_value_type {
+            writeln!(output, "    #[rsz()]")?;
+        } else {
+            let version = if version == 0 {
+                "0".to_owned()
+            } else {
+                format!(
+                    "{}_{:02}_{:02}",
+                    version / 10000,
+                    version / 100 % 100,
+                    version % 100
+                )
+            };
+            writeln!(output, "    #[rsz(\"{}\",", type_info.full_name)?;
+            writeln!(output, "        0x{:08X} = {version},", type_info.crc)?;
+            writeln!(output, "    )]")?;
+        }
+        writeln!(output, "    #[derive(Debug, Serialize)]")?;
+        writeln!(output, "    pub struct {} {{", self.rust_type_name(ti))?;
+        for t in chain.iter().rev() {
+            for field in &t.fields {
+                if field.flags.intersects(
+                    FieldAttribute::STATIC | FieldAttribute::LITERAL | FieldAttribute::NO_SERIALIZE,
+                ) {
+                    continue;
+                }
+                let name = Self::rust_field_name(&field.name);
+                let (field_type, comment) = self.rust_field_type(field.ti, queue);
+                write!(output, "        pub {name}: {field_type},")?;
+                if let Some(comment) = comment {
+                    write!(output, " // {comment}")?;
+                }
+                writeln!(output)?;
+            }
+        }
+        writeln!(output, "    }}")?;
+        writeln!(output, "}}")?;
+        Ok(())
+    }
+
+    pub fn gen_rsz(&self, symbols: &[String], version: u32) -> Result<String> {
+        let type_map: HashMap<&str, usize> = self
+            .types
+            .iter()
+            .enumerate()
+            .map(|(ti, type_info)| (type_info.full_name.as_str(), ti))
+            .collect();
+
+        let mut queue = symbols
+            .iter()
+            .rev()
+            .map(|symbol| {
+                type_map
+                    .get(symbol.as_str())
+                    .copied()
+                    .with_context(|| format!("Type {symbol} not found"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut visited = HashSet::new();
+        let mut output = String::new();
+        while let Some(ti) = queue.pop() {
+            if !visited.insert(ti) {
+                continue;
+            }
+            if !output.is_empty() {
+                writeln!(output)?;
+            }
+            writeln!(output, "// {}", self.types[ti].full_name)?;
+            let mut referenced = vec![];
+            if matches!(
+                self.types[ti]
+                    .ti_base
+                    .map(|ti_base| self.types[ti_base].full_name.as_str()),
+                Some("System.Enum")
+            ) {
+                self.write_rsz_enum(ti, &mut output)?;
+            } else {
+                self.write_rsz_struct(ti, version, &mut output, &mut referenced)?;
+            }
+            queue.extend(referenced.into_iter().rev());
+        }
+        Ok(output)
+    }
+
+    fn type_map(&self) -> BTreeMap<&str, usize> {
+        let mut type_map = BTreeMap::new();
+        for (ti, type_info) in self.types.iter().enumerate() {
+            type_map.entry(type_info.full_name.as_str()).or_insert(ti);
+        }
+        type_map
+    }
+
+    fn is_enum(&self, ti: usize) -> bool {
+        matches!(
+            self.types[ti]
+                .ti_base
+                .map(|ti_base| self.types[ti_base].full_name.as_str()),
+            Some("System.Enum")
+        )
+    }
+
+    // Returns (name, value) of all constants in an enum
+    fn enum_constants(&self, ti: usize) -> Vec<(&str, String)> {
+        let type_info = &self.types[ti];
+        let underlying = type_info
+            .fields
+            .iter()
+            .find(|field| !field.flags.contains(FieldAttribute::STATIC))
+            .map(|field| self.types[field.ti].full_name.as_str());
+        type_info
+            .fields
+            .iter()
+            .filter(|field| field.flags.contains(FieldAttribute::LITERAL))
+            .filter_map(|field| {
+                let Some(ValueInfo::Bytes(b)) = &field.value else {
+                    return None;
+                };
+                let value = match (underlying, b.as_slice()) {
+                    (Some("System.SByte"), &[b0]) => (b0 as i8).to_string(),
+                    (Some("System.Int16"), &[b0, b1]) => i16::from_le_bytes([b0, b1]).to_string(),
+                    (Some("System.Int32"), b) if b.len() == 4 => {
+                        i32::from_le_bytes(b.try_into().unwrap()).to_string()
+                    }
+                    (Some("System.Int64"), b) if b.len() == 8 => {
+                        i64::from_le_bytes(b.try_into().unwrap()).to_string()
+                    }
+                    (_, b) => {
+                        let mut bytes = [0; 8];
+                        bytes[..b.len().min(8)].copy_from_slice(&b[..b.len().min(8)]);
+                        u64::from_le_bytes(bytes).to_string()
+                    }
+                };
+                Some((field.name.as_str(), value))
+            })
+            .collect()
+    }
+
+    fn method_signature(&self, method: &MethodInfo) -> String {
+        let params: Vec<String> = method
+            .params
+            .iter()
+            .map(|param| {
+                format!(
+                    "{}{}",
+                    display_param_modifier(param.modifier, false),
+                    self.types[param.ti].full_name
+                )
+            })
+            .collect();
+        format!(
+            "{} {}({})",
+            self.types[method.ret.ti].full_name,
+            method.name,
+            params.join(", ")
+        )
+    }
+
+    fn diff_layout(old: &RszSchemaType, new: &RszSchemaType, output: &mut String) -> Result<()> {
+        let old_fields: HashMap<&str, &RszSchemaField> =
+            old.fields.iter().map(|f| (f.name.as_str(), f)).collect();
+        let new_fields: HashMap<&str, &RszSchemaField> =
+            new.fields.iter().map(|f| (f.name.as_str(), f)).collect();
+        for field in &old.fields {
+            match new_fields.get(field.name.as_str()) {
+                None => writeln!(output, "    - {}: {}", field.name, field.type_name)?,
+                Some(new_field) if new_field.kind != field.kind => writeln!(
+                    output,
+                    "    ~ {}: {} -> {}",
+                    field.name, field.type_name, new_field.type_name
+                )?,
+                _ => (),
+            }
+        }
+        for field in &new.fields {
+            if !old_fields.contains_key(field.name.as_str()) {
+                writeln!(output, "    + {}: {}", field.name, field.type_name)?;
+            }
+        }
+        let old_order: Vec<&str> = old
+            .fields
+            .iter()
+            .map(|f| f.name.as_str())
+            .filter(|name| new_fields.contains_key(name))
+            .collect();
+        let new_order: Vec<&str> = new
+            .fields
+            .iter()
+            .map(|f| f.name.as_str())
+            .filter(|name| old_fields.contains_key(name))
+            .collect();
+        if old_order != new_order {
+            writeln!(output, "    reordered: {}", old_order.join(", "))?;
+            writeln!(output, "            -> {}", new_order.join(", "))?;
+        }
+        Ok(())
+    }
+
+    // Compares against a TDB of another game build.
+    // Layouts are compared as in rsz_schema, so only changes visible to RSZ are reported.
+    pub fn diff(&self, new: &Tdb) -> Result<String> {
+        let old_map = self.type_map();
+        let new_map = new.type_map();
+        let old_schema = self.rsz_schema();
+        let new_schema = new.rsz_schema();
+        let mut output = String::new();
+
+        writeln!(output, "== Removed types ==")?;
+        for name in old_map.keys().filter(|name| !new_map.contains_key(*name)) {
+            writeln!(output, "{name}")?;
+        }
+        writeln!(output)?;
+
+        writeln!(output, "== Added types ==")?;
+        for name in new_map.keys().filter(|name| !old_map.contains_key(*name)) {
+            writeln!(output, "{name}")?;
+        }
+        writeln!(output)?;
+
+        let common: Vec<(&str, usize, usize)> = old_map
+            .iter()
+            .filter_map(|(&name, &old_ti)| Some((name, old_ti, *new_map.get(name)?)))
+            .collect();
+
+        writeln!(output, "== RSZ layout changes ==")?;
+        for &(name, old_ti, new_ti) in &common {
+            let (Some(old_type), Some(new_type)) = (old_schema.get(name), new_schema.get(name))
+            else {
+                continue;
+            };
+            let mut changes = String::new();
+            Self::diff_layout(old_type, new_type, &mut changes)?;
+            if !changes.is_empty() {
+                writeln!(
+                    output,
+                    "{name} (CRC {:08X} -> {:08X})",
+                    self.types[old_ti].crc, new.types[new_ti].crc
+                )?;
+                output += &changes;
+            }
+        }
+        writeln!(output)?;
+
+        writeln!(output, "== Enum changes ==")?;
+        for &(name, old_ti, new_ti) in &common {
+            if !self.is_enum(old_ti) || !new.is_enum(new_ti) {
+                continue;
+            }
+            let old_constants = self.enum_constants(old_ti);
+            let new_constants = new.enum_constants(new_ti);
+            let old_values: HashMap<&str, &str> = old_constants
+                .iter()
+                .map(|(name, value)| (*name, value.as_str()))
+                .collect();
+            let new_values: HashMap<&str, &str> = new_constants
+                .iter()
+                .map(|(name, value)| (*name, value.as_str()))
+                .collect();
+            let mut changes = String::new();
+            for (constant, value) in &old_constants {
+                match new_values.get(constant) {
+                    None => writeln!(changes, "    - {constant} = {value}")?,
+                    Some(new_value) if new_value != value => {
+                        writeln!(changes, "    ~ {constant}: {value} -> {new_value}")?
+                    }
+                    _ => (),
+                }
+            }
+            for (constant, value) in &new_constants {
+                if !old_values.contains_key(constant) {
+                    writeln!(changes, "    + {constant} = {value}")?;
+                }
+            }
+            if !changes.is_empty() {
+                writeln!(output, "{name}")?;
+                output += &changes;
+            }
+        }
+        writeln!(output)?;
+
+        writeln!(output, "== Method changes ==")?;
+        for &(name, old_ti, new_ti) in &common {
+            // Methods of constructed generics and arrays follow their templates
+            if self.types[old_ti].ti_dearray.is_some()
+                || matches!(
+                    self.types[old_ti].generics,
+                    Some(Generics::Constructed { .. })
+                )
+            {
+                continue;
+            }
+            let old_methods: BTreeSet<String> = self.types[old_ti]
+                .methods
+                .iter()
+                .map(|method| self.method_signature(method))
+                .collect();
+            let new_methods: BTreeSet<String> = new.types[new_ti]
+                .methods
+                .iter()
+                .map(|method| new.method_signature(method))
+                .collect();
+            let mut changes = String::new();
+            for signature in old_methods.difference(&new_methods) {
+                writeln!(changes, "    - {signature}")?;
+            }
+            for signature in new_methods.difference(&old_methods) {
+                writeln!(changes, "    + {signature}")?;
+            }
+            if !changes.is_empty() {
+                writeln!(output, "{name}")?;
+                output += &changes;
+            }
+        }
+        writeln!(output)?;
+
+        // These are the rsz_struct! definitions to update
+        writeln!(output, "== Changed types in RSZ_TYPE_MAP ==")?;
+        let mut registered: Vec<&str> = crate::rsz::RSZ_TYPE_MAP
+            .values()
+            .map(|type_info| &*type_info.symbol)
+            .collect();
+        registered.sort_unstable();
+        for symbol in registered {
+            let (Some(&old_ti), Some(old_type)) = (old_map.get(symbol), old_schema.get(symbol))
+            else {
+                continue;
+            };
+            let (Some(&new_ti), Some(new_type)) = (new_map.get(symbol), new_schema.get(symbol))
+            else {
+                writeln!(output, "{symbol}: removed")?;
+                continue;
+            };
+            let mut changes = String::new();
+            Self::diff_layout(old_type, new_type, &mut changes)?;
+            let (old_crc, new_crc) = (self.types[old_ti].crc, new.types[new_ti].crc);
+            if !changes.is_empty() {
+                writeln!(
+                    output,
+                    "{symbol}: layout changed, CRC {old_crc:08X} -> {new_crc:08X}"
+                )?;
+            } else if old_crc != new_crc {
+                writeln!(output, "{symbol}: CRC {old_crc:08X} -> {new_crc:08X}")?;
+            }
+        }
+
+        Ok(output)
+    }
+
+    pub fn find_type(&self, full_name: &str) -> Option<usize> {
+        self.types.iter().position(|t| t.full_name == full_name)
+    }
+
+    // Looks up by the hash of full name, which is also used in RSZ type descriptors
+    pub fn find_hash(&self, hash: u32) -> Option<usize> {
+        self.types.iter().position(|t| t.hash == hash)
+    }
+
+    pub fn search(&self, pattern: &regex::Regex) -> Vec<usize> {
+        (0..self.types.len())
+            .filter(|&ti| pattern.is_match(&self.types[ti].full_name))
+            .collect()
+    }
+
+    pub fn type_summary(&self, ti: usize) -> String {
+        let type_info = &self.types[ti];
+        format!("{:08X} {}", type_info.hash, type_info.full_name)
+    }
+
+    pub fn describe_type(&self, ti: usize) -> Result<String> {
+        let type_info = &self.types[ti];
+        let mut output = String::new();
+        let attribute_names = |attributes: &[AttributeInfo]| -> String {
+            attributes
+                .iter()
+                .map(|a| format!("[{}]", self.types[a.ti_attribute].full_name))
+                .collect()
+        };
+
+        writeln!(output, "{}", type_info.full_name)?;
+        writeln!(output, "    hash: {:08X}", type_info.hash)?;
+        writeln!(output, "    CRC: {:08X}", type_info.crc)?;
+        writeln!(output, "    size: 0x{:X}", type_info.len)?;
+        writeln!(
+            output,
+            "    assembly: {}",
+            self.assemblies
+                .get(type_info.assembly)
+                .map_or("", |a| a.name.as_str())
+        )?;
+        writeln!(output, "    {}", display_type_flags(type_info.flags))?;
+        if !type_info.attributes.is_empty() {
+            writeln!(output, "    {}", attribute_names(&type_info.attributes))?;
+        }
+
+        let mut chain = vec![type_info.full_name.as_str()];
+        let mut current = type_info.ti_base;
+        while let Some(ti_base) = current {
+            chain.push(&self.types[ti_base].full_name);
+            current = self.types[ti_base].ti_base;
+        }
+        writeln!(output, "Inheritance: {}", chain.join(" -> "))?;
+        for interface in &type_info.interfaces {
+            writeln!(
+                output,
+                "    implements {}",
+                self.types[interface.ti].full_name
+            )?;
+        }
+
+        if self.is_enum(ti) {
+            writeln!(output, "Enum constants:")?;
+            for (name, value) in self.enum_constants(ti) {
+                writeln!(output, "    {name} = {value}")?;
+            }
+        }
+
+        writeln!(output, "Fields:")?;
+        for field in &type_info.fields {
+            if field.flags.contains(FieldAttribute::LITERAL) {
+                continue;
+            }
+            writeln!(
+                output,
+                "    0x{:04X} {}{}{} {}",
+                field.position,
+                attribute_names(&field.attributes),
+                display_field_attributes(field.flags),
+                self.types[field.ti].full_name,
+                field.name
+            )?;
+        }
+
+        writeln!(output, "Methods:")?;
+        for method in &type_info.methods {
+            let params: Vec<String> = method
+                .params
+                .iter()
+                .map(|param| {
+                    format!(
+                        "{}{} {} {}",
+                        display_param_modifier(param.modifier, false),
+                        display_param_attributes(param.flags),
+                        self.types[param.ti].full_name,
+                        param.name
+                    )
+                })
+                .collect();
+            writeln!(
+                output,
+                "    {}{}{} {}({})",
+                attribute_names(&method.attributes),
+                display_method_attributes(method.flags),
+                self.types[method.ret.ti].full_name,
+                method.name,
+                params.join(", ")
+            )?;
+        }
+
+        Ok(output)
+    }
+
+    /// Decodes a managed object from process memory, such as a minidump, into JSON.
+    /// The type is detected from the object header if not specified.
+    /// References are followed up to the given depth.
+    pub fn inspect_object<F: Read + Seek>(
+        &self,
+        memory: F,
+        address: u64,
+        ti: Option<usize>,
+        depth: usize,
+    ) -> Result<serde_json::Value> {
+        let mut inspector = ObjectInspector {
+            tdb: self,
+            memory,
+            vtables: HashMap::new(),
+            type_defs: HashMap::new(),
+        };
+        for (ti, type_info) in self.types.iter().enumerate() {
+            if type_info.runtime_vtable != 0 {
+                inspector.vtables.insert(type_info.runtime_vtable, ti);
+            }
+            if type_info.type_def_address != 0 {
+                inspector.type_defs.insert(type_info.type_def_address, ti);
+            }
+        }
+
+        if address == 0 {
+            bail!("Null object")
+        }
+        let ti = if let Some(ti) = ti {
+            ti
+        } else {
+            inspector
+                .runtime_type(address)?
+                .with_context(|| format!("Unknown runtime type for object at 0x{address:016X}"))?
+        };
+        inspector.expand(address, ti, depth)
+    }
+}
+
+struct ObjectInspector<'a, F> {
+    tdb: &'a Tdb,
+    memory: F,
+    vtables: HashMap<u64, usize>,
+    type_defs: HashMap<u64, usize>,
+}
+
+// Pointers come from the dump and can be garbage, so offsets from them are checked
+fn offset_address(address: u64, offset: u64) -> Result<u64> {
+    address
+        .checked_add(offset)
+        .with_context(|| format!("Address 0x{address:016X} + 0x{offset:X} overflows"))
+}
+
+impl<'a, F: Read + Seek> ObjectInspector<'a, F> {
+    fn read_u64_at(&mut self, address: u64) -> Result<u64> {
+        self.memory.seek(SeekFrom::Start(address))?;
+        self.memory.read_u64()
+    }
+
+    // The first pointer of an object is the managed vtable, which starts with the type definition
+    fn runtime_type(&mut self, address: u64) -> Result<Option<usize>> {
+        let vtable = self.read_u64_at(address)?;
+        if let Some(&ti) = self.vtables.get(&vtable) {
+            return Ok(Some(ti));
+        }
+        let type_def = self.read_u64_at(vtable)?;
+        Ok(self.type_defs.get(&type_def).copied())
+    }
+
+    fn primitive(&mut self, address: u64, ti: usize) -> Result<Option<serde_json::Value>> {
+        use serde_json::json;
+        let system_type = self.tdb.types[ti].system_type;
+        self.memory.seek(SeekFrom::Start(address))?;
+        let memory = &mut self.memory;
+        Ok(Some(match system_type {
+            13 => json!(memory.read_u8()?),
+            14 => json!(memory.read_i8()?),
+            15 | 17 => json!(memory.read_u16()?),
+            16 => json!(memory.read_i16()?),
+            18 => json!(memory.read_i32()?),
+            19 => json!(memory.read_u32()?),
+            20 | 24 => json!(memory.read_i64()?),
+            21 | 25 => json!(memory.read_u64()?),
+            22 => json!(memory.read_f32()?),
+            23 => json!(memory.read_f64()?),
+            26 => json!(memory.read_u8()? != 0),
+            _ => return Ok(None),
+        }))
+    }
+
+    fn value(&mut self, address: u64, ti: usize, depth: usize) -> Result<serde_json::Value> {
+        let tdb = self.tdb;
+        let type_info = &tdb.types[ti];
+
+        if type_info.vmobj_type != 5 {
+            let pointer = self.read_u64_at(address)?;
+            if pointer == 0 {
+                return Ok(serde_json::Value::Null);
+            }
+            // Broken references shouldn't stop the rest of the object from being shown
+            return Ok(match self.object(pointer, ti, depth) {
+                Ok(value) => value,
+                Err(e) => serde_json::Value::String(format!("0x{pointer:016X}: {e:#}")),
+            });
+        }
+
+        if tdb.is_enum(ti) {
+            let underlying = type_info
+                .fields
+                .iter()
+                .find(|field| !field.flags.contains(FieldAttribute::STATIC))
+                .context("Enum without value")?
+                .ti;
+            let value = self.value(address, underlying, depth)?;
+            let value_string = value.to_string();
+            return Ok(tdb
+                .enum_constants(ti)
+                .into_iter()
+                .find(|(_, constant)| *constant == value_string)
+                .map_or(value, |(name, _)| {
+                    serde_json::Value::String(name.to_owned())
+                }));
+        }
+
+        if let Some(value) = self.primitive(address, ti)? {
+            return Ok(value);
+        }
+
+        if type_info.full_name.contains('!') {
+            let mut bytes = vec![0; type_info.len];
+            self.memory.seek(SeekFrom::Start(address))?;
+            self.memory.read_exact(&mut bytes)?;
+            return Ok(serde_json::Value::String(
+                bytes.iter().map(|b| format!("{b:02X}")).collect(),
+            ));
+        }
+
+        let mut map = serde_json::Map::new();
+        self.fields(address, ti, 0, depth, &mut map)?;
+        Ok(serde_json::Value::Object(map))
+    }
+
+    fn fields(
+        &mut self,
+        address: u64,
+        ti: usize,
+        header_len: usize,
+        depth: usize,
+        map: &mut serde_json::Map<String, serde_json::Value>,
+    ) -> Result<()> {
+        let tdb = self.tdb;
+        if let Some(ti_base) = tdb.types[ti].ti_base {
+            self.fields(address, ti_base, header_len, depth, map)?;
+        }
+        for field in &tdb.types[ti].fields {
+            if field.flags.contains(FieldAttribute::STATIC) {
+                continue;
+            }
+            let position = u64::try_from(header_len)? + u64::from(field.position);
+            let value = self.value(offset_address(address, position)?, field.ti, depth)?;
+            map.insert(field.name.clone(), value);
+        }
+        Ok(())
+    }
+
+    // Shows a referenced object. The runtime type is preferred over the declared type.
+    fn object(
+        &mut self,
+        address: u64,
+        ti_declared: usize,
+        depth: usize,
+    ) -> Result<serde_json::Value> {
+        let ti = self.runtime_type(address)?.unwrap_or(ti_declared);
+        if depth == 0 {
+            return Ok(serde_json::json!({
+                "$type": self.tdb.types[ti].full_name,
+                "$address": format!("0x{address:016X}"),
+            }));
+        }
+        self.expand(address, ti, depth - 1)
+    }
+
+    fn expand(&mut self, address: u64, ti: usize, depth: usize) -> Result<serde_json::Value> {
+        let tdb = self.tdb;
+        let type_info = &tdb.types[ti];
+        if type_info.vmobj_type == 5 {
+            return self.value(address, ti, depth);
+        }
+
+        // Reference types are preceded by the object header, which is not part of field positions
+        let header_len = type_info.runtime_len.saturating_sub(type_info.len);
+
+        if type_info.full_name == "System.String" {
+            let start = offset_address(address, u64::try_from(header_len)?)?;
+            self.memory.seek(SeekFrom::Start(start))?;
+            let len = self.memory.read_u32()?;
+            if len > 0x100000 {
+                bail!("Unexpected string length {len}");
+            }
+            let chars = (0..len)
+                .map(|_| self.memory.read_u16())
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(serde_json::Value::String(String::from_utf16_lossy(&chars)));
+        }
+
+        let mut map = serde_json::Map::new();
+        map.insert("$type".to_owned(), type_info.full_name.clone().into());
+        map.insert("$address".to_owned(), format!("0x{address:016X}").into());
+
+        // Array elements follow the rank and the count after the object
+        if let Some(ti_element) = type_info.ti_dearray {
+            let header = offset_address(address, u64::try_from(type_info.runtime_len)?)?;
+            self.memory
+                .seek(SeekFrom::Start(offset_address(header, 12)?))?;
+            let count = self.memory.read_u32()?;
+            if count > 0x100000 {
+                bail!("Unexpected array length {count}");
+            }
+            let element_type = &tdb.types[ti_element];
+            let stride = if element_type.vmobj_type == 5 {
+                element_type.len
+            } else {
+                8
+            };
+            let stride = u64::try_from(stride)?;
+            let elements = (0..u64::from(count))
+                .map(|i| {
+                    let offset = i
+                        .checked_mul(stride)
+                        .and_then(|offset| offset.checked_add(16))
+                        .context("Array element offset overflows")?;
+                    self.value(offset_address(header, offset)?, ti_element, depth)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            map.insert("$elements".to_owned(), elements.into());
+            return Ok(serde_json::Value::Object(map));
+        }
+
+        self.fields(address, ti, header_len, depth, &mut map)?;
+        Ok(serde_json::Value::Object(map))
+    }
 }
 
 pub fn print<F: Read + Seek>(file: F, base_address: u64, options: crate::TdbOptions) -> Result<()> {
-    if options.json.is_none() && options.json_split.is_none() && options.cs.is_none() {
-        eprintln!("Please specify at least one of --json, --json-split, --map, --cs");
+    if options.json.is_none()
+        && options.json_split.is_none()
+        && options.cs.is_none()
+        && options.header.is_none()
+        && options.rsz_schema.is_none()
+    {
+        eprintln!(
+            "Please specify at least one of --json, --json-split, --map, --cs, --header, --rsz-schema"
+        );
         return Ok(());
     }
 
@@ -2441,5 +4135,314 @@ pub fn print<F: Read + Seek>(file: F, base_address: u64, options: crate::TdbOpti
     if let Some(cs) = &options.cs {
         tdb.write_cs(cs, &options)?;
     }
+    if let Some(header) = &options.header {
+        tdb.write_header(header, &options)?;
+    }
+    if let Some(rsz_schema) = &options.rsz_schema {
+        tdb.rsz_schema().save(rsz_schema)?;
+    }
     Ok(())
 }
+
+pub fn gen_rsz<F: Read + Seek>(
+    file: F,
+    base_address: u64,
+    symbols: &[String],
+    version: u32,
+) -> Result<()> {
+    let tdb = Tdb::new(file, base_address)?;
+    print!("{}", tdb.gen_rsz(symbols, version)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn pack(fields: &[(u64, usize)]) -> u64 {
+        let mut packed = 0;
+        let mut shift = 0;
+        for &(value, bits) in fields {
+            assert!(value < 1 << bits);
+            // Padding may be empty at the end of the word
+            if bits != 0 {
+                packed |= value << shift;
+                shift += bits;
+            }
+        }
+        packed
+    }
+
+    fn push_u16(table: &mut Vec<u8>, v: u16) {
+        table.extend(v.to_le_bytes());
+    }
+
+    fn push_u32(table: &mut Vec<u8>, v: u64) {
+        table.extend(u32::try_from(v).unwrap().to_le_bytes());
+    }
+
+    fn push_u64(table: &mut Vec<u8>, v: u64) {
+        table.extend(v.to_le_bytes());
+    }
+
+    const FIELD_FLAGS: FieldAttribute = FieldAttribute::PUBLIC.union(FieldAttribute::DEFAULT);
+
+    // A TDB with the type app.Test, which has a field and a property read by a method
+    fn synthetic_tdb(version: u32) -> Vec<u8> {
+        let layout = TdbLayout::new(version).unwrap();
+        let tb = layout.type_bits;
+
+        let mut string_table = vec![0];
+        let mut string = |name: &str| {
+            let offset = u64::try_from(string_table.len()).unwrap();
+            string_table.extend(name.bytes());
+            string_table.push(0);
+            offset
+        };
+        let namespace = string("app");
+        let type_name = string("Test");
+        let method_name = string("get_Value");
+        let field_name = string("_Value");
+        let property_name = string("Value");
+
+        // The parameter list of the method, with no parameters and the return type at param 0
+        let heap = vec![0; 8];
+
+        // The first entries of type instances, types and method memberships are null
+        let mut type_instances = vec![0; 72];
+        push_u64(
+            &mut type_instances,
+            pack(&[(1, tb), (0, tb), (0, tb), (0x1C, 7), (0, 57 - 3 * tb)]),
+        );
+        push_u64(
+            &mut type_instances,
+            pack(&[
+                (0, tb),
+                (0, tb),
+                (1, 18),
+                (0x2A, layout.system_type_bits),
+                (0, 46 - 2 * tb - layout.system_type_bits),
+            ]),
+        );
+        push_u32(&mut type_instances, 0);
+        push_u32(&mut type_instances, 0x20);
+        push_u32(&mut type_instances, 0x12345678);
+        push_u32(&mut type_instances, 0x9ABCDEF0);
+        push_u64(
+            &mut type_instances,
+            pack(&[
+                (0, 22),
+                (1, 22),
+                (0, layout.field_start_bits),
+                (0, 20 - layout.field_start_bits),
+            ]),
+        );
+        push_u64(
+            &mut type_instances,
+            pack(&[(1, 12), (0, 20), (0, 26), (0, 3), (0, 3)]),
+        );
+        push_u64(&mut type_instances, pack(&[(0, 26), (0, 26), (0xFFF, 12)]));
+        push_u64(&mut type_instances, 0);
+        push_u64(&mut type_instances, 0);
+
+        let mut method_memberships = vec![0; 12];
+        push_u32(&mut method_memberships, pack(&[(1, tb), (0, 32 - tb)]));
+        push_u32(&mut method_memberships, pack(&[(0, tb), (0, 32 - tb)]));
+        push_u32(&mut method_memberships, 0x100);
+
+        let attribute_bits = 32 - layout.field_attribute_list_bits;
+        let flags = u64::from(FIELD_FLAGS.bits());
+        let mut field_memberships = vec![];
+        push_u64(
+            &mut field_memberships,
+            pack(&[
+                (1, tb),
+                (0, tb),
+                (1, tb),
+                (0, 6),
+                (flags >> attribute_bits, 1),
+                (0, 57 - 3 * tb),
+            ]),
+        );
+
+        let mut types = vec![0; 48];
+        push_u32(&mut types, type_name);
+        push_u32(&mut types, namespace);
+        push_u32(&mut types, 0x10);
+        push_u32(&mut types, 0);
+        push_u64(&mut types, pack(&[(0, 17), (0, 16), (1, 24), (0, 7)]));
+        push_u16(&mut types, 1);
+        push_u16(&mut types, 0);
+        push_u32(&mut types, 0);
+        push_u64(&mut types, 0);
+        push_u64(&mut types, 0);
+
+        let mut methods = vec![];
+        push_u16(&mut methods, 0);
+        push_u16(&mut methods, u16::MAX);
+        push_u16(&mut methods, 0);
+        push_u16(&mut methods, 0);
+        push_u32(&mut methods, method_name);
+
+        let mut fields = vec![];
+        push_u32(
+            &mut fields,
+            pack(&[
+                (0, layout.field_attribute_list_bits),
+                (flags & ((1 << attribute_bits) - 1), attribute_bits),
+            ]),
+        );
+        push_u32(&mut fields, pack(&[(0x10, 26), (0, 6)]));
+        push_u32(&mut fields, pack(&[(field_name, 28), (0, 4)]));
+
+        let mut property_memberships = vec![];
+        push_u64(
+            &mut property_memberships,
+            pack(&[(0, 20), (1, 22), (0, 22)]),
+        );
+
+        let mut properties = vec![];
+        push_u16(&mut properties, 0);
+        push_u16(&mut properties, 0);
+        push_u32(&mut properties, property_name);
+
+        let mut params = vec![];
+        push_u16(&mut params, 0);
+        push_u16(&mut params, 0);
+        push_u32(&mut params, 0);
+        push_u32(&mut params, pack(&[(1, tb), (0, 32 - tb)]));
+
+        // A single empty attribute list
+        let attribute_lists = vec![0; 4];
+
+        // Tables are laid out in the order they are read, each aligned to 16 bytes
+        let header_len = if layout.data_attribute_lists {
+            240
+        } else {
+            232
+        };
+        let mut data = vec![0; header_len];
+        let mut place = |table: &[u8]| {
+            data.resize(data.len().next_multiple_of(16), 0);
+            let offset = u64::try_from(data.len()).unwrap();
+            data.extend(table);
+            offset
+        };
+        let type_instance_offset = place(&type_instances);
+        let method_membership_offset = place(&method_memberships);
+        let field_membership_offset = place(&field_memberships);
+        let type_offset = place(&types);
+        let method_offset = place(&methods);
+        let field_offset = place(&fields);
+        let property_membership_offset = place(&property_memberships);
+        let property_offset = place(&properties);
+        let event_offset = place(&[]);
+        let attribute_offset = place(&[]);
+        let param_offset = place(&params);
+        let constant_offset = place(&[]);
+        let attribute_list_offset = place(&attribute_lists);
+        let data_attribute_list_offset =
+            layout.data_attribute_lists.then(|| place(&attribute_lists));
+        let string_table_offset = place(&string_table);
+        let heap_offset = place(&heap);
+        let intern_string_offset = place(&[]);
+
+        let mut header = vec![];
+        header.extend(b"TDB\0");
+        push_u32(&mut header, version.into());
+        push_u32(&mut header, 0);
+        for count in [2, 2, 1, 2, 1, 1, 1, 1, 0, 1, 0, 0] {
+            push_u32(&mut header, count);
+        }
+        if layout.data_attribute_lists {
+            push_u32(&mut header, pack(&[(1, 16), (1, 16)]));
+        } else {
+            push_u32(&mut header, 1);
+        }
+        for count in [0, 0, 0, 0] {
+            push_u32(&mut header, count);
+        }
+        push_u32(&mut header, string_table.len().try_into().unwrap());
+        push_u32(&mut header, heap.len().try_into().unwrap());
+        push_u64(&mut header, header_len.try_into().unwrap());
+        for offset in [
+            type_instance_offset,
+            type_offset,
+            method_membership_offset,
+            method_offset,
+            field_membership_offset,
+            field_offset,
+            property_membership_offset,
+            property_offset,
+            event_offset,
+            param_offset,
+            attribute_offset,
+            constant_offset,
+            attribute_list_offset,
+        ] {
+            push_u64(&mut header, offset);
+        }
+        if let Some(offset) = data_attribute_list_offset {
+            push_u64(&mut header, offset);
+        }
+        for offset in [string_table_offset, heap_offset, intern_string_offset, 0] {
+            push_u64(&mut header, offset);
+        }
+        assert_eq!(header.len(), header_len);
+        data[..header_len].copy_from_slice(&header);
+        data
+    }
+
+    fn check_version(version: u32) {
+        let tdb = Tdb::new(Cursor::new(synthetic_tdb(version)), 0).unwrap();
+        assert_eq!(tdb.types.len(), 1);
+        let ty = &tdb.types[0];
+        assert_eq!(ty.full_name, "app.Test");
+        assert_eq!((ty.hash, ty.crc), (0x12345678, 0x9ABCDEF0));
+        assert_eq!((ty.element_type, ty.system_type), (0x1C, 0x2A));
+        assert_eq!((ty.len, ty.runtime_len), (0x10, 0x20));
+
+        assert_eq!(ty.fields.len(), 1);
+        assert_eq!(ty.fields[0].name, "_Value");
+        assert_eq!(ty.fields[0].ti, 0);
+        assert_eq!(ty.fields[0].flags, FIELD_FLAGS);
+        assert_eq!(ty.fields[0].position, 0x10);
+
+        assert_eq!(ty.methods.len(), 1);
+        assert_eq!(ty.methods[0].name, "get_Value");
+        assert_eq!(ty.methods[0].runtime_address, 0x100);
+        assert_eq!(ty.methods[0].ret.ti, 0);
+
+        assert_eq!(ty.properties.len(), 1);
+        assert_eq!(ty.properties[0].name, "Value");
+        assert_eq!(ty.properties[0].mi_get, Some(0));
+        assert_eq!(ty.properties[0].mi_set, None);
+    }
+
+    #[test]
+    fn parse_version_45() {
+        check_version(0x45);
+    }
+
+    #[test]
+    fn parse_version_46() {
+        check_version(0x46);
+    }
+
+    #[test]
+    fn parse_version_47() {
+        check_version(0x47);
+    }
+
+    #[test]
+    fn supported_versions_have_layouts() {
+        for &version in SUPPORTED_VERSIONS {
+            assert!(TdbLayout::new(version).is_some(), "0x{version:X}");
+        }
+        let error = Tdb::new(Cursor::new(b"TDB\0\x48\0\0\0\0\0\0\0".to_vec()), 0)
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("Unsupported TDB version 0x48"));
+    }
+}
@@ -1,7 +1,8 @@
+use crate::align::*;
 use crate::file_ext::*;
 use crate::rsz::Rsz;
 use anyhow::{bail, Context, Result};
-use std::io::{Read, Seek};
+use std::io::{Read, Seek, Write};
 
 #[derive(Debug)]
 pub struct UserChild {
@@ -86,4 +87,130 @@ impl User {
             rsz,
         })
     }
+
+    pub fn write<F: Write + Seek>(&self, mut file: F) -> Result<()> {
+        let resource_list_offset = 0x30;
+        let child_list_offset = align_up(
+            resource_list_offset + u64::try_from(self.resource_names.len())? * 8,
+            16,
+        );
+        let mut string_offset = child_list_offset + u64::try_from(self.children.len())? * 0x10;
+        let mut resource_name_offsets = vec![];
+        for name in &self.resource_names {
+            resource_name_offsets.push(string_offset);
+            string_offset += u64::try_from(name.encode_utf16().count() + 1)? * 2;
+        }
+        let mut child_name_offsets = vec![];
+        for child in &self.children {
+            child_name_offsets.push(string_offset);
+            string_offset += u64::try_from(child.name.encode_utf16().count() + 1)? * 2;
+        }
+        let rsz_offset = align_up(string_offset, 16);
+
+        file.write_magic(b"USR\0")?;
+        file.write_u32(u32::try_from(self.resource_names.len())?)?;
+        file.write_u32(u32::try_from(self.children.len())?)?;
+        file.write_u32(0)?;
+        file.write_u64(resource_list_offset)?;
+        file.write_u64(child_list_offset)?;
+        file.write_u64(rsz_offset)?;
+
+        file.write_align_up(16)?;
+        for offset in resource_name_offsets {
+            file.write_u64(offset)?;
+        }
+        file.write_align_up(16)?;
+        for (child, offset) in self.children.iter().zip(child_name_offsets) {
+            file.write_u32(child.hash)?;
+            file.write_u32(0)?;
+            file.write_u64(offset)?;
+        }
+        for name in &self.resource_names {
+            file.write_u16str(name)?;
+        }
+        for child in &self.children {
+            file.write_u16str(&child.name)?;
+        }
+        file.write_align_up(16)?;
+        self.rsz.write(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rsz::{RefTableData, RszSerializer};
+    use std::io::Cursor;
+
+    fn table(first: u16) -> RefTableData {
+        RefTableData {
+            vital_tbl_no: first,
+            attack_tbl_no: first + 1,
+            parts_tbl_no: first + 2,
+            core_tbl_no: first + 3,
+            other_tbl_no: first + 4,
+            multi_tbl_no: first + 5,
+        }
+    }
+
+    fn table_rsz(first: u16) -> Rsz {
+        let mut serializer = RszSerializer::new(None);
+        serializer.add_root(&table(first)).unwrap();
+        serializer.finish()
+    }
+
+    // One resource and one child, laid out by hand
+    fn sample() -> Vec<u8> {
+        let mut file = Cursor::new(vec![]);
+        file.write_magic(b"USR\0").unwrap();
+        file.write_u32(1).unwrap();
+        file.write_u32(1).unwrap();
+        file.write_u32(0).unwrap();
+        file.write_u64(0x30).unwrap();
+        file.write_u64(0x40).unwrap();
+        file.write_u64(0x70).unwrap();
+        file.write_u64(0).unwrap();
+        file.write_u64(0x50).unwrap();
+        file.write_u64(0).unwrap();
+        file.write_u32(0x12345678).unwrap();
+        file.write_u32(0).unwrap();
+        file.write_u64(0x5C).unwrap();
+        file.write_u16str("a.tex").unwrap();
+        file.write_u16str("b.user").unwrap();
+        file.write_all(&[0; 6]).unwrap();
+        assert_eq!(file.position(), 0x70);
+        table_rsz(0x10).write(&mut file).unwrap();
+        file.into_inner()
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = sample();
+        let user = User::new(Cursor::new(&bytes)).unwrap();
+        assert_eq!(user.resource_names, ["a.tex"]);
+        assert_eq!(user.children[0].name, "b.user");
+        let mut written = Cursor::new(vec![]);
+        user.write(&mut written).unwrap();
+        assert_eq!(written.into_inner(), bytes);
+    }
+
+    #[test]
+    fn write_modified() {
+        let mut user = User::new(Cursor::new(sample())).unwrap();
+        let mut value: RefTableData = user.rsz.deserialize_single(None).unwrap();
+        value.multi_tbl_no = 0x1234;
+        let mut serializer = RszSerializer::new(None);
+        serializer.add_hints(&user.rsz);
+        serializer.add_root(&value).unwrap();
+        user.rsz = serializer.finish();
+
+        let mut written = Cursor::new(vec![]);
+        user.write(&mut written).unwrap();
+        let user = User::new(Cursor::new(written.into_inner())).unwrap();
+        assert_eq!(user.resource_names, ["a.tex"]);
+        assert_eq!(user.children[0].hash, 0x12345678);
+        let value: RefTableData = user.rsz.deserialize_single(None).unwrap();
+        assert_eq!(value.vital_tbl_no, 0x10);
+        assert_eq!(value.multi_tbl_no, 0x1234);
+    }
 }